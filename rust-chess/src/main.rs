@@ -4,25 +4,69 @@
 pub mod shared;
 pub use shared::*;
 
-use {game::Game, helpers::ErrorResult, uci::Uci};
+use {
+    analysis::{analyze_game, AnalysisOptions},
+    bench::{run_bench, DEFAULT_BENCH_DEPTH},
+    engine_match::{load_openings, run_match, MatchEngine, MatchOptions, Sprt, TimeControl},
+    game::Game,
+    helpers::{err_result, ErrorResult},
+    perft_debug::perft_debug,
+    pgn::PgnReader,
+    puzzles::{extract_puzzles, PuzzleOptions},
+    self_play::{run_self_play, SelfPlayOptions},
+    tactical_suite::{run_tactical_suite_file, tactical_suite_summary, SuiteLimit},
+    std::{io::Write, time::Duration},
+    uci::Uci,
+};
 
-fn next_stdin() -> String {
+const USAGE: &str = "\
+usage: main                                     run as a UCI engine
+       main analyze <pgn> [movetime <ms>] [depth <n>] [out <file>]
+                                                annotate every game with evals, marks and accuracy
+       main bench [depth]                       search the bench positions, printing nodes and NPS
+       main perft-debug <engine> <depth> [fen]  compare perft counts with another UCI engine
+       main tactics <epd> [depth <n> | movetime <ms>]
+                                                score the engine on a bm/am test suite
+       main match <engine> <engine> [games <n>] [depth <n> | movetime <ms> | tc <base>+<inc>]
+                  [openings <file>] [sprt <elo0> <elo1>]
+                                                play a match, where an engine is a UCI binary or
+                                                self[:skip_razoring,contempt=0,skill=5,...]
+       main puzzles <pgn> <file> [depth <n>] [win <cp>] [gap <cp>] [plies <n>] [min-ply <n>]
+                                                write positions with one winning move as EPD
+       main selfplay <file> [games <n>] [nodes <n>] [random-plies <n>] [seed <n>]
+                                                write fen | score | result training records";
+
+// `None` once stdin is closed
+fn next_stdin() -> Option<String> {
     let mut input = String::new();
-    std::io::stdin().read_line(&mut input).unwrap();
-    input.trim().to_string()
+    match std::io::stdin().read_line(&mut input) {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(input.trim().to_string()),
+    }
 }
 
-fn run() -> ErrorResult<()> {
-    let mut uci = Uci::new();
+fn exit_with_usage() -> ! {
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
 
-    loop {
-        let input = next_stdin().to_string();
+fn logger(s: &str) {
+    println!("{}", s);
+}
+
+fn run_uci() -> ErrorResult<()> {
+    let mut uci = Uci::new(logger);
+
+    while let Some(input) = next_stdin() {
         let input = input.trim();
         if input.is_empty() {
             continue;
         }
 
         for line in input.split("\n") {
+            if line == "quit" {
+                return Ok(());
+            }
             let result = uci.handle_line(line);
             match result {
                 Ok(line) => println!("{}", line),
@@ -30,8 +74,270 @@ fn run() -> ErrorResult<()> {
             }
         }
     }
+    Ok(())
+}
+
+fn run_bench_command(args: &[String]) -> ErrorResult<()> {
+    let depth = match args {
+        [] => DEFAULT_BENCH_DEPTH,
+        [depth] => match depth.parse::<usize>() {
+            Ok(depth) => depth,
+            Err(_) => return err_result(&format!("invalid depth '{}'", depth)),
+        },
+        _ => exit_with_usage(),
+    };
+    let result = run_bench(depth, &mut |line| eprintln!("{}", line))?;
+    println!("{}", result);
+    Ok(())
+}
+
+fn run_perft_debug(args: &[String]) -> ErrorResult<()> {
+    let (engine, depth, fen) = match args {
+        [engine, depth] => (engine, depth, None),
+        [engine, depth, fen] => (engine, depth, Some(fen)),
+        _ => exit_with_usage(),
+    };
+    let depth = match depth.parse::<usize>() {
+        Ok(depth) => depth,
+        Err(_) => return err_result(&format!("invalid depth '{}'", depth)),
+    };
+    let game = match fen {
+        Some(fen) => Game::from_fen(fen)?,
+        None => Game::from_position_uci("position startpos")?,
+    };
+
+    match perft_debug(engine, &game, depth, &mut |line| println!("{}", line))? {
+        Some(divergence) => {
+            println!(
+                "moves differ after [{}]: {}",
+                divergence.moves.join(" "),
+                divergence.fen
+            );
+            println!("missing: [{}]", divergence.missing.join(" "));
+            println!("extra:   [{}]", divergence.extra.join(" "));
+        }
+        None => println!("perft {} matches", depth),
+    }
+    Ok(())
+}
+
+// One second per position unless told otherwise
+const DEFAULT_TACTICS_LIMIT: SuiteLimit = SuiteLimit::Time(Duration::from_millis(1000));
+
+fn run_tactics(args: &[String]) -> ErrorResult<()> {
+    let (path, limit) = match args {
+        [path] => (path, DEFAULT_TACTICS_LIMIT),
+        [path, kind, value] => {
+            let Ok(value) = value.parse::<u64>() else {
+                return err_result(&format!("invalid {} '{}'", kind, value));
+            };
+            match kind.as_str() {
+                "depth" => (path, SuiteLimit::Depth(value as usize)),
+                "movetime" => (path, SuiteLimit::Time(Duration::from_millis(value))),
+                _ => exit_with_usage(),
+            }
+        }
+        _ => exit_with_usage(),
+    };
+
+    let results = run_tactical_suite_file(path, limit, &mut |line| println!("{}", line))?;
+    println!("{}", tactical_suite_summary(&results));
+    Ok(())
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: &str) -> ErrorResult<T> {
+    match value.parse::<T>() {
+        Ok(value) => Ok(value),
+        Err(_) => err_result(&format!("invalid {} '{}'", name, value)),
+    }
+}
+
+// `<base>+<increment>` in seconds, e.g. 10+0.1
+fn parse_clock(value: &str) -> ErrorResult<TimeControl> {
+    let (base, increment) = value.split_once('+').unwrap_or((value, "0"));
+    Ok(TimeControl::Clock {
+        base: Duration::from_secs_f64(parse_number("tc", base)?),
+        increment: Duration::from_secs_f64(parse_number("tc", increment)?),
+    })
+}
+
+fn run_match_command(args: &[String]) -> ErrorResult<()> {
+    let [first, second, rest @ ..] = args else {
+        exit_with_usage();
+    };
+
+    let mut options = MatchOptions::default();
+    let mut rest = rest.iter().map(|arg| arg.as_str());
+    while let Some(name) = rest.next() {
+        let Some(value) = rest.next() else {
+            exit_with_usage();
+        };
+        match name {
+            "games" => options.max_games = parse_number(name, value)?,
+            "depth" => options.time_control = TimeControl::Depth(parse_number(name, value)?),
+            "movetime" => {
+                let time = Duration::from_millis(parse_number(name, value)?);
+                options.time_control = TimeControl::MoveTime(time);
+            }
+            "tc" => options.time_control = parse_clock(value)?,
+            "openings" => options.openings = load_openings(value)?,
+            "sprt" => {
+                let Some(elo1) = rest.next() else {
+                    exit_with_usage();
+                };
+                let (elo0, elo1) = (parse_number(name, value)?, parse_number(name, elo1)?);
+                options.sprt = Some(Sprt::new(elo0, elo1));
+            }
+            _ => exit_with_usage(),
+        }
+    }
+
+    let mut first = MatchEngine::from_spec(first)?;
+    let mut second = MatchEngine::from_spec(second)?;
+    let report = run_match(&mut first, &mut second, &options, &mut |line| println!("{}", line))?;
+    println!("{}", report);
+    Ok(())
+}
+
+fn run_self_play_command(args: &[String]) -> ErrorResult<()> {
+    let [path, rest @ ..] = args else {
+        exit_with_usage();
+    };
+
+    let mut options = SelfPlayOptions::default();
+    let mut rest = rest.iter().map(|arg| arg.as_str());
+    while let Some(name) = rest.next() {
+        let Some(value) = rest.next() else {
+            exit_with_usage();
+        };
+        match name {
+            "games" => options.games = parse_number(name, value)?,
+            "nodes" => options.nodes_per_move = parse_number(name, value)?,
+            "random-plies" => options.random_plies = parse_number(name, value)?,
+            "seed" => options.seed = parse_number(name, value)?,
+            _ => exit_with_usage(),
+        }
+    }
+
+    let file = match std::fs::File::create(path) {
+        Ok(file) => file,
+        Err(e) => return err_result(&format!("couldn't create {}: {}", path, e)),
+    };
+    let mut out = std::io::BufWriter::new(file);
+    let stats = run_self_play(&options, &mut out, &mut |line| println!("{}", line))?;
+    if let Err(e) = out.flush() {
+        return err_result(&format!("couldn't write {}: {}", path, e));
+    }
+    println!("{}", stats);
+    Ok(())
+}
+
+// Half a second per position unless told otherwise
+const DEFAULT_ANALYSIS_MOVETIME: Duration = Duration::from_millis(500);
+
+fn run_analyze(args: &[String]) -> ErrorResult<()> {
+    let [path, rest @ ..] = args else {
+        exit_with_usage();
+    };
+
+    let mut movetime = DEFAULT_ANALYSIS_MOVETIME;
+    let mut options = AnalysisOptions::default();
+    let mut out_path = None;
+    let mut rest = rest.iter().map(|arg| arg.as_str());
+    while let Some(name) = rest.next() {
+        let Some(value) = rest.next() else {
+            exit_with_usage();
+        };
+        match name {
+            "movetime" => movetime = Duration::from_millis(parse_number(name, value)?),
+            "depth" => options.max_depth = Some(parse_number(name, value)?),
+            "out" => out_path = Some(value),
+            _ => exit_with_usage(),
+        }
+    }
+
+    // Annotated games go to the file if there is one, otherwise to stdout with everything else on
+    // stderr so that the output is still a valid PGN
+    let to_stdout = out_path.is_none();
+    let mut out: Box<dyn Write> = match out_path {
+        Some(out_path) => match std::fs::File::create(out_path) {
+            Ok(file) => Box::new(std::io::BufWriter::new(file)),
+            Err(e) => return err_result(&format!("couldn't create {}: {}", out_path, e)),
+        },
+        None => Box::new(std::io::stdout()),
+    };
+
+    for game in PgnReader::open(path)? {
+        let analysis = analyze_game(game?, movetime, options.clone(), &mut |line| {
+            eprintln!("{}", line)
+        })?;
+        if to_stdout {
+            eprintln!("{}", analysis.summary());
+        } else {
+            println!("{}", analysis.summary());
+        }
+        if let Err(e) = writeln!(out, "{}", analysis.annotated_game()?.to_pgn()) {
+            return err_result(&format!("couldn't write annotated game: {}", e));
+        }
+    }
+    if let Err(e) = out.flush() {
+        return err_result(&format!("couldn't write annotated games: {}", e));
+    }
+    Ok(())
+}
+
+fn run_puzzles(args: &[String]) -> ErrorResult<()> {
+    let [pgn_path, path, rest @ ..] = args else {
+        exit_with_usage();
+    };
+
+    let mut options = PuzzleOptions::default();
+    let mut rest = rest.iter().map(|arg| arg.as_str());
+    while let Some(name) = rest.next() {
+        let Some(value) = rest.next() else {
+            exit_with_usage();
+        };
+        match name {
+            "depth" => options.depth = parse_number(name, value)?,
+            "win" => options.win_score = parse_number(name, value)?,
+            "gap" => options.min_gap = parse_number(name, value)?,
+            "plies" => options.max_solution_plies = parse_number(name, value)?,
+            "min-ply" => options.min_ply = parse_number(name, value)?,
+            _ => exit_with_usage(),
+        }
+    }
+
+    let file = match std::fs::File::create(path) {
+        Ok(file) => file,
+        Err(e) => return err_result(&format!("couldn't create {}: {}", path, e)),
+    };
+    let mut out = std::io::BufWriter::new(file);
+    let stats = extract_puzzles(pgn_path, &options, &mut out, &mut |line| println!("{}", line))?;
+    if let Err(e) = out.flush() {
+        return err_result(&format!("couldn't write {}: {}", path, e));
+    }
+    println!("{}", stats);
+    Ok(())
+}
+
+fn run() -> ErrorResult<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(|arg| arg.as_str()) {
+        None => run_uci(),
+        Some("analyze") => run_analyze(&args[1..]),
+        Some("bench") => run_bench_command(&args[1..]),
+        Some("perft-debug") => run_perft_debug(&args[1..]),
+        Some("tactics") => run_tactics(&args[1..]),
+        Some("match") => run_match_command(&args[1..]),
+        Some("puzzles") => run_puzzles(&args[1..]),
+        Some("selfplay") => run_self_play_command(&args[1..]),
+        Some(_) => exit_with_usage(),
+    }
 }
 
 fn main() {
-    run().unwrap();
+    if let Err(e) = run() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
 }
@@ -1,21 +1,40 @@
 pub use super::*;
 
 pub mod alphabeta;
+pub mod analysis;
+pub mod bench;
 pub mod bitboard;
 pub mod board;
+pub mod chess960;
 pub mod danger;
+pub mod engine_match;
+pub mod epd;
 pub mod evaluation;
 pub mod fen;
 pub mod game;
 pub mod helpers;
 pub mod iterative_deepening;
+pub mod legal_moves;
+pub mod mate_search;
 pub mod move_ordering;
 pub mod moves;
 pub mod perft;
+pub mod perft_debug;
+pub mod perft_suite;
+pub mod perft_table;
+pub mod pgn;
+pub mod puzzles;
+pub mod proof_number;
+pub mod san;
 pub mod transposition_table;
 pub mod traversal;
 pub mod types;
 pub mod score;
+pub mod self_play;
+pub mod strength;
+pub mod tactical_suite;
 pub mod uci;
+pub mod uci_engine;
 pub mod zobrist;
 pub mod simple_move;
+pub mod staged_moves;
@@ -5,10 +5,11 @@ use itertools::Itertools;
 use crate::{
     defer,
     helpers::{err_result, pad_left, Joinable, OptionResult},
-    score::Score,
+    score::{Contempt, Score},
     simple_move::SimpleMove,
+    staged_moves::StagedMoves,
     transposition_table::{CacheEntry, CacheValue, TranspositionTable},
-    traversal::{null_move_sort, TraversalData, TraversalStack},
+    traversal::{null_move_sort, TraversalData, TraversalStack, TraversalStackFrame},
     zobrist::ZobristHistory,
 };
 
@@ -25,6 +26,23 @@ use super::{
 
 type BestMoveReturn = (Vec<SimpleMove>, Score);
 
+// Forward pruning is only applied this close to the leaves. Margins are in centipawns per
+// remaining ply.
+const REVERSE_FUTILITY_MAX_DEPTH: usize = 3;
+const REVERSE_FUTILITY_MARGIN: isize = 120;
+
+const FUTILITY_MAX_DEPTH: usize = 2;
+const FUTILITY_MARGIN: isize = 200;
+
+const RAZORING_MAX_DEPTH: usize = 1;
+const RAZORING_MARGIN: isize = 500;
+
+const LATE_MOVE_PRUNING_MAX_DEPTH: usize = 3;
+
+fn late_move_count(depth_remaining: usize) -> usize {
+    6 + 2 * depth_remaining * depth_remaining
+}
+
 #[derive(Debug, Eq, PartialEq, Clone)]
 enum SearchResult {
     // Returned if we pass both beta/alpha cut-offs
@@ -180,40 +198,6 @@ impl CachedBetaCutoffs {
     }
 }
 
-#[derive(Default, Debug, Eq, PartialEq)]
-struct HighPriorityMoves {
-    moves: Vec<SimpleMove>,
-    applied: HashSet<SimpleMove>,
-}
-
-impl HighPriorityMoves {
-    pub fn clear(&mut self) {
-        self.moves.clear();
-        self.applied.clear();
-    }
-    pub fn add(&mut self, m: &SimpleMove) {
-        self.moves.push(*m);
-    }
-    pub fn done(&self) -> bool {
-        self.moves.is_empty()
-    }
-    pub fn next(&mut self) -> Option<SimpleMove> {
-        let m = self.moves.pop();
-        if let Some(m) = &m {
-            if self.applied.contains(m) {
-                return self.next();
-            }
-            self.applied.insert(*m);
-            Some(*m)
-        } else {
-            None
-        }
-    }
-    pub fn already_applied(&self, m: &SimpleMove) -> bool {
-        self.applied.contains(m)
-    }
-}
-
 #[derive(Default, Debug, Eq, PartialEq)]
 struct AlphaBetaFrame {
     alpha: Score,
@@ -222,8 +206,12 @@ struct AlphaBetaFrame {
 
     alpha_move: Option<BestMoveReturn>,
     found_legal_moves: bool,
+    legal_moves_seen: usize,
+
+    static_evaluation: Option<isize>,
+    checked_node_pruning: bool,
 
-    high_priority_moves: HighPriorityMoves,
+    moves: StagedMoves,
 
     last_applied_move: Option<SimpleMove>,
 
@@ -238,15 +226,22 @@ impl TraversalData for AlphaBetaFrame {
         self.in_quiescence = previous.in_quiescence;
         self.alpha_move = None;
         self.found_legal_moves = false;
+        self.legal_moves_seen = 0;
+        self.static_evaluation = None;
+        self.checked_node_pruning = false;
         self.last_applied_move = None;
 
-        self.high_priority_moves.clear();
-        for cutoff_move in self.cached_beta_cutoffs.moves.iter() {
-            if let Some(cutoff_move) = cutoff_move {
-                self.high_priority_moves.add(cutoff_move)
-            }
-        }
+        self.moves.reset(self.cached_beta_cutoffs.moves);
+    }
+}
+
+fn static_evaluation(frame: &mut TraversalStackFrame<AlphaBetaFrame>) -> isize {
+    if let Some(score) = frame.data.static_evaluation {
+        return score;
     }
+    let score = evaluate(&frame.game);
+    frame.data.static_evaluation = Some(score);
+    score
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -255,11 +250,45 @@ pub enum LoopResult {
     Done,
 }
 
+#[derive(Debug, Default, Clone)]
+pub struct RootMoves {
+    // When set, only these moves are searched from the root position
+    pub include: Option<Vec<SimpleMove>>,
+    pub exclude: Vec<SimpleMove>,
+}
+
+impl RootMoves {
+    pub fn is_restricted(&self) -> bool {
+        self.include.is_some() || !self.exclude.is_empty()
+    }
+
+    pub fn allows(&self, m: &SimpleMove) -> bool {
+        if self.exclude.contains(m) {
+            return false;
+        }
+        match &self.include {
+            Some(include) => include.contains(m),
+            None => true,
+        }
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct AlphaBetaOptions {
     pub skip_quiescence: bool,
     pub skip_sibling_beta_cutoff_sort: bool,
     pub skip_null_move_pruning: bool,
+    pub skip_reverse_futility_pruning: bool,
+    pub skip_futility_pruning: bool,
+    pub skip_razoring: bool,
+    pub skip_late_move_pruning: bool,
+    // Leaves are neutral and every promotion is searched, so only forced mates can raise alpha
+    pub only_mates: bool,
+    pub root_moves: RootMoves,
+    // Search every root move with the full window so each one gets an exact score
+    pub score_root_moves: bool,
+    // How much the root player would give up to avoid a draw, see `Contempt`
+    pub contempt: isize,
     pub aspiration_window: Option<(Score, Score)>,
     pub starting_history: ZobristHistory,
     pub transposition_table: Option<Rc<RefCell<TranspositionTable>>>,
@@ -270,6 +299,7 @@ pub struct AlphaBetaOptions {
 pub struct AlphaBetaStack {
     traversal: TraversalStack<AlphaBetaFrame>,
     best_move: Option<BestMoveReturn>,
+    contempt: Contempt,
 
     pub done: bool,
     pub evaluate_at_depth: usize,
@@ -278,7 +308,11 @@ pub struct AlphaBetaStack {
 
     pub num_beta_cutoffs: usize,
     pub num_evaluations: usize,
+    pub num_nodes: usize,
     pub num_starting_moves_searched: usize,
+    pub num_pruned: usize,
+
+    pub root_move_scores: Vec<(SimpleMove, Score)>,
 }
 
 impl AlphaBetaStack {
@@ -308,19 +342,26 @@ impl AlphaBetaStack {
                     in_quiescence: InQuiescence::No,
                     alpha_move: None,
                     found_legal_moves: false,
-                    high_priority_moves: HighPriorityMoves::default(),
+                    legal_moves_seen: 0,
+                    static_evaluation: None,
+                    checked_node_pruning: false,
+                    moves: StagedMoves::default(),
                     last_applied_move: None,
                     cached_beta_cutoffs: CachedBetaCutoffs::default(),
                 },
                 options.starting_history.clone(),
             )?,
             best_move: None,
+            contempt: Contempt::new(game.player(), options.contempt),
             evaluate_at_depth,
             done: false,
             options,
             num_beta_cutoffs: 0,
             num_evaluations: 0,
+            num_nodes: 0,
             num_starting_moves_searched: 0,
+            num_pruned: 0,
+            root_move_scores: vec![],
         })
     }
 
@@ -342,13 +383,22 @@ impl AlphaBetaStack {
     }
 
     fn statically_evaluate_leaf(&mut self) -> ErrorResult<Option<LoopResult>> {
-        let (current, current_depth) = self.traversal.current()?;
+        let (_, current_depth) = self.traversal.current()?;
 
         if current_depth < self.evaluate_at_depth {
             return Ok(None);
         }
 
-        let score = Score::Centipawns(current.game.player(), evaluate(&current.game));
+        self.statically_evaluate()
+    }
+
+    fn statically_evaluate(&mut self) -> ErrorResult<Option<LoopResult>> {
+        let (current, _) = self.traversal.current_mut()?;
+        let score = if self.options.only_mates {
+            Score::Centipawns(current.game.player(), 0)
+        } else {
+            Score::Centipawns(current.game.player(), static_evaluation(current))
+        };
 
         self.num_evaluations += 1;
         return self.return_early(SearchResult::StaticEvaluation(score));
@@ -367,7 +417,10 @@ impl AlphaBetaStack {
             )
         };
 
-        if !in_quiescence {
+        // A restricted root's best move isn't the position's best move
+        let restricted_root = self.traversal.depth() == 0 && self.options.root_moves.is_restricted();
+
+        if !in_quiescence && !restricted_root {
             if self.options.transposition_table.is_some() {
                 if let Some(cache_value) = child_result.to_cache_value(depth_remaining) {
                     let (current, _) = self.traversal.current()?;
@@ -395,6 +448,9 @@ impl AlphaBetaStack {
 
         self.traversal.decrement_depth()?;
 
+        // When scoring root moves, alpha stays put so every move is searched with the full window
+        let score_root_move = self.options.score_root_moves && self.traversal.depth() == 0;
+
         let child_score = child_result.score().increment_turns();
         let (parent, _) = self.traversal.current_mut()?;
         let parent_to_child_move = parent.data.last_applied_move.expect_ok(|| {
@@ -420,29 +476,54 @@ impl AlphaBetaStack {
             ));
         }
 
-        if Score::compare(parent.game.player(), child_score, parent.data.alpha).is_better() {
+        let score_to_beat = match &parent.data.alpha_move {
+            Some((_, alpha_move_score)) if score_root_move => *alpha_move_score,
+            _ => parent.data.alpha,
+        };
+
+        if score_root_move {
+            self.root_move_scores.push((parent_to_child_move, child_score));
+        }
+
+        if Score::compare(parent.game.player(), child_score, score_to_beat).is_better() {
             let mut variation = vec![parent_to_child_move];
             if let Some(child_variation) = child_result.variation() {
                 variation.extend(child_variation);
             }
             parent.data.alpha_move = Some((variation, child_score));
-            parent.data.alpha = child_score;
+            if !score_root_move {
+                parent.data.alpha = child_score;
+            }
         }
 
         Ok(Some(LoopResult::Continue))
     }
 
-    fn traverse_move(&mut self, m: &Move) -> ErrorResult<Option<LoopResult>> {
+    fn traverse_move(&mut self, m: &Move, known_legal: bool) -> ErrorResult<Option<LoopResult>> {
+        if self.traversal.depth() == 0 && !self.options.root_moves.allows(&SimpleMove::from(m)) {
+            return Ok(Some(LoopResult::Continue));
+        }
+
         {
             let (current, next) = self.traversal.current_and_next_mut()?;
-            let result = next.setup(current, &m).unwrap();
+            let result = next.setup(current, m, known_legal).unwrap();
 
             if result == Legal::No {
                 return Ok(Some(LoopResult::Continue));
             }
 
-            current.data.last_applied_move = Some(SimpleMove::from(m));
             current.data.found_legal_moves = true;
+            current.data.legal_moves_seen += 1;
+        }
+
+        if self.should_prune_move(m)? {
+            self.num_pruned += 1;
+            return Ok(Some(LoopResult::Continue));
+        }
+
+        {
+            let (current, _) = self.traversal.current_mut()?;
+            current.data.last_applied_move = Some(SimpleMove::from(m));
 
             if self.traversal.depth() == 0 {
                 self.num_starting_moves_searched += 1;
@@ -450,6 +531,7 @@ impl AlphaBetaStack {
         }
 
         // Recurse into our newly applied move
+        self.num_nodes += 1;
         self.traversal.increment_depth()?;
 
         {
@@ -470,7 +552,7 @@ impl AlphaBetaStack {
                 // Otherwise, prioritize the previous best-move
                 if let Some(best_move) = entry.value.best_move() {
                     let (current, _) = self.traversal.current_mut()?;
-                    current.data.high_priority_moves.add(&best_move);
+                    current.data.moves.set_hash_move(best_move);
                 }
             }
         }
@@ -478,30 +560,126 @@ impl AlphaBetaStack {
         Ok(Some(LoopResult::Continue))
     }
 
-    fn traverse_next<S>(&mut self, sorter: S) -> ErrorResult<Option<LoopResult>>
-    where
-        S: Fn(&Game, &mut [Move]) -> ErrorResult<()>,
-    {
+    fn should_prune_move(&mut self, m: &Move) -> ErrorResult<bool> {
+        if self.options.skip_futility_pruning && self.options.skip_late_move_pruning {
+            return Ok(false);
+        }
+        if !m.is_quiet() || m.promotion.is_some() {
+            return Ok(false);
+        }
+
+        let current_depth = self.traversal.depth();
+        if current_depth == 0 {
+            return Ok(false);
+        }
+        let depth_remaining = self.depth_remaining(current_depth);
+
+        let skip_futility_pruning = self.options.skip_futility_pruning;
+        let skip_late_move_pruning = self.options.skip_late_move_pruning;
+
+        let (current, next) = self.traversal.current_and_next_mut()?;
+        if current.data.in_quiescence == InQuiescence::Yes {
+            return Ok(false);
+        }
+
+        // Never prune our way out of check, or past a move that gives check
+        if current.danger()?.check || next.danger()?.check {
+            return Ok(false);
+        }
+
+        // Once we've looked at the first few moves, later quiet moves are unlikely to matter
+        if !skip_late_move_pruning
+            && depth_remaining <= LATE_MOVE_PRUNING_MAX_DEPTH
+            && current.data.legal_moves_seen > late_move_count(depth_remaining)
+        {
+            return Ok(true);
+        }
+
+        // A quiet move can't raise alpha if even an optimistic evaluation stays below it
+        if !skip_futility_pruning && depth_remaining <= FUTILITY_MAX_DEPTH {
+            let player = current.game.player();
+            let alpha = current.data.alpha;
+            let optimistic_score = Score::Centipawns(
+                player,
+                static_evaluation(current) + FUTILITY_MARGIN * depth_remaining as isize,
+            );
+            if !Score::compare(player, optimistic_score, alpha).is_better() {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn prune_node(&mut self) -> ErrorResult<Option<LoopResult>> {
+        let current_depth = self.traversal.depth();
+        let depth_remaining = self.depth_remaining(current_depth);
+
+        let skip_reverse_futility_pruning = self.options.skip_reverse_futility_pruning;
+        let skip_razoring = self.options.skip_razoring;
+
         let (current, _) = self.traversal.current_mut()?;
-        let current_options = current.data.in_quiescence.move_options();
 
-        while !current.data.high_priority_moves.done() {
-            if let Some(next_move) = current.data.high_priority_moves.next() {
-                let next_move = next_move.to_move(&current.game)?;
+        // Only consider pruning when we first enter the node, not every time a child returns
+        if current.data.checked_node_pruning {
+            return Ok(None);
+        }
+        current.data.checked_node_pruning = true;
 
-                if let Some(next_move) = next_move {
-                    return self.traverse_move(&next_move);
-                }
+        if current_depth == 0 || current.danger()?.check {
+            return Ok(None);
+        }
+
+        let player = current.game.player();
+        let alpha = current.data.alpha;
+        let beta = current.data.beta;
+
+        if !skip_reverse_futility_pruning
+            && depth_remaining <= REVERSE_FUTILITY_MAX_DEPTH
+            && !matches!(beta, Score::WinInN(..))
+        {
+            // Even a pessimistic evaluation beats beta: the enemy will avoid this line
+            let pessimistic_score = Score::Centipawns(
+                player,
+                static_evaluation(current) - REVERSE_FUTILITY_MARGIN * depth_remaining as isize,
+            );
+            if Score::compare(player, pessimistic_score, beta).is_better_or_equal() {
+                self.num_pruned += 1;
+                return self.return_early(SearchResult::BetaCutoff(beta, None));
+            }
+        }
+
+        if !skip_razoring && depth_remaining <= RAZORING_MAX_DEPTH {
+            // We're hopelessly behind. Only look for captures that might save us.
+            let optimistic_score = Score::Centipawns(
+                player,
+                static_evaluation(current) + RAZORING_MARGIN * depth_remaining as isize,
+            );
+            if Score::compare(player, optimistic_score, alpha).is_worse() {
+                self.num_pruned += 1;
+                current.data.in_quiescence = InQuiescence::Yes;
+                return Ok(Some(LoopResult::Continue));
             }
         }
 
+        Ok(None)
+    }
+
+    fn traverse_next<S>(&mut self, sorter: S) -> ErrorResult<Option<LoopResult>>
+    where
+        S: Fn(&Game, &mut [Move]) -> ErrorResult<()>,
+    {
+        let restricted_root = self.traversal.depth() == 0 && self.options.root_moves.is_restricted();
+
         let (current, _) = self.traversal.current_mut()?;
-        let current_game = &current.game;
-        let current_moves = &mut current.moves;
+        let mut current_options = current.data.in_quiescence.move_options();
+        if self.options.only_mates || restricted_root {
+            current_options.only_queen_promotion = OnlyQueenPromotion::No;
+        }
 
-        let next_move = current_moves.next(current_game, current_options, sorter)?;
-        if let Some(next_move) = next_move {
-            return self.traverse_move(&next_move);
+        let next_move = current.data.moves.next(&current.game, current_options, sorter)?;
+        if let Some((next_move, known_legal)) = next_move {
+            self.traverse_move(&next_move, known_legal)
         } else {
             Ok(None)
         }
@@ -558,9 +736,9 @@ impl AlphaBetaStack {
             self.log_if_history_matches(|| "".to_string())?;
         }
 
-        if self.traversal.is_draw_by_repetition() {
+        if let Some(draw) = self.traversal.draw_by_repetition(self.contempt) {
             return self
-                .return_early(SearchResult::StaticEvaluation(Score::DrawInN(0)))?
+                .return_early(SearchResult::StaticEvaluation(draw))?
                 .as_result();
         }
 
@@ -584,6 +762,10 @@ impl AlphaBetaStack {
                 }
             }
 
+            if let Some(result) = self.prune_node()? {
+                return Ok(result);
+            }
+
             if !self.options.skip_null_move_pruning {
                 let (current, _) = self.traversal.current_mut()?;
                 let current_danger = current.danger()?;
@@ -653,10 +835,14 @@ impl AlphaBetaStack {
                         0,
                     )))
                 } else {
-                    self.return_early(SearchResult::StaticEvaluation(Score::DrawInN(0)))
+                    self.return_early(SearchResult::StaticEvaluation(Score::DrawInN(
+                        self.contempt,
+                        0,
+                    )))
                 }
             } else {
-                self.statically_evaluate_leaf()
+                // Razored nodes can run out of captures above the leaf depth
+                self.statically_evaluate()
             }
         }?;
 
@@ -702,10 +888,12 @@ impl InQuiescence {
             InQuiescence::No => MoveOptions {
                 only_captures: OnlyCaptures::No,
                 only_queen_promotion: OnlyQueenPromotion::Yes,
+                only_quiets: OnlyQuiets::No,
             },
             InQuiescence::Yes => MoveOptions {
                 only_captures: OnlyCaptures::Yes,
                 only_queen_promotion: OnlyQueenPromotion::Yes,
+                only_quiets: OnlyQuiets::No,
             },
         }
     }
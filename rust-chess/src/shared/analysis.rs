@@ -0,0 +1,494 @@
+/*
+Game analysis and PGN annotation.
+
+Every position on the main line is searched in turn, from the start position to the end. The
+position before and after each move then give the move's cost to the player who made it, in
+centipawns from their point of view. Costs over the thresholds in `AnalysisOptions` mark the move
+as an inaccuracy (?!), mistake (?) or blunder (??). Each marked move gets the line the engine
+preferred as a variation, so the annotation shows what should have been played instead.
+
+`GameAnalysis` only searches when told to, a few iterations at a time, so the caller decides how
+long each position gets. This keeps timing out of the library, which matters in the browser where
+`std::time::Instant` isn't available. `analyze_game` is the native driver with a fixed time per
+position.
+
+Accuracy follows the usual approach of converting centipawns to a winning chance and scoring each
+move by how much winning chance it gave away: 100% for none, falling off exponentially.
+*/
+
+use std::{
+    cell::RefCell,
+    fmt::Display,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    game::{Game, GameStatus},
+    helpers::{err_result, ErrorResult, OptionResult},
+    iterative_deepening::{IterativeSearch, IterativeSearchOptions},
+    pgn::PgnGame,
+    score::Score,
+    simple_move::SimpleMove,
+    transposition_table::TranspositionTable,
+    types::Player,
+    zobrist::ZobristHistory,
+};
+
+// Standard NAGs for the move marks
+pub const NAG_MISTAKE: u8 = 2;
+pub const NAG_BLUNDER: u8 = 4;
+pub const NAG_INACCURACY: u8 = 6;
+
+// Evaluations past this are all clearly winning, and don't make a move worse by going further
+const MAX_LOSS_CENTIPAWNS: isize = 1_000;
+
+#[derive(Debug, Clone)]
+pub struct AnalysisOptions {
+    // Stop searching a position at this depth, even with time left
+    pub max_depth: Option<usize>,
+    // Moves of the engine's line to add as a variation
+    pub variation_plies: usize,
+    // Centipawns lost for each mark
+    pub inaccuracy: isize,
+    pub mistake: isize,
+    pub blunder: isize,
+}
+
+impl Default for AnalysisOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: None,
+            variation_plies: 4,
+            inaccuracy: 50,
+            mistake: 100,
+            blunder: 300,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PositionEvaluation {
+    pub score: Score,
+    // The engine's line, empty once the game is over
+    pub variation: Vec<SimpleMove>,
+}
+
+fn centipawns_for(player: Player, score: Score) -> isize {
+    score.centipawns_for(player).clamp(-MAX_LOSS_CENTIPAWNS, MAX_LOSS_CENTIPAWNS)
+}
+
+// The PGN `[%eval]` value: pawns from white's point of view, or `#n` / `#-n` for mates
+fn eval_comment(score: Score) -> String {
+    match score {
+        Score::WinInN(Player::White, plies) => format!("[%eval #{}]", plies.div_ceil(2)),
+        Score::WinInN(Player::Black, plies) => format!("[%eval #-{}]", plies.div_ceil(2)),
+        _ => format!("[%eval {:.2}]", score.centipawns_for(Player::White) as f64 / 100.0),
+    }
+}
+
+// Chance of winning from 0 to 100 for a centipawn evaluation
+fn winning_chance(centipawns: isize) -> f64 {
+    let centipawns = centipawns.clamp(-MAX_LOSS_CENTIPAWNS, MAX_LOSS_CENTIPAWNS) as f64;
+    50.0 + 50.0 * (2.0 / (1.0 + (-0.00368208 * centipawns).exp()) - 1.0)
+}
+
+fn move_accuracy(chance_before: f64, chance_after: f64) -> f64 {
+    let lost = (chance_before - chance_after).max(0.0);
+    (103.1668 * (-0.04354 * lost).exp() - 3.1669).clamp(0.0, 100.0)
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PlayerSummary {
+    pub moves: usize,
+    pub accuracy: f64,
+    pub average_centipawn_loss: f64,
+    pub inaccuracies: usize,
+    pub mistakes: usize,
+    pub blunders: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnalysisSummary {
+    pub white_name: String,
+    pub black_name: String,
+    pub white: PlayerSummary,
+    pub black: PlayerSummary,
+}
+
+impl Display for AnalysisSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let players = [
+            ("White", &self.white_name, &self.white),
+            ("Black", &self.black_name, &self.black),
+        ];
+        let lines: Vec<String> = players
+            .iter()
+            .map(|(colour, name, summary)| {
+                format!(
+                    "{} ({}): accuracy {:.1}%, average centipawn loss {:.0}, \
+                     {} inaccuracies, {} mistakes, {} blunders",
+                    colour,
+                    name,
+                    summary.accuracy,
+                    summary.average_centipawn_loss,
+                    summary.inaccuracies,
+                    summary.mistakes,
+                    summary.blunders
+                )
+            })
+            .collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+// What a played move cost and how it's marked
+struct MoveReview {
+    player: Player,
+    loss: isize,
+    accuracy: f64,
+    nag: Option<u8>,
+    // The engine's line when it preferred a different move
+    alternative: Option<(Vec<SimpleMove>, Score)>,
+}
+
+pub struct GameAnalysis {
+    game: PgnGame,
+    options: AnalysisOptions,
+    // Node indices of the start position and every position on the main line
+    positions: Vec<usize>,
+    history: ZobristHistory,
+    tt: Rc<RefCell<TranspositionTable>>,
+    search: Option<IterativeSearch>,
+    evaluations: Vec<PositionEvaluation>,
+}
+
+impl GameAnalysis {
+    pub fn new(game: PgnGame, options: AnalysisOptions) -> Self {
+        let mut positions = vec![0];
+        positions.extend(game.mainline());
+        let mut history = ZobristHistory::new();
+        history.add(game.start().zobrist());
+
+        Self {
+            game,
+            options,
+            positions,
+            history,
+            tt: Rc::new(RefCell::new(TranspositionTable::small())),
+            search: None,
+            evaluations: vec![],
+        }
+    }
+
+    // Positions to analyse, one more than the number of moves
+    pub fn positions(&self) -> usize {
+        self.positions.len()
+    }
+
+    // Index of the position being analysed
+    pub fn current(&self) -> usize {
+        self.evaluations.len()
+    }
+
+    pub fn done(&self) -> bool {
+        self.current() == self.positions()
+    }
+
+    fn current_game(&self) -> ErrorResult<&Game> {
+        let index = self.positions.get(self.current()).as_result()?;
+        Ok(&self.game.nodes[*index].game)
+    }
+
+    fn current_status(&self) -> ErrorResult<GameStatus> {
+        self.current_game()?.status(&self.history)
+    }
+
+    // Searches the current position for up to `iterations` more steps
+    pub fn think(&mut self, iterations: usize) -> ErrorResult<()> {
+        if self.done() || self.current_status()?.is_over() {
+            return Ok(());
+        }
+
+        if self.search.is_none() {
+            self.search = Some(IterativeSearch::new(
+                *self.current_game()?,
+                IterativeSearchOptions {
+                    max_depth: self.options.max_depth,
+                    // Judge the moves, not the players' attitude to draws
+                    contempt: 0,
+                    starting_history: self.history.clone(),
+                    transposition_table: Some(self.tt.clone()),
+                    ..IterativeSearchOptions::default()
+                },
+            )?);
+        }
+
+        let search = self.search.as_mut().as_result()?;
+        for _ in 0..iterations {
+            if search.done() {
+                break;
+            }
+            search.iterate(&mut |_| {})?;
+        }
+        Ok(())
+    }
+
+    // True once the current position has an evaluation to record
+    pub fn has_result(&self) -> ErrorResult<bool> {
+        if self.done() || self.current_status()?.is_over() {
+            return Ok(true);
+        }
+        Ok(self.search.as_ref().is_some_and(|search| search.bestmove().is_some()))
+    }
+
+    // True once searching the current position further won't change anything
+    pub fn position_finished(&self) -> ErrorResult<bool> {
+        if self.done() || self.current_status()?.is_over() {
+            return Ok(true);
+        }
+        Ok(self.search.as_ref().is_some_and(|search| search.done()))
+    }
+
+    // Records the current position's evaluation and moves on to the next one
+    pub fn finish_position(&mut self) -> ErrorResult<()> {
+        if self.done() {
+            return err_result("the analysis is already done");
+        }
+
+        let evaluation = match self.current_status()? {
+            GameStatus::Ongoing => {
+                let search = self.search.take().as_result()?;
+                let (bestmove, responses) = match search.bestmove() {
+                    Some(bestmove) => bestmove,
+                    None => return err_result("the position hasn't been searched yet"),
+                };
+                let mut variation = vec![bestmove];
+                variation.extend(responses);
+                PositionEvaluation {
+                    score: search.best_score().as_result()?,
+                    variation,
+                }
+            }
+            GameStatus::Checkmate { winner } => PositionEvaluation {
+                score: Score::WinInN(winner, 0),
+                variation: vec![],
+            },
+            _ => PositionEvaluation {
+                score: Score::default(),
+                variation: vec![],
+            },
+        };
+        self.evaluations.push(evaluation);
+        self.search = None;
+
+        if let Some(&next) = self.positions.get(self.current()) {
+            self.history.add(self.game.nodes[next].game.zobrist());
+        }
+        Ok(())
+    }
+
+    pub fn evaluations(&self) -> &[PositionEvaluation] {
+        &self.evaluations
+    }
+
+    // Reviews of the moves whose positions before and after have both been evaluated
+    fn reviews(&self) -> Vec<MoveReview> {
+        let mut reviews = vec![];
+        for (i, pair) in self.evaluations.windows(2).enumerate() {
+            let (before, after) = (&pair[0], &pair[1]);
+            let node = &self.game.nodes[self.positions[i + 1]];
+            let player = self.game.nodes[self.positions[i]].game.player();
+
+            let loss = (centipawns_for(player, before.score) - centipawns_for(player, after.score))
+                .max(0);
+            let accuracy = move_accuracy(
+                winning_chance(centipawns_for(player, before.score)),
+                winning_chance(centipawns_for(player, after.score)),
+            );
+
+            let played = node.history_move.map(|m| SimpleMove::from(&m));
+            let best = before.variation.first().copied();
+            let alternative = match best {
+                Some(best) if Some(best) != played => {
+                    Some((before.variation.clone(), before.score))
+                }
+                _ => None,
+            };
+            let nag = if alternative.is_none() {
+                None
+            } else if loss >= self.options.blunder {
+                Some(NAG_BLUNDER)
+            } else if loss >= self.options.mistake {
+                Some(NAG_MISTAKE)
+            } else if loss >= self.options.inaccuracy {
+                Some(NAG_INACCURACY)
+            } else {
+                None
+            };
+
+            reviews.push(MoveReview {
+                player,
+                loss,
+                accuracy,
+                nag,
+                alternative,
+            });
+        }
+        reviews
+    }
+
+    // The game with an evaluation after every analysed move, marks on the bad ones, and the
+    // engine's line as a variation where it disagrees with a marked move
+    pub fn annotated_game(&self) -> ErrorResult<PgnGame> {
+        let mut game = self.game.clone();
+        game.set_tag("Annotator", "rust-chess");
+
+        for (i, review) in self.reviews().into_iter().enumerate() {
+            let index = self.positions[i + 1];
+            let after = &self.evaluations[i + 1];
+            game.nodes[index].comments.push(eval_comment(after.score));
+
+            let Some(nag) = review.nag else {
+                continue;
+            };
+            game.nodes[index].nags.push(nag);
+
+            let Some((variation, score)) = review.alternative else {
+                continue;
+            };
+            let mut parent = self.positions[i];
+            for (ply, &m) in variation.iter().take(self.options.variation_plies).enumerate() {
+                let m = game.nodes[parent].game.legal_move(m)?;
+                parent = game.add_move(parent, m)?;
+                if ply == 0 {
+                    game.nodes[parent].comments.push(eval_comment(score));
+                }
+            }
+        }
+        Ok(game)
+    }
+
+    pub fn summary(&self) -> AnalysisSummary {
+        let mut white = PlayerSummary::default();
+        let mut black = PlayerSummary::default();
+        let mut white_loss = 0;
+        let mut black_loss = 0;
+
+        for review in self.reviews() {
+            let (summary, loss) = match review.player {
+                Player::White => (&mut white, &mut white_loss),
+                Player::Black => (&mut black, &mut black_loss),
+            };
+            summary.moves += 1;
+            summary.accuracy += review.accuracy;
+            *loss += review.loss;
+            match review.nag {
+                Some(NAG_BLUNDER) => summary.blunders += 1,
+                Some(NAG_MISTAKE) => summary.mistakes += 1,
+                Some(NAG_INACCURACY) => summary.inaccuracies += 1,
+                _ => {}
+            }
+        }
+        for (summary, loss) in [(&mut white, white_loss), (&mut black, black_loss)] {
+            let moves = summary.moves.max(1) as f64;
+            summary.accuracy /= moves;
+            summary.average_centipawn_loss = loss as f64 / moves;
+        }
+
+        AnalysisSummary {
+            white_name: self.game.tag("White").unwrap_or("?").to_string(),
+            black_name: self.game.tag("Black").unwrap_or("?").to_string(),
+            white,
+            black,
+        }
+    }
+}
+
+// Iterations between checks of the clock
+const ITERATIONS_PER_THINK: usize = 1_000;
+
+// Searches each position for `movetime`, or until `options.max_depth` if that comes first
+pub fn analyze_game(
+    game: PgnGame,
+    movetime: Duration,
+    options: AnalysisOptions,
+    log: &mut dyn FnMut(&str),
+) -> ErrorResult<GameAnalysis> {
+    let mut analysis = GameAnalysis::new(game, options);
+    while !analysis.done() {
+        let start = Instant::now();
+        while !analysis.position_finished()?
+            && (start.elapsed() < movetime || !analysis.has_result()?)
+        {
+            analysis.think(ITERATIONS_PER_THINK)?;
+        }
+        analysis.finish_position()?;
+
+        let evaluation = analysis.evaluations().last().as_result()?;
+        log(&format!(
+            "position {}/{}: {}",
+            analysis.current(),
+            analysis.positions(),
+            eval_comment(evaluation.score)
+        ));
+    }
+    Ok(analysis)
+}
+
+#[test]
+fn test_winning_chance_and_accuracy() {
+    assert_eq!(winning_chance(0), 50.0);
+    assert!(winning_chance(300) > 74.0 && winning_chance(300) < 76.0);
+    assert_eq!(winning_chance(5_000), winning_chance(MAX_LOSS_CENTIPAWNS));
+    assert!((move_accuracy(60.0, 60.0) - 100.0).abs() < 0.01);
+    assert!(move_accuracy(60.0, 70.0) > 99.9);
+    assert!(move_accuracy(80.0, 20.0) < 10.0);
+
+    assert_eq!(eval_comment(Score::Centipawns(Player::Black, 35)), "[%eval -0.35]");
+    assert_eq!(eval_comment(Score::WinInN(Player::Black, 3)), "[%eval #-2]");
+}
+
+#[test]
+fn test_analyze_game() {
+    // In the Two Knights, 8... cxb5 wins a bishop but loses the rook on a8, without any mate
+    let pgn = r#"[White "Carol"]
+[Black "Dave"]
+
+1. e4 e5 2. Nf3 Nc6 3. Bc4 Nf6 4. Ng5 d5 5. exd5 Na5 6. Bb5+ c6 7. dxc6 bxc6 8. Qf3
+cxb5 9. Qxa8 1-0"#;
+    let game = PgnGame::from_pgn(pgn).unwrap();
+    let options = AnalysisOptions {
+        max_depth: Some(3),
+        ..AnalysisOptions::default()
+    };
+    let mut lines = vec![];
+    let analysis = analyze_game(game, Duration::from_secs(10), options, &mut |line| {
+        lines.push(line.to_string())
+    })
+    .unwrap();
+
+    assert!(analysis.done());
+    assert_eq!(analysis.positions(), 18);
+    assert_eq!(lines.len(), 18);
+
+    let annotated = analysis.annotated_game().unwrap();
+    let text = annotated.to_pgn();
+    println!("{}", text);
+    assert!(text.contains("[Annotator \"rust-chess\"]"));
+    assert!(text.contains("[%eval "));
+    // The engine would have kept the rook, so 8... cxb5 gets a variation
+    assert!(text.contains("cxb5 $4"));
+    assert!(text.contains("(8... "));
+
+    let summary = analysis.summary();
+    println!("{}", summary);
+    assert_eq!((summary.white.moves, summary.black.moves), (9, 8));
+    assert_eq!(summary.black.blunders, 1);
+    assert!(summary.white.accuracy > summary.black.accuracy);
+    assert!(summary.to_string().starts_with("White (Carol): accuracy"));
+
+    // The annotated game reads back in with the same main line
+    let reread = PgnGame::from_pgn(&text).unwrap();
+    assert_eq!(reread.mainline_moves(), annotated.mainline_moves());
+}
@@ -0,0 +1,130 @@
+/*
+A fixed benchmark for speed and search regressions.
+
+Every position is searched to the same depth with a fresh transposition table and no history, so
+nothing carries over between positions or runs and the search is fully deterministic. The total
+node count is then a signature of the search: it only changes when move ordering, pruning or
+evaluation change, and a change that should be a pure speedup has to leave it alone.
+*/
+
+use std::{
+    cell::RefCell,
+    fmt::Display,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    game::Game,
+    helpers::{err_result, ErrorResult},
+    iterative_deepening::{IterativeSearch, IterativeSearchOptions},
+    transposition_table::TranspositionTable,
+};
+
+// Openings, middlegames and endgames, including the perft positions with lots of special moves
+pub const BENCH_POSITIONS: [&str; 10] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "r3k2r/1bq1bppp/pp2p3/2p1n3/P3PP2/2PBN3/1P1BQ1PP/R4RK1 b kq - 0 16",
+    "r1bq1rk1/ppp1nppp/4n3/3p3Q/3P4/1BP1B3/PP1N2PP/R4RK1 w - - 1 16",
+    "6k1/8/4p3/3r4/5n2/1Q6/1K1R4/8 w - - 0 1",
+    "8/8/1p1k4/p2p4/P2P1K2/1P6/8/8 w - - 0 1",
+];
+
+pub const DEFAULT_BENCH_DEPTH: usize = 7;
+
+// Much smaller than the default table, which takes longer to allocate than a whole bench search.
+// The size is part of the signature, since collisions change what the search finds.
+const BENCH_TT_BITS: u32 = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchResult {
+    pub nodes: usize,
+    pub elapsed: Duration,
+}
+
+impl BenchResult {
+    pub fn nps(&self) -> usize {
+        (self.nodes as f64 / self.elapsed.as_secs_f64().max(0.001)) as usize
+    }
+}
+
+impl Display for BenchResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Total time (ms) : {}\nNodes searched  : {}\nNodes/second    : {}",
+            self.elapsed.as_millis(),
+            self.nodes,
+            self.nps()
+        )
+    }
+}
+
+// Returns the nodes, best move and time spent searching, leaving out setting up the table
+fn bench_position(fen: &str, depth: usize) -> ErrorResult<(usize, String, Duration)> {
+    let mut search = IterativeSearch::new(
+        Game::from_fen(fen)?,
+        IterativeSearchOptions {
+            max_depth: Some(depth),
+            transposition_table: Some(Rc::new(RefCell::new(TranspositionTable::with_bits(
+                BENCH_TT_BITS,
+            )))),
+            ..IterativeSearchOptions::default()
+        },
+    )?;
+    let start = Instant::now();
+    while !search.done() {
+        search.iterate(&mut |_| {})?;
+    }
+
+    let bestmove = match search.bestmove() {
+        Some((bestmove, _)) => bestmove.to_string(),
+        None => "(none)".to_string(),
+    };
+    Ok((search.num_nodes(), bestmove, start.elapsed()))
+}
+
+// Searches each of `BENCH_POSITIONS` to `depth`, logging the nodes and best move for each
+pub fn run_bench(depth: usize, log: &mut dyn FnMut(&str)) -> ErrorResult<BenchResult> {
+    if depth == 0 {
+        return err_result("bench depth must be at least 1");
+    }
+
+    let mut result = BenchResult {
+        nodes: 0,
+        elapsed: Duration::ZERO,
+    };
+    for (i, fen) in BENCH_POSITIONS.iter().enumerate() {
+        let (nodes, bestmove, elapsed) = bench_position(fen, depth)?;
+        log(&format!(
+            "Position {}/{}: {} nodes, bestmove {} ({})",
+            i + 1,
+            BENCH_POSITIONS.len(),
+            nodes,
+            bestmove,
+            fen
+        ));
+        result.nodes += nodes;
+        result.elapsed += elapsed;
+    }
+    Ok(result)
+}
+
+#[test]
+fn test_bench_is_deterministic() {
+    let mut lines = vec![];
+    let first = run_bench(3, &mut |line| lines.push(line.to_string())).unwrap();
+    let second = run_bench(3, &mut |_| {}).unwrap();
+
+    assert!(first.nodes > 0);
+    assert_eq!(first.nodes, second.nodes);
+    assert_eq!(lines.len(), BENCH_POSITIONS.len());
+    assert!(first.to_string().contains(&format!("Nodes searched  : {}", first.nodes)));
+
+    assert!(run_bench(0, &mut |_| {}).is_err());
+}
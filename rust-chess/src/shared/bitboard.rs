@@ -13,6 +13,9 @@ pub use each_index_of::*;
 pub mod directions;
 pub use directions::*;
 
+pub mod lines;
+pub use lines::*;
+
 pub mod castling;
 pub use castling::*;
 
@@ -3,10 +3,47 @@ use lazy_static::*;
 use super::super::types::*;
 use super::*;
 
-#[derive(Debug, Clone)]
+// Starting files of the king and both castling rooks. They are only ever different from the
+// standard ones in Chess960.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CastlingFiles {
+    pub king: u8,
+    pub kingside_rook: u8,
+    pub queenside_rook: u8,
+}
+
+pub const STANDARD_CASTLING_FILES: CastlingFiles = CastlingFiles {
+    king: 4,
+    kingside_rook: 7,
+    queenside_rook: 0,
+};
+
+impl Default for CastlingFiles {
+    fn default() -> Self {
+        STANDARD_CASTLING_FILES
+    }
+}
+
+impl CastlingFiles {
+    pub fn rook(&self, side: CastlingSide) -> usize {
+        match side {
+            CastlingSide::Kingside => self.kingside_rook as usize,
+            CastlingSide::Queenside => self.queenside_rook as usize,
+        }
+    }
+
+    pub fn set_rook(&mut self, side: CastlingSide, file: usize) {
+        match side {
+            CastlingSide::Kingside => self.kingside_rook = file as u8,
+            CastlingSide::Queenside => self.queenside_rook = file as u8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct CastlingRequirements {
-    pub require_safe: Vec<BoardIndex>,
-    pub require_empty: Vec<BoardIndex>,
+    pub require_safe: Bitboard,
+    pub require_empty: Bitboard,
     pub king_start: BoardIndex,
     pub king_end: BoardIndex,
     pub rook_start: BoardIndex,
@@ -14,57 +51,115 @@ pub struct CastlingRequirements {
     pub castling_pieces: Bitboard,
 }
 
+pub fn back_rank(player: Player) -> usize {
+    match player {
+        Player::White => 0,
+        Player::Black => 7,
+    }
+}
+
+pub fn rank_span(rank: usize, a: usize, b: usize) -> Bitboard {
+    let mut bb = 0;
+    for file in a.min(b)..=a.max(b) {
+        bb |= single_bitboard(BoardIndex::from_file_rank(file, rank));
+    }
+    bb
+}
+
+impl CastlingRequirements {
+    // Wherever they start, the king and rook end up on the same squares as in standard chess.
+    // Every square either of them crosses has to be empty apart from the two of them, and the
+    // king may not cross an attacked square.
+    pub fn new(player: Player, side: CastlingSide, files: CastlingFiles) -> Self {
+        let rank = back_rank(player);
+        let (king_end_file, rook_end_file) = match side {
+            CastlingSide::Kingside => (6, 5),
+            CastlingSide::Queenside => (2, 3),
+        };
+        let king_file = files.king as usize;
+        let rook_file = files.rook(side);
+
+        let king_start = BoardIndex::from_file_rank(king_file, rank);
+        let rook_start = BoardIndex::from_file_rank(rook_file, rank);
+        let castling_pieces = single_bitboard(king_start) | single_bitboard(rook_start);
+
+        let king_path = rank_span(rank, king_file, king_end_file);
+        let rook_path = rank_span(rank, rook_file, rook_end_file);
+
+        Self {
+            require_safe: king_path,
+            require_empty: (king_path | rook_path) & !castling_pieces,
+            king_start,
+            king_end: BoardIndex::from_file_rank(king_end_file, rank),
+            rook_start,
+            rook_end: BoardIndex::from_file_rank(rook_end_file, rank),
+            castling_pieces,
+        }
+    }
+
+    pub fn notation_end(&self) -> BoardIndex {
+        castling_notation_end(self.king_start, self.king_end, self.rook_start)
+    }
+}
+
+// Castling is written as the king's move when king and rook start on their standard squares.
+// Otherwise it is written as the king taking its own rook, since in Chess960 the king's move
+// alone can look exactly like a normal king move.
+pub fn castling_notation_end(
+    king_start: BoardIndex,
+    king_end: BoardIndex,
+    rook_start: BoardIndex,
+) -> BoardIndex {
+    let standard = king_start.file() == STANDARD_CASTLING_FILES.king as usize
+        && (rook_start.file() == STANDARD_CASTLING_FILES.kingside_rook as usize
+            || rook_start.file() == STANDARD_CASTLING_FILES.queenside_rook as usize);
+    if standard {
+        king_end
+    } else {
+        rook_start
+    }
+}
+
 lazy_static! {
-    static ref WHITE_KINGSIDE_CASTLING: CastlingRequirements = CastlingRequirements {
-        require_safe: map_index_from_file_rank_strs(["e1", "f1", "g1"]),
-        require_empty: map_index_from_file_rank_strs(["f1", "g1"]),
-        king_start: index_from_file_rank_str("e1").unwrap(),
-        king_end: index_from_file_rank_str("g1").unwrap(),
-        rook_start: index_from_file_rank_str("h1").unwrap(),
-        rook_end: index_from_file_rank_str("f1").unwrap(),
-        castling_pieces: bitboard_with_file_rank_strs_set(&["e1", "h1"]),
-    };
-    static ref WHITE_QUEENSIDE_CASTLING: CastlingRequirements = CastlingRequirements {
-        require_safe: map_index_from_file_rank_strs(["e1", "d1", "c1"]),
-        require_empty: map_index_from_file_rank_strs(["b1", "c1", "d1"]),
-        king_start: index_from_file_rank_str("e1").unwrap(),
-        king_end: index_from_file_rank_str("c1").unwrap(),
-        rook_start: index_from_file_rank_str("a1").unwrap(),
-        rook_end: index_from_file_rank_str("d1").unwrap(),
-        castling_pieces: bitboard_with_file_rank_strs_set(&["e1", "a1"]),
-    };
-    static ref BLACK_KINGSIDE_CASTLING: CastlingRequirements = CastlingRequirements {
-        require_safe: map_index_from_file_rank_strs(["e8", "f8", "g8"]),
-        require_empty: map_index_from_file_rank_strs(["f8", "g8"]),
-        king_start: index_from_file_rank_str("e8").unwrap(),
-        king_end: index_from_file_rank_str("g8").unwrap(),
-        rook_start: index_from_file_rank_str("h8").unwrap(),
-        rook_end: index_from_file_rank_str("f8").unwrap(),
-        castling_pieces: bitboard_with_file_rank_strs_set(&["e8", "h8"]),
-    };
-    static ref BLACK_QUEENSIDE_CASTLING: CastlingRequirements = CastlingRequirements {
-        require_safe: map_index_from_file_rank_strs(["e8", "d8", "c8"]),
-        require_empty: map_index_from_file_rank_strs(["b8", "c8", "d8"]),
-        king_start: index_from_file_rank_str("e8").unwrap(),
-        king_end: index_from_file_rank_str("c8").unwrap(),
-        rook_start: index_from_file_rank_str("a8").unwrap(),
-        rook_end: index_from_file_rank_str("d8").unwrap(),
-        castling_pieces: bitboard_with_file_rank_strs_set(&["e8", "a8"]),
-    };
+    static ref WHITE_KINGSIDE_CASTLING: CastlingRequirements = CastlingRequirements::new(
+        Player::White,
+        CastlingSide::Kingside,
+        STANDARD_CASTLING_FILES
+    );
+    static ref WHITE_QUEENSIDE_CASTLING: CastlingRequirements = CastlingRequirements::new(
+        Player::White,
+        CastlingSide::Queenside,
+        STANDARD_CASTLING_FILES
+    );
+    static ref BLACK_KINGSIDE_CASTLING: CastlingRequirements = CastlingRequirements::new(
+        Player::Black,
+        CastlingSide::Kingside,
+        STANDARD_CASTLING_FILES
+    );
+    static ref BLACK_QUEENSIDE_CASTLING: CastlingRequirements = CastlingRequirements::new(
+        Player::Black,
+        CastlingSide::Queenside,
+        STANDARD_CASTLING_FILES
+    );
 }
 
 pub fn castling_requirements(
     player: Player,
     castling_side: CastlingSide,
-) -> &'static CastlingRequirements {
+    files: CastlingFiles,
+) -> CastlingRequirements {
+    if files != STANDARD_CASTLING_FILES {
+        return CastlingRequirements::new(player, castling_side, files);
+    }
+
     match player {
         Player::White => match castling_side {
-            CastlingSide::Kingside => &WHITE_KINGSIDE_CASTLING,
-            CastlingSide::Queenside => &WHITE_QUEENSIDE_CASTLING,
+            CastlingSide::Kingside => *WHITE_KINGSIDE_CASTLING,
+            CastlingSide::Queenside => *WHITE_QUEENSIDE_CASTLING,
         },
         Player::Black => match castling_side {
-            CastlingSide::Kingside => &BLACK_KINGSIDE_CASTLING,
-            CastlingSide::Queenside => &BLACK_QUEENSIDE_CASTLING,
+            CastlingSide::Kingside => *BLACK_KINGSIDE_CASTLING,
+            CastlingSide::Queenside => *BLACK_QUEENSIDE_CASTLING,
         },
     }
 }
@@ -72,21 +167,24 @@ pub fn castling_requirements(
 pub fn castling_allowed_after_move(
     player: Player,
     castling_side: CastlingSide,
+    files: CastlingFiles,
     start_index: BoardIndex,
 ) -> bool {
-    let castling_requirements = castling_requirements(player, castling_side);
+    let castling_requirements = castling_requirements(player, castling_side, files);
     let castling_piece_moved = bb_contains(castling_requirements.castling_pieces, start_index);
     !castling_piece_moved
 }
 
+// Finds the castling move written as `start` to `end`, see `castling_notation_end`
 pub fn matches_castling(
     player: Player,
+    files: CastlingFiles,
     start: BoardIndex,
     end: BoardIndex,
-) -> Option<(CastlingSide, &'static CastlingRequirements)> {
+) -> Option<(CastlingSide, CastlingRequirements)> {
     for side in CASTLING_SIDES {
-        let req = castling_requirements(player, side);
-        if start == req.king_start && end == req.king_end {
+        let req = castling_requirements(player, side, files);
+        if start == req.king_start && (end == req.notation_end() || end == req.rook_start) {
             return Some((side, req));
         }
     }
@@ -0,0 +1,84 @@
+use lazy_static::lazy_static;
+
+use super::*;
+
+// The file and rank step from `a` towards `b`, when they share a rank, file or diagonal
+fn line_step(a: BoardIndex, b: BoardIndex) -> Option<(isize, isize)> {
+    let file_delta = b.file() as isize - a.file() as isize;
+    let rank_delta = b.rank() as isize - a.rank() as isize;
+
+    let aligned = file_delta == 0 || rank_delta == 0 || file_delta.abs() == rank_delta.abs();
+    if a == b || !aligned {
+        return None;
+    }
+    Some((file_delta.signum(), rank_delta.signum()))
+}
+
+fn walk_from(start: BoardIndex, (file_step, rank_step): (isize, isize)) -> Bitboard {
+    let mut bb = 0;
+    let mut file = start.file() as isize + file_step;
+    let mut rank = start.rank() as isize + rank_step;
+    while (0..8).contains(&file) && (0..8).contains(&rank) {
+        bb |= single_bitboard(BoardIndex::from_file_rank(file as usize, rank as usize));
+        file += file_step;
+        rank += rank_step;
+    }
+    bb
+}
+
+fn compute_lines(between: bool) -> Vec<[Bitboard; 64]> {
+    let mut lines = vec![[0; 64]; 64];
+    for (a, row) in lines.iter_mut().enumerate() {
+        for (b, line) in row.iter_mut().enumerate() {
+            let (a_index, b_index) = (BoardIndex::from(a), BoardIndex::from(b));
+            let Some(step) = line_step(a_index, b_index) else {
+                continue;
+            };
+            let towards = walk_from(a_index, step);
+            let away = walk_from(b_index, (-step.0, -step.1));
+            *line = if between {
+                towards & away
+            } else {
+                towards | away
+            };
+        }
+    }
+    lines
+}
+
+lazy_static! {
+    static ref BETWEEN: Vec<[Bitboard; 64]> = compute_lines(true);
+    static ref LINE: Vec<[Bitboard; 64]> = compute_lines(false);
+}
+
+// Squares strictly between `a` and `b`, empty unless they share a rank, file or diagonal
+pub fn squares_between(a: BoardIndex, b: BoardIndex) -> Bitboard {
+    BETWEEN[a.i][b.i]
+}
+
+// The whole rank, file or diagonal through `a` and `b`, from edge to edge
+pub fn line_through(a: BoardIndex, b: BoardIndex) -> Bitboard {
+    LINE[a.i][b.i]
+}
+
+#[test]
+fn test_lines() {
+    let index = |s: &str| BoardIndex::from_str(s).unwrap();
+
+    assert_eq!(
+        squares_between(index("b2"), index("e5")),
+        bitboard_with_file_rank_strs_set(&["c3", "d4"])
+    );
+    assert_eq!(
+        squares_between(index("e1"), index("e8")),
+        bitboard_with_file_rank_strs_set(&["e2", "e3", "e4", "e5", "e6", "e7"])
+    );
+    assert_eq!(squares_between(index("e1"), index("e2")), 0);
+    assert_eq!(squares_between(index("g1"), index("f3")), 0);
+
+    assert_eq!(
+        line_through(index("c3"), index("d4")),
+        bitboard_with_file_rank_strs_set(&["a1", "b2", "c3", "d4", "e5", "f6", "g7", "h8"])
+    );
+    assert_eq!(line_through(index("a1"), index("b3")), 0);
+}
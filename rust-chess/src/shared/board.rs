@@ -1,7 +1,7 @@
 use std::collections::{HashMap, HashSet};
 
 use crate::{
-    bitboard::{Bitboards, BoardIndex, ForPlayer},
+    bitboard::{Bitboards, BoardIndex, CastlingFiles, ForPlayer},
     game::{CanCastleOnSide, Game},
     helpers::{err_result, ErrorResult},
     perft::traverse_game_callback,
@@ -15,6 +15,7 @@ pub struct Board {
     bitboards: Bitboards,
     player: Player,
     can_castle: ForPlayer<CanCastleOnSide>,
+    castling_files: ForPlayer<CastlingFiles>,
     en_passant: Option<BoardIndex>,
 
     zobrist: ZobristHash,
@@ -25,6 +26,7 @@ impl Board {
         bitboards: Bitboards,
         player: Player,
         can_castle: ForPlayer<CanCastleOnSide>,
+        castling_files: ForPlayer<CastlingFiles>,
         en_passant: Option<BoardIndex>,
     ) -> Self {
         let zobrist = ZobristHash::from(&bitboards, player, can_castle, en_passant);
@@ -32,6 +34,7 @@ impl Board {
             bitboards,
             player,
             can_castle,
+            castling_files,
             en_passant,
             zobrist,
         }
@@ -42,7 +45,8 @@ impl Board {
             "{} {} {} {}",
             self.bitboards().to_fen(),
             self.player().to_fen(),
-            self.can_castle().to_fen(),
+            self.can_castle()
+                .to_fen(self.castling_files(), self.bitboards()),
             self.en_passant()
                 .map(|i| i.to_string())
                 .unwrap_or("-".to_string()),
@@ -107,6 +111,28 @@ impl Board {
         self.zobrist.on_update_square(index, piece);
         Ok(())
     }
+
+    // Unmaking a move puts back the hash saved before it, so squares change without rehashing
+    pub fn clear_square_unhashed(&mut self, index: BoardIndex, piece: PlayerPiece) {
+        self.bitboards.clear_square(index, piece);
+    }
+
+    pub fn set_square_unhashed(&mut self, index: BoardIndex, piece: PlayerPiece) {
+        self.bitboards.set_square(index, piece);
+    }
+
+    pub fn restore(
+        &mut self,
+        player: Player,
+        can_castle: ForPlayer<CanCastleOnSide>,
+        en_passant: Option<BoardIndex>,
+        zobrist: ZobristHash,
+    ) {
+        self.player = player;
+        self.can_castle = can_castle;
+        self.en_passant = en_passant;
+        self.zobrist = zobrist;
+    }
 }
 
 #[test]
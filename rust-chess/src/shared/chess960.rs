@@ -0,0 +1,174 @@
+/*
+Chess960 (Fischer Random) start positions.
+
+The 960 back ranks are numbered with Scharnagl's scheme, where index 518 is the standard position.
+The index is read as a mixed radix number that places, in order: the light squared bishop, the
+dark squared bishop, the queen on one of the six remaining squares and the two knights on the
+remaining five. The last three squares always get rook, king, rook, so the king ends up between
+the rooks and the position can be written with plain KQkq castling rights.
+*/
+
+use crate::{
+    game::Game,
+    helpers::{err_result, ErrorResult},
+};
+
+pub const CHESS960_POSITIONS: usize = 960;
+
+pub const STANDARD_CHESS960_INDEX: usize = 518;
+
+// Knight placements among the five squares left after the bishops and queen
+const KNIGHT_SQUARES: [(usize, usize); 10] = [
+    (0, 1),
+    (0, 2),
+    (0, 3),
+    (0, 4),
+    (1, 2),
+    (1, 3),
+    (1, 4),
+    (2, 3),
+    (2, 4),
+    (3, 4),
+];
+
+// White's back rank from the a-file to the h-file, e.g. "RNBQKBNR" for 518
+pub fn chess960_back_rank(index: usize) -> ErrorResult<String> {
+    if index >= CHESS960_POSITIONS {
+        return err_result(&format!(
+            "chess960 index {} isn't below {}",
+            index, CHESS960_POSITIONS
+        ));
+    }
+
+    let mut rank: [Option<char>; 8] = [None; 8];
+    let mut n = index;
+
+    rank[2 * (n % 4) + 1] = Some('B');
+    n /= 4;
+    rank[2 * (n % 4)] = Some('B');
+    n /= 4;
+
+    let empty = |rank: &[Option<char>; 8]| -> Vec<usize> {
+        (0..8).filter(|&file| rank[file].is_none()).collect()
+    };
+
+    rank[empty(&rank)[n % 6]] = Some('Q');
+    n /= 6;
+
+    let (first, second) = KNIGHT_SQUARES[n];
+    let remaining = empty(&rank);
+    rank[remaining[first]] = Some('N');
+    rank[remaining[second]] = Some('N');
+
+    for (file, piece) in empty(&rank).into_iter().zip(['R', 'K', 'R']) {
+        rank[file] = Some(piece);
+    }
+
+    Ok(rank.iter().map(|piece| piece.unwrap()).collect())
+}
+
+pub fn chess960_start_fen(index: usize) -> ErrorResult<String> {
+    let white = chess960_back_rank(index)?;
+    let black = white.to_lowercase();
+    Ok(format!(
+        "{}/pppppppp/8/8/8/8/PPPPPPPP/{} w KQkq - 0 1",
+        black, white
+    ))
+}
+
+pub fn chess960_start_game(index: usize) -> ErrorResult<Game> {
+    Game::from_fen(&chess960_start_fen(index)?)
+}
+
+#[test]
+fn test_chess960_start_positions() {
+    use crate::perft::run_perft;
+
+    assert_eq!(
+        chess960_start_fen(STANDARD_CHESS960_INDEX).unwrap(),
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+    );
+    assert_eq!(chess960_back_rank(0).unwrap(), "BBQNNRKR");
+    assert_eq!(chess960_back_rank(959).unwrap(), "RKRNNQBB");
+    assert!(chess960_back_rank(CHESS960_POSITIONS).is_err());
+
+    let mut back_ranks = std::collections::HashSet::new();
+    for index in 0..CHESS960_POSITIONS {
+        let back_rank = chess960_back_rank(index).unwrap();
+        let king = back_rank.find('K').unwrap();
+        assert!(back_rank[..king].contains('R') && back_rank[king..].contains('R'));
+        back_ranks.insert(back_rank);
+    }
+    assert_eq!(back_ranks.len(), CHESS960_POSITIONS);
+
+    // Neither side can interfere with the other's first move, but some start positions allow
+    // castling right away or have a knight boxed in
+    for index in (0..CHESS960_POSITIONS).step_by(37) {
+        let game = chess960_start_game(index).unwrap();
+        assert_eq!(game.to_fen(), chess960_start_fen(index).unwrap());
+        let moves = run_perft(&game, 1).unwrap();
+        assert_eq!(run_perft(&game, 2).unwrap(), moves * moves);
+    }
+
+    let game = chess960_start_game(259).unwrap();
+    assert_eq!(game.board().to_fen(), "bnrknqrb/pppppppp/8/8/8/8/PPPPPPPP/BNRKNQRB w KQkq -");
+    assert_eq!(run_perft(&game, 1).unwrap(), 21);
+    assert!(game.move_from_str("d1c1").is_some());
+}
+
+#[test]
+fn test_chess960_perft() {
+    use crate::perft::run_perft;
+
+    let positions = [
+        (
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            [21, 528, 12189],
+        ),
+        (
+            "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+            [21, 807, 18002],
+        ),
+        (
+            "b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9",
+            [20, 479, 10471],
+        ),
+    ];
+
+    for (fen, expected) in positions {
+        let game = Game::from_fen(fen).unwrap();
+        for (depth, &count) in expected.iter().enumerate() {
+            assert_eq!(run_perft(&game, depth + 1).unwrap(), count, "{}", fen);
+        }
+    }
+}
+
+#[test]
+fn test_chess960_castling() {
+    use crate::moves::{MoveType, Quiet};
+
+    // With the king on b1, O-O-O and a plain king move both end on c1
+    let mut game = Game::from_fen("r4kr1/8/8/8/8/8/8/RK5R w AHag - 0 1").unwrap();
+    assert_eq!(game.board().to_fen(), "r4kr1/8/8/8/8/8/8/RK5R w KQkq -");
+
+    let queenside = game.move_from_str("b1a1").unwrap();
+    assert_eq!(queenside.to_uci(), "b1a1");
+    assert_eq!(queenside.end_index.to_string(), "c1");
+    let king_move = game.move_from_str("b1c1").unwrap();
+    assert_eq!(king_move.move_type, MoveType::Quiet(Quiet::Move));
+
+    game.make_move(queenside).unwrap();
+    assert_eq!(game.board().to_fen(), "r4kr1/8/8/8/8/8/8/2KR3R b kq -");
+
+    // Black's king castles onto the square its rook leaves
+    let kingside = game.move_from_str("f8g8").unwrap();
+    game.make_move(kingside).unwrap();
+    assert_eq!(game.board().to_fen(), "r4rk1/8/8/8/8/8/8/2KR3R w - -");
+
+    // Standard castling keeps its usual notation, and takes the rook with UCI_Chess960
+    let game = Game::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+    let castle = game.move_from_str("e1g1").unwrap();
+    assert_eq!(castle.to_uci(), "e1g1");
+    assert_eq!(castle.to_uci_with(true), "e1h1");
+    assert_eq!(game.move_from_str("e1h1"), Some(castle));
+}
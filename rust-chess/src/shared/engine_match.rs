@@ -0,0 +1,799 @@
+/*
+Engine-vs-engine matches.
+
+Two engines, either this engine with its own `IterativeSearchOptions` and skill level or external
+UCI binaries, play each opening twice with colours reversed so neither side profits from a lucky
+position. Games end on the usual rules, on a flag fall or illegal move, or as a draw after
+`max_plies`.
+
+The score is kept from the first engine's point of view. The Elo difference and its 95% error bars
+come from the mean and variance of the per-game scores. A match can also run a sequential
+probability ratio test: with H0 "the first engine is elo0 stronger" and H1 "it is elo1 stronger",
+the log-likelihood ratio of H1 over H0 is updated after every game (using the normal approximation
+of the trinomial GSPRT), and the match stops once it crosses either bound.
+*/
+
+use std::{
+    cell::RefCell,
+    fmt::Display,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    epd::Epd,
+    game::Game,
+    helpers::{err_result, ErrorResult},
+    iterative_deepening::{IterativeSearch, IterativeSearchOptions},
+    strength::StrengthLimit,
+    transposition_table::TranspositionTable,
+    types::Player,
+    uci_engine::UciEngine,
+    zobrist::ZobristHistory,
+};
+
+// Common openings a few moves in, all roughly level
+pub const DEFAULT_OPENINGS: [&str; 8] = [
+    "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+    "rnbqkbnr/ppp2ppp/4p3/3p4/2PP4/8/PP2PPPP/RNBQKBNR w KQkq - 0 3",
+    "rnbqkbnr/pp2pppp/3p4/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 3",
+    "rnbqkb1r/pppppp1p/5np1/8/2PP4/8/PP2PPPP/RNBQKBNR w KQkq - 0 3",
+    "rnbqkbnr/ppp2ppp/4p3/3p4/3PP3/8/PPP2PPP/RNBQKBNR w KQkq d6 0 3",
+    "rnbqkb1r/pppp1ppp/5n2/4p3/2P5/2N5/PP1PPPPP/R1BQKBNR w KQkq - 2 3",
+    "rnbqkbnr/pp2pppp/2p5/3p4/3PP3/8/PPP2PPP/RNBQKBNR w KQkq d6 0 3",
+    "rnbqkb1r/ppp1pppp/5n2/3p4/8/5NP1/PPPPPP1P/RNBQKB1R w KQkq - 1 3",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeControl {
+    Depth(usize),
+    MoveTime(Duration),
+    // Time for the whole game plus an increment after every move
+    Clock { base: Duration, increment: Duration },
+}
+
+impl Display for TimeControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TimeControl::Depth(depth) => write!(f, "depth {}", depth),
+            TimeControl::MoveTime(time) => write!(f, "movetime {}ms", time.as_millis()),
+            TimeControl::Clock { base, increment } => {
+                write!(f, "{}+{}", base.as_secs_f64(), increment.as_secs_f64())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Clocks {
+    white: Duration,
+    black: Duration,
+}
+
+impl Clocks {
+    fn get(&self, player: Player) -> Duration {
+        match player {
+            Player::White => self.white,
+            Player::Black => self.black,
+        }
+    }
+
+    fn set(&mut self, player: Player, time: Duration) {
+        match player {
+            Player::White => self.white = time,
+            Player::Black => self.black = time,
+        }
+    }
+}
+
+pub struct InternalEngine {
+    pub name: String,
+    pub options: IterativeSearchOptions,
+    // Plays like `Skill Level` over UCI, within the time control's limits
+    pub strength: Option<StrengthLimit>,
+    tt: Rc<RefCell<TranspositionTable>>,
+    // Picks between close moves for a limited strength, seeded so that matches can be replayed
+    rng: ChaCha8Rng,
+}
+
+pub enum MatchEngine {
+    // Boxed since the engine carries its whole search state
+    Internal(Box<InternalEngine>),
+    External(UciEngine),
+}
+
+// Sets one of the `skip_*` flags or `contempt` from a `self:` engine spec
+fn set_search_option(
+    options: &mut IterativeSearchOptions,
+    name: &str,
+    value: Option<&str>,
+) -> ErrorResult<()> {
+    let flag = match name {
+        "skip_quiescence" => &mut options.skip_quiescence,
+        "skip_cache_sort" => &mut options.skip_cache_sort,
+        "skip_capture_sort" => &mut options.skip_capture_sort,
+        "skip_sibling_beta_cutoff_sort" => &mut options.skip_sibling_beta_cutoff_sort,
+        "skip_aspiration_window" => &mut options.skip_aspiration_window,
+        "skip_null_move_pruning" => &mut options.skip_null_move_pruning,
+        "skip_reverse_futility_pruning" => &mut options.skip_reverse_futility_pruning,
+        "skip_futility_pruning" => &mut options.skip_futility_pruning,
+        "skip_razoring" => &mut options.skip_razoring,
+        "skip_late_move_pruning" => &mut options.skip_late_move_pruning,
+        "contempt" => {
+            return match value.map(|value| value.parse::<isize>()) {
+                Some(Ok(contempt)) => {
+                    options.contempt = contempt;
+                    Ok(())
+                }
+                _ => err_result(&format!("invalid contempt {:?}", value)),
+            };
+        }
+        _ => return err_result(&format!("unknown search option '{}'", name)),
+    };
+    *flag = match value {
+        None | Some("true") => true,
+        Some("false") => false,
+        Some(value) => return err_result(&format!("invalid value for {}: '{}'", name, value)),
+    };
+    Ok(())
+}
+
+impl MatchEngine {
+    pub fn internal(name: &str, options: IterativeSearchOptions) -> Self {
+        MatchEngine::Internal(Box::new(InternalEngine {
+            name: name.to_string(),
+            options,
+            strength: None,
+            tt: Rc::new(RefCell::new(TranspositionTable::small())),
+            rng: ChaCha8Rng::seed_from_u64(0),
+        }))
+    }
+
+    // `self` for this engine with default options, `self:skip_razoring,contempt=0` to change them,
+    // `self:skill=5` for a limited strength, and anything else is the path to a UCI engine
+    pub fn from_spec(spec: &str) -> ErrorResult<Self> {
+        let options = match spec.strip_prefix("self") {
+            Some("") => Some(""),
+            Some(options) => options.strip_prefix(':'),
+            None => None,
+        };
+        let Some(options) = options else {
+            return Ok(MatchEngine::External(UciEngine::spawn(spec)?));
+        };
+
+        let mut search_options = IterativeSearchOptions::default();
+        let mut strength = None;
+        for option in options.split(',').filter(|option| !option.is_empty()) {
+            match option.split_once('=') {
+                Some(("skill", value)) => match value.parse::<usize>() {
+                    Ok(level) => strength = Some(StrengthLimit::from_skill_level(level)),
+                    Err(_) => return err_result(&format!("invalid skill level '{}'", value)),
+                },
+                Some((name, value)) => set_search_option(&mut search_options, name, Some(value))?,
+                None => set_search_option(&mut search_options, option, None)?,
+            }
+        }
+        let mut engine = MatchEngine::internal(spec, search_options);
+        if let MatchEngine::Internal(internal) = &mut engine {
+            internal.strength = strength;
+        }
+        Ok(engine)
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            MatchEngine::Internal(engine) => &engine.name,
+            MatchEngine::External(engine) => &engine.name,
+        }
+    }
+
+    fn new_game(&mut self) -> ErrorResult<()> {
+        match self {
+            MatchEngine::Internal(engine) => {
+                engine.tt = Rc::new(RefCell::new(TranspositionTable::small()));
+                Ok(())
+            }
+            MatchEngine::External(engine) => engine.new_game(),
+        }
+    }
+
+    // The move to play in `game`, reached by playing `moves` from `opening`
+    fn bestmove(
+        &mut self,
+        opening: &str,
+        moves: &[String],
+        game: &Game,
+        history: &ZobristHistory,
+        time_control: TimeControl,
+        clocks: Clocks,
+    ) -> ErrorResult<String> {
+        match self {
+            MatchEngine::Internal(engine) => {
+                engine.bestmove(game, history, time_control, clocks)
+            }
+            MatchEngine::External(engine) => {
+                let position = match moves {
+                    [] => format!("position fen {}", opening),
+                    _ => format!("position fen {} moves {}", opening, moves.join(" ")),
+                };
+                let go = match time_control {
+                    TimeControl::Depth(depth) => format!("go depth {}", depth),
+                    TimeControl::MoveTime(time) => format!("go movetime {}", time.as_millis()),
+                    TimeControl::Clock { increment, .. } => format!(
+                        "go wtime {} btime {} winc {} binc {}",
+                        clocks.white.as_millis(),
+                        clocks.black.as_millis(),
+                        increment.as_millis(),
+                        increment.as_millis()
+                    ),
+                };
+                engine.bestmove(&position, &go)
+            }
+        }
+    }
+}
+
+impl InternalEngine {
+    fn bestmove(
+        &mut self,
+        game: &Game,
+        history: &ZobristHistory,
+        time_control: TimeControl,
+        clocks: Clocks,
+    ) -> ErrorResult<String> {
+        let budget = match time_control {
+            TimeControl::Depth(_) => None,
+            TimeControl::MoveTime(time) => Some(time),
+            // A small slice of what's left so the clock lasts the whole game
+            TimeControl::Clock { increment, .. } => {
+                let remaining = clocks.get(game.player());
+                Some((remaining / 30 + increment / 2).min(remaining / 2))
+            }
+        };
+
+        let max_depth = match time_control {
+            TimeControl::Depth(depth) => Some(depth),
+            _ => self.options.max_depth,
+        };
+        let strength = self.strength;
+        let mut search = IterativeSearch::new(
+            *game,
+            IterativeSearchOptions {
+                max_depth: [max_depth, strength.map(|limit| limit.max_depth)]
+                    .into_iter()
+                    .flatten()
+                    .min(),
+                max_nodes: strength.map(|limit| limit.max_nodes).or(self.options.max_nodes),
+                score_root_moves: strength.is_some_and(|limit| limit.score_margin > 0)
+                    || self.options.score_root_moves,
+                starting_history: history.clone(),
+                transposition_table: Some(self.tt.clone()),
+                ..self.options.clone()
+            },
+        )?;
+
+        let start = Instant::now();
+        while !search.done() {
+            // Always finish the first depth so that there's a move to play
+            let out_of_time = budget.is_some_and(|budget| start.elapsed() >= budget);
+            if out_of_time && search.bestmove().is_some() {
+                break;
+            }
+            search.iterate(&mut |_| {})?;
+        }
+
+        let weakened = strength.and_then(|limit| {
+            limit.choose_move(game.player(), search.root_move_scores(), &mut self.rng)
+        });
+        match (weakened, search.bestmove()) {
+            (Some(m), _) | (None, Some((m, _))) => Ok(m.to_string()),
+            (None, None) => {
+                err_result(&format!("{} found no move in {}", self.name, game.to_fen()))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchGame {
+    pub opening: String,
+    pub white: String,
+    pub black: String,
+    // `None` for a draw
+    pub winner: Option<Player>,
+    pub reason: String,
+    pub moves: Vec<String>,
+}
+
+impl MatchGame {
+    pub fn result(&self) -> &'static str {
+        match self.winner {
+            Some(Player::White) => "1-0",
+            Some(Player::Black) => "0-1",
+            None => "1/2-1/2",
+        }
+    }
+}
+
+impl Display for MatchGame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} vs {}: {} ({}) after {} plies",
+            self.white,
+            self.black,
+            self.result(),
+            self.reason,
+            self.moves.len()
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchOptions {
+    pub time_control: TimeControl,
+    pub max_games: usize,
+    // FENs, each played once with either engine as white
+    pub openings: Vec<String>,
+    pub sprt: Option<Sprt>,
+    // Games this long are drawn, in case neither engine can make progress
+    pub max_plies: usize,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        Self {
+            time_control: TimeControl::MoveTime(Duration::from_millis(100)),
+            max_games: 100,
+            openings: DEFAULT_OPENINGS.iter().map(|fen| fen.to_string()).collect(),
+            sprt: None,
+            max_plies: 400,
+        }
+    }
+}
+
+// Reads one FEN or EPD record per line, skipping blank lines and lines starting with '#'
+pub fn load_openings(path: &str) -> ErrorResult<Vec<String>> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => return err_result(&format!("couldn't read openings {}: {}", path, e)),
+    };
+    text.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| match Game::from_fen(line) {
+            Ok(game) => Ok(game.to_fen()),
+            Err(_) => Ok(Epd::from(line)?.game()?.to_fen()),
+        })
+        .collect()
+}
+
+fn play_game(
+    white: &mut MatchEngine,
+    black: &mut MatchEngine,
+    opening: &str,
+    options: &MatchOptions,
+) -> ErrorResult<MatchGame> {
+    white.new_game()?;
+    black.new_game()?;
+
+    let mut game = Game::from_fen(opening)?;
+    let mut history = ZobristHistory::new();
+    history.add(game.zobrist());
+    let mut moves: Vec<String> = vec![];
+    let mut clocks = match options.time_control {
+        TimeControl::Clock { base, .. } => Clocks {
+            white: base,
+            black: base,
+        },
+        _ => Clocks {
+            white: Duration::ZERO,
+            black: Duration::ZERO,
+        },
+    };
+
+    let (winner, reason) = loop {
+        let status = game.status(&history)?;
+        if status.is_over() {
+            break (status.winner(), status.to_string());
+        }
+        if moves.len() >= options.max_plies {
+            break (None, format!("{} plies", options.max_plies));
+        }
+
+        let player = game.player();
+        let engine = match player {
+            Player::White => &mut *white,
+            Player::Black => &mut *black,
+        };
+        let start = Instant::now();
+        let bestmove =
+            engine.bestmove(opening, &moves, &game, &history, options.time_control, clocks)?;
+        let elapsed = start.elapsed();
+
+        if let TimeControl::Clock { increment, .. } = options.time_control {
+            let remaining = clocks.get(player);
+            if elapsed > remaining {
+                break (Some(player.other()), format!("{} lost on time", engine.name()));
+            }
+            clocks.set(player, remaining - elapsed + increment);
+        }
+
+        let Some(m) = game.move_from_str(&bestmove) else {
+            let reason = format!("illegal move {} by {}", bestmove, engine.name());
+            break (Some(player.other()), reason);
+        };
+        game.make_move(m)?;
+        history.add(game.zobrist());
+        moves.push(bestmove);
+    };
+
+    Ok(MatchGame {
+        opening: opening.to_string(),
+        white: white.name().to_string(),
+        black: black.name().to_string(),
+        winner,
+        reason,
+        moves,
+    })
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MatchScore {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+fn elo_from_score(score: f64) -> f64 {
+    if score <= 0.0 {
+        f64::NEG_INFINITY
+    } else if score >= 1.0 {
+        f64::INFINITY
+    } else {
+        400.0 * (score / (1.0 - score)).log10()
+    }
+}
+
+// Variance of a single game's score, given how many games ended each way
+fn score_variance(wins: f64, draws: f64, losses: f64) -> f64 {
+    let games = (wins + draws + losses).max(1.0);
+    let score = (wins + draws / 2.0) / games;
+    (wins * (1.0 - score).powi(2) + draws * (0.5 - score).powi(2) + losses * score.powi(2))
+        / games
+}
+
+impl MatchScore {
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    // Points per game, between 0 and 1
+    pub fn score(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games().max(1) as f64
+    }
+
+    // Variance of a single game's score
+    fn variance(&self) -> f64 {
+        score_variance(self.wins as f64, self.draws as f64, self.losses as f64)
+    }
+
+    // The Elo difference and the half width of its 95% confidence interval
+    pub fn elo(&self) -> (f64, f64) {
+        let score = self.score();
+        let error = 1.96 * (self.variance() / self.games().max(1) as f64).sqrt();
+        let lower = elo_from_score(score - error);
+        let upper = elo_from_score(score + error);
+        // A clean sweep puts both bounds at infinity
+        let margin = if lower.is_finite() || upper.is_finite() {
+            (upper - lower) / 2.0
+        } else {
+            f64::INFINITY
+        };
+        (elo_from_score(score), margin)
+    }
+
+    pub fn add(&mut self, game: &MatchGame, first_is_white: bool) {
+        let first = if first_is_white {
+            Player::White
+        } else {
+            Player::Black
+        };
+        match game.winner {
+            Some(winner) if winner == first => self.wins += 1,
+            Some(_) => self.losses += 1,
+            None => self.draws += 1,
+        }
+    }
+}
+
+impl Display for MatchScore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (elo, error) = self.elo();
+        write!(
+            f,
+            "W/D/L {}/{}/{} ({:.1}%), Elo {:+.1} ± {:.1}",
+            self.wins,
+            self.draws,
+            self.losses,
+            100.0 * self.score(),
+            elo,
+            error
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    // Chances of accepting H1 when H0 is true, and H0 when H1 is true
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Sprt {
+    pub fn new(elo0: f64, elo1: f64) -> Self {
+        Self {
+            elo0,
+            elo1,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+
+    // The LLR bounds for accepting H0 and H1
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    // Half a game of each result is added so the variance is never zero. Otherwise a clean
+    // sweep, the clearest result there is, would never cross a bound.
+    pub fn llr(&self, score: &MatchScore) -> f64 {
+        if score.games() == 0 {
+            return 0.0;
+        }
+        let wins = score.wins as f64 + 0.5;
+        let draws = score.draws as f64 + 0.5;
+        let losses = score.losses as f64 + 0.5;
+        let mean = (wins + draws / 2.0) / (wins + draws + losses);
+        let variance = score_variance(wins, draws, losses);
+
+        let s0 = expected_score(self.elo0);
+        let s1 = expected_score(self.elo1);
+        score.games() as f64 * (s1 - s0) * (2.0 * mean - s0 - s1) / (2.0 * variance)
+    }
+
+    pub fn verdict(&self, score: &MatchScore) -> SprtVerdict {
+        let llr = self.llr(score);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            SprtVerdict::AcceptH1
+        } else if llr <= lower {
+            SprtVerdict::AcceptH0
+        } else {
+            SprtVerdict::Continue
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SprtVerdict {
+    AcceptH0,
+    AcceptH1,
+    Continue,
+}
+
+impl Display for SprtVerdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            SprtVerdict::AcceptH0 => "H0 accepted",
+            SprtVerdict::AcceptH1 => "H1 accepted",
+            SprtVerdict::Continue => "inconclusive",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchReport {
+    pub first: String,
+    pub second: String,
+    pub time_control: TimeControl,
+    pub games: Vec<MatchGame>,
+    pub score: MatchScore,
+    pub sprt: Option<Sprt>,
+}
+
+impl Display for MatchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} vs {} at {}: {}",
+            self.first, self.second, self.time_control, self.score
+        )?;
+        if let Some(sprt) = &self.sprt {
+            let (lower, upper) = sprt.bounds();
+            write!(
+                f,
+                "\nSPRT elo0 {} elo1 {}: LLR {:.2} ({:.2}, {:.2}), {}",
+                sprt.elo0,
+                sprt.elo1,
+                sprt.llr(&self.score),
+                lower,
+                upper,
+                sprt.verdict(&self.score)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Plays up to `max_games`, alternating colours on each opening and stopping early once the SPRT
+// is decided. Each game and the running score are logged.
+pub fn run_match(
+    first: &mut MatchEngine,
+    second: &mut MatchEngine,
+    options: &MatchOptions,
+    log: &mut dyn FnMut(&str),
+) -> ErrorResult<MatchReport> {
+    if options.openings.is_empty() {
+        return err_result("a match needs at least one opening");
+    }
+
+    let mut report = MatchReport {
+        first: first.name().to_string(),
+        second: second.name().to_string(),
+        time_control: options.time_control,
+        games: vec![],
+        score: MatchScore::default(),
+        sprt: options.sprt,
+    };
+
+    for i in 0..options.max_games {
+        let opening = &options.openings[(i / 2) % options.openings.len()];
+        let first_is_white = i % 2 == 0;
+        let game = if first_is_white {
+            play_game(first, second, opening, options)?
+        } else {
+            play_game(second, first, opening, options)?
+        };
+
+        report.score.add(&game, first_is_white);
+        log(&format!("Game {}: {}", i + 1, game));
+        log(&format!("Score: {}", report.score));
+        report.games.push(game);
+
+        let decided = options
+            .sprt
+            .is_some_and(|sprt| sprt.verdict(&report.score) != SprtVerdict::Continue);
+        if decided {
+            break;
+        }
+    }
+    Ok(report)
+}
+
+#[test]
+fn test_elo_and_sprt() {
+    let even = MatchScore {
+        wins: 30,
+        draws: 40,
+        losses: 30,
+    };
+    let (elo, error) = even.elo();
+    assert_eq!(elo, 0.0);
+    assert!(error > 40.0 && error < 60.0, "{}", error);
+
+    let ahead = MatchScore {
+        wins: 60,
+        draws: 20,
+        losses: 20,
+    };
+    assert_eq!(ahead.score(), 0.7);
+    assert!((ahead.elo().0 - 147.2).abs() < 0.1);
+
+    // A hundred games can only tell big differences apart
+    let sprt = Sprt::new(0.0, 50.0);
+    let (lower, upper) = sprt.bounds();
+    assert!((lower + 2.944).abs() < 0.001 && (upper - 2.944).abs() < 0.001);
+    assert_eq!(sprt.verdict(&ahead), SprtVerdict::AcceptH1);
+    assert_eq!(sprt.verdict(&even), SprtVerdict::Continue);
+    let behind = MatchScore {
+        wins: 20,
+        draws: 20,
+        losses: 60,
+    };
+    assert_eq!(sprt.verdict(&behind), SprtVerdict::AcceptH0);
+    assert_eq!(sprt.verdict(&MatchScore::default()), SprtVerdict::Continue);
+
+    let sweep = MatchScore {
+        wins: 2,
+        draws: 0,
+        losses: 0,
+    };
+    assert_eq!(sweep.elo(), (f64::INFINITY, f64::INFINITY));
+    assert_eq!(sprt.verdict(&sweep), SprtVerdict::Continue);
+
+    // A sweep is the clearest result there is, and ends the match early
+    let sweep = MatchScore {
+        wins: 10,
+        draws: 0,
+        losses: 0,
+    };
+    assert_eq!(sprt.verdict(&sweep), SprtVerdict::AcceptH1);
+    let swept = MatchScore {
+        wins: 0,
+        draws: 0,
+        losses: 10,
+    };
+    assert_eq!(sprt.verdict(&swept), SprtVerdict::AcceptH0);
+}
+
+#[test]
+fn test_run_match() {
+    let mut first = MatchEngine::from_spec("self").unwrap();
+    let mut second = MatchEngine::from_spec("self:skip_quiescence,contempt=0").unwrap();
+    assert!(MatchEngine::from_spec("self:skip_everything").is_err());
+    assert!(MatchEngine::from_spec("self:skill=lots").is_err());
+    let MatchEngine::Internal(weak) = MatchEngine::from_spec("self:skill=3,contempt=0").unwrap()
+    else {
+        panic!("expected an internal engine");
+    };
+    assert_eq!(weak.strength, Some(StrengthLimit::from_skill_level(3)));
+
+    // White mates on the back rank at once, so each engine wins the games it plays as white
+    let back_rank = "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1";
+    let options = MatchOptions {
+        time_control: TimeControl::Depth(2),
+        max_games: 4,
+        openings: vec![back_rank.to_string()],
+        sprt: Some(Sprt::new(0.0, 5.0)),
+        max_plies: 40,
+    };
+    let mut lines = vec![];
+    let report =
+        run_match(&mut first, &mut second, &options, &mut |line| lines.push(line.to_string()))
+            .unwrap();
+    println!("{}", report);
+
+    assert_eq!(report.games.len(), 4);
+    assert_eq!(lines.len(), 8);
+    assert_eq!(report.games[0].white, "self");
+    assert_eq!(report.games[1].white, "self:skip_quiescence,contempt=0");
+    for game in &report.games {
+        assert_eq!(game.moves, ["d1d8"]);
+        assert_eq!(game.winner, Some(Player::White));
+    }
+    assert_eq!(
+        report.score,
+        MatchScore {
+            wins: 2,
+            draws: 0,
+            losses: 2
+        }
+    );
+    assert!(report.to_string().contains("W/D/L 2/0/2 (50.0%), Elo +0.0"));
+    assert!(report.to_string().contains("inconclusive"));
+
+    // A short game between different settings, ended by the ply limit if nothing else
+    let options = MatchOptions {
+        time_control: TimeControl::MoveTime(Duration::from_millis(5)),
+        max_games: 1,
+        max_plies: 10,
+        ..MatchOptions::default()
+    };
+    let report = run_match(&mut first, &mut second, &options, &mut |_| {}).unwrap();
+    assert_eq!(report.games.len(), 1);
+    assert!(report.games[0].moves.len() <= 10);
+}
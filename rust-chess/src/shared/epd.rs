@@ -0,0 +1,435 @@
+/*
+Extended Position Description.
+
+An EPD record is the first four FEN fields followed by semicolon terminated operations, each an
+opcode and zero or more operands, e.g.
+
+    r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - bm Qxf7#; id "mate.001";
+
+Opcodes are kept in order and as written, so unknown ones survive a round trip. Move operands
+(bm, am, pv) are in SAN and resolved against the position.
+*/
+
+use std::fmt::Display;
+
+use crate::{
+    game::Game,
+    helpers::{err_result, ErrorResult},
+    moves::Move,
+    san::{move_from_san, move_to_san, moves_to_san},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EpdOperation {
+    pub opcode: String,
+    pub operands: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Epd {
+    // Piece placement, side to move, castling and en passant
+    pub position: String,
+    pub operations: Vec<EpdOperation>,
+}
+
+// Opcodes whose operand is free text and always written quoted
+fn is_string_opcode(opcode: &str) -> bool {
+    matches!(
+        opcode,
+        "id" | "ecn" | "eco" | "nic" | "tcgs" | "tcri" | "tcsi" | "v0" | "v1"
+    ) || (opcode.len() == 2
+        && opcode.starts_with('c')
+        && opcode[1..].chars().all(|c| c.is_ascii_digit()))
+}
+
+fn parse_operations(s: &str) -> ErrorResult<Vec<EpdOperation>> {
+    let mut operations = vec![];
+    let mut words: Vec<String> = vec![];
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            _ if c.is_whitespace() => {}
+            ';' => {
+                if words.is_empty() {
+                    continue;
+                }
+                let opcode = words.remove(0);
+                operations.push(EpdOperation {
+                    opcode,
+                    operands: std::mem::take(&mut words),
+                });
+            }
+            '"' => {
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => match chars.next() {
+                            Some(escaped) => word.push(escaped),
+                            None => return err_result("unterminated string in EPD"),
+                        },
+                        Some('"') => break,
+                        Some(c) => word.push(c),
+                        None => return err_result("unterminated string in EPD"),
+                    }
+                }
+                words.push(word);
+            }
+            _ => {
+                let mut word = c.to_string();
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || next == ';' {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+                words.push(word);
+            }
+        }
+    }
+
+    // Tolerate a missing semicolon after the last operation
+    if !words.is_empty() {
+        let opcode = words.remove(0);
+        operations.push(EpdOperation {
+            opcode,
+            operands: words,
+        });
+    }
+
+    Ok(operations)
+}
+
+impl Epd {
+    pub fn from(line: &str) -> ErrorResult<Epd> {
+        let line = line.trim();
+
+        // Split off the four position fields, the rest belongs to the operations
+        let mut rest = line;
+        let mut fields = vec![];
+        for _ in 0..4 {
+            rest = rest.trim_start();
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            if end == 0 {
+                return err_result(&format!("EPD needs four position fields: {}", line));
+            }
+            fields.push(&rest[..end]);
+            rest = &rest[end..];
+        }
+
+        let epd = Epd {
+            position: fields.join(" "),
+            operations: parse_operations(rest)?,
+        };
+
+        // Catch broken positions early rather than when the game is first needed
+        epd.game()?;
+        Ok(epd)
+    }
+
+    pub fn from_game(game: &Game) -> Epd {
+        let fen = game.to_fen();
+        let fields: Vec<&str> = fen.split(' ').take(4).collect();
+        Epd {
+            position: fields.join(" "),
+            operations: vec![],
+        }
+    }
+
+    // The position, with the move counters taken from hmvc and fmvn if present
+    pub fn game(&self) -> ErrorResult<Game> {
+        let half_moves = self.operand("hmvc").unwrap_or("0");
+        let full_moves = self.operand("fmvn").unwrap_or("1");
+        Game::from_fen(&format!("{} {} {}", self.position, half_moves, full_moves))
+    }
+
+    pub fn operands(&self, opcode: &str) -> Option<&[String]> {
+        self.operations
+            .iter()
+            .find(|operation| operation.opcode == opcode)
+            .map(|operation| operation.operands.as_slice())
+    }
+
+    pub fn operand(&self, opcode: &str) -> Option<&str> {
+        self.operands(opcode)
+            .and_then(|operands| operands.first())
+            .map(|operand| operand.as_str())
+    }
+
+    pub fn set_operands(&mut self, opcode: &str, operands: Vec<String>) {
+        match self
+            .operations
+            .iter_mut()
+            .find(|operation| operation.opcode == opcode)
+        {
+            Some(operation) => operation.operands = operands,
+            None => self.operations.push(EpdOperation {
+                opcode: opcode.to_string(),
+                operands,
+            }),
+        }
+    }
+
+    pub fn remove(&mut self, opcode: &str) {
+        self.operations
+            .retain(|operation| operation.opcode != opcode);
+    }
+
+    fn moves(&self, opcode: &str) -> ErrorResult<Vec<Move>> {
+        let game = self.game()?;
+        self.operands(opcode)
+            .unwrap_or(&[])
+            .iter()
+            .map(|san| move_from_san(&game, san))
+            .collect()
+    }
+
+    fn set_moves(&mut self, opcode: &str, moves: &[Move]) -> ErrorResult<()> {
+        let game = self.game()?;
+        let sans = moves
+            .iter()
+            .map(|m| move_to_san(&game, m))
+            .collect::<ErrorResult<Vec<String>>>()?;
+        self.set_operands(opcode, sans);
+        Ok(())
+    }
+
+    fn number<T: std::str::FromStr>(&self, opcode: &str) -> ErrorResult<Option<T>> {
+        match self.operand(opcode) {
+            Some(operand) => match operand.parse::<T>() {
+                Ok(value) => Ok(Some(value)),
+                Err(_) => err_result(&format!("invalid {} operand {}", opcode, operand)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn best_moves(&self) -> ErrorResult<Vec<Move>> {
+        self.moves("bm")
+    }
+
+    pub fn set_best_moves(&mut self, moves: &[Move]) -> ErrorResult<()> {
+        self.set_moves("bm", moves)
+    }
+
+    pub fn avoid_moves(&self) -> ErrorResult<Vec<Move>> {
+        self.moves("am")
+    }
+
+    pub fn set_avoid_moves(&mut self, moves: &[Move]) -> ErrorResult<()> {
+        self.set_moves("am", moves)
+    }
+
+    // The principal variation is a sequence, so each move is read from the position before it
+    pub fn principal_variation(&self) -> ErrorResult<Vec<Move>> {
+        let mut game = self.game()?;
+        let mut variation = vec![];
+        for san in self.operands("pv").unwrap_or(&[]) {
+            let m = move_from_san(&game, san)?;
+            game.make_move(m)?;
+            variation.push(m);
+        }
+        Ok(variation)
+    }
+
+    pub fn set_principal_variation(&mut self, variation: &[Move]) -> ErrorResult<()> {
+        let sans = moves_to_san(&self.game()?, variation)?;
+        self.set_operands("pv", sans);
+        Ok(())
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.operand("id")
+    }
+
+    // Comments c0 to c9
+    pub fn comment(&self, n: usize) -> Option<&str> {
+        self.operand(&format!("c{}", n))
+    }
+
+    // Analysis depth in plies
+    pub fn analysis_depth(&self) -> ErrorResult<Option<usize>> {
+        self.number("acd")
+    }
+
+    // Centipawn evaluation from the side to move's point of view
+    pub fn centipawn_evaluation(&self) -> ErrorResult<Option<isize>> {
+        self.number("ce")
+    }
+
+    // Forced mate in this many moves, negative if the side to move gets mated
+    pub fn direct_mate(&self) -> ErrorResult<Option<isize>> {
+        self.number("dm")
+    }
+
+    // Expected perft node counts from the D1, D2, ... opcodes of perft suites, by depth
+    pub fn perft_counts(&self) -> ErrorResult<Vec<(usize, usize)>> {
+        let mut counts = vec![];
+        for operation in &self.operations {
+            let depth = operation.opcode.strip_prefix('D');
+            let Some(Ok(depth)) = depth.map(|depth| depth.parse::<usize>()) else {
+                continue;
+            };
+            match self.number(&operation.opcode)? {
+                Some(nodes) => counts.push((depth, nodes)),
+                None => return err_result(&format!("missing {} operand", operation.opcode)),
+            }
+        }
+        counts.sort();
+        Ok(counts)
+    }
+
+    pub fn set_analysis(&mut self, depth: usize, centipawns: isize) {
+        self.set_operands("acd", vec![depth.to_string()]);
+        self.set_operands("ce", vec![centipawns.to_string()]);
+    }
+
+    pub fn to_epd(&self) -> String {
+        let mut out = self.position.clone();
+        for EpdOperation { opcode, operands } in &self.operations {
+            out.push(' ');
+            out.push_str(opcode);
+            for operand in operands {
+                out.push(' ');
+                let needs_quotes = is_string_opcode(opcode)
+                    || operand.is_empty()
+                    || operand.contains(|c: char| c.is_whitespace() || c == ';' || c == '"');
+                if needs_quotes {
+                    out.push_str(&format!(
+                        "\"{}\"",
+                        operand.replace('\\', "\\\\").replace('"', "\\\"")
+                    ));
+                } else {
+                    out.push_str(operand);
+                }
+            }
+            out.push(';');
+        }
+        out
+    }
+}
+
+impl Display for Epd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_epd())
+    }
+}
+
+// Parses one record per line, skipping blank lines and lines starting with '#'
+pub fn parse_epd_lines(text: &str) -> ErrorResult<Vec<Epd>> {
+    text.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(Epd::from)
+        .collect()
+}
+
+#[test]
+fn test_epd_parse() {
+    let epd = Epd::from(
+        "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";",
+    )
+    .unwrap();
+
+    assert_eq!(
+        epd.position,
+        "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - -"
+    );
+    assert_eq!(epd.id(), Some("WAC.001"));
+    let best_moves: Vec<String> = epd
+        .best_moves()
+        .unwrap()
+        .iter()
+        .map(|m| m.to_uci())
+        .collect();
+    assert_eq!(best_moves, vec!["g3g6"]);
+    assert_eq!(epd.avoid_moves().unwrap(), vec![]);
+    assert_eq!(epd.analysis_depth().unwrap(), None);
+}
+
+#[test]
+fn test_epd_opcodes() {
+    let line = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - \
+        bm Qxf7# Qxf7; am Qh3 Qh4; acd 12; ce 32000; dm 1; hmvc 4; fmvn 4; \
+        c0 \"Scholar's mate; the classic\"; pv Qxf7#; D1 44 ;";
+    let epd = Epd::from(line).unwrap();
+
+    assert_eq!(epd.operands("bm").unwrap().len(), 2);
+    assert_eq!(epd.best_moves().unwrap()[0].to_uci(), "h5f7");
+    let avoid: Vec<String> = epd
+        .avoid_moves()
+        .unwrap()
+        .iter()
+        .map(|m| m.to_uci())
+        .collect();
+    assert_eq!(avoid, vec!["h5h3", "h5h4"]);
+    assert_eq!(epd.analysis_depth().unwrap(), Some(12));
+    assert_eq!(epd.centipawn_evaluation().unwrap(), Some(32000));
+    assert_eq!(epd.direct_mate().unwrap(), Some(1));
+    assert_eq!(epd.comment(0), Some("Scholar's mate; the classic"));
+    assert_eq!(epd.comment(1), None);
+    assert_eq!(epd.principal_variation().unwrap().len(), 1);
+    assert_eq!(epd.operand("D1"), Some("44"));
+
+    let game = epd.game().unwrap();
+    assert_eq!(game.half_moves_since_pawn_or_capture, 4);
+    assert_eq!(game.full_moves_total, 4);
+
+    let written = epd.to_epd();
+    assert!(written.contains("c0 \"Scholar's mate; the classic\";"));
+    assert_eq!(Epd::from(&written).unwrap(), epd);
+}
+
+#[test]
+fn test_epd_write() {
+    let game = Game::from_fen("startpos").unwrap();
+    let mut epd = Epd::from_game(&game);
+    assert_eq!(
+        epd.to_epd(),
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -"
+    );
+
+    let e4 = game.move_from_str("e2e4").unwrap();
+    let nf3 = game.move_from_str("g1f3").unwrap();
+    epd.set_best_moves(&[e4, nf3]).unwrap();
+    epd.set_analysis(20, 35);
+    epd.set_operands("id", vec!["start".to_string()]);
+    assert_eq!(
+        epd.to_epd(),
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - bm e4 Nf3; acd 20; ce 35; id \"start\";"
+    );
+
+    epd.set_analysis(22, -10);
+    epd.remove("bm");
+    assert_eq!(
+        epd.to_epd(),
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - acd 22; ce -10; id \"start\";"
+    );
+}
+
+#[test]
+fn test_epd_errors() {
+    assert!(Epd::from("8/8/8/8 w").is_err());
+    assert!(Epd::from("4k3/8/8/8/8/8/8/4K3 w - - id \"unterminated;").is_err());
+
+    let epd = Epd::from("4k3/8/8/8/8/8/8/4K3 w - - bm Qh5; acd deep").unwrap();
+    assert!(epd.best_moves().is_err());
+    assert!(epd.analysis_depth().is_err());
+
+    let epds = parse_epd_lines(
+        "# comment\n\n4k3/8/8/8/8/8/8/4K3 w - - id \"a\";\n4k3/8/8/8/8/8/8/4K3 b - -\n",
+    )
+    .unwrap();
+    assert_eq!(epds.len(), 2);
+}
+
+#[test]
+fn test_epd_perft_counts() {
+    let epd = Epd::from("4k3/8/8/8/8/8/8/4K2R w K - ;D2 66 ;D1 15 ;id \"x\"").unwrap();
+    assert_eq!(epd.perft_counts().unwrap(), vec![(1, 15), (2, 66)]);
+    assert_eq!(epd.id(), Some("x"));
+
+    let epd = Epd::from("4k3/8/8/8/8/8/8/4K2R w K - ;D1 lots").unwrap();
+    assert!(epd.perft_counts().is_err());
+}
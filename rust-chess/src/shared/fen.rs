@@ -1,14 +1,76 @@
+use std::fmt::Display;
+
 use crate::{
-    bitboard::{index_from_file_rank_str, Bitboard, Bitboards, BoardIndex, ForPlayer},
+    bitboard::{
+        castling_requirements, index_from_file_rank_str, Bitboard, Bitboards, BoardIndex,
+        CastlingFiles, ForPlayer, STANDARD_CASTLING_FILES,
+    },
     game::CanCastleOnSide,
-    helpers::{err_result, ErrorResult},
-    types::Player,
+    helpers::{err, err_result, Error, ErrorResult},
+    moves::index_in_danger,
+    types::{CastlingSide, Piece, Player, PlayerPiece, CASTLING_SIDES},
 };
 
+const BACK_RANKS: Bitboard = 0xFF00_0000_0000_00FF;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FenStrictness {
+    // Only reject FENs that can't be read at all
+    #[default]
+    Lenient,
+
+    // Also reject positions that can't come up in a game
+    Strict,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    Syntax(String),
+    MissingKing(Player),
+    TooManyKings(Player),
+    TooManyPawns(Player),
+    TooManyPieces(Player),
+    PawnOnBackRank(BoardIndex),
+    SideNotToMoveInCheck(Player),
+    ImpossibleCastling(Player, CastlingSide),
+    ImpossibleEnPassant(BoardIndex),
+}
+
+impl Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FenError::Syntax(msg) => write!(f, "{}", msg),
+            FenError::MissingKing(player) => write!(f, "{:?} has no king", player),
+            FenError::TooManyKings(player) => write!(f, "{:?} has more than one king", player),
+            FenError::TooManyPawns(player) => write!(f, "{:?} has more than 8 pawns", player),
+            FenError::TooManyPieces(player) => write!(f, "{:?} has more than 16 pieces", player),
+            FenError::PawnOnBackRank(index) => write!(f, "pawn on the back rank at {}", index),
+            FenError::SideNotToMoveInCheck(player) => {
+                write!(f, "{:?} is in check but it isn't their move", player)
+            }
+            FenError::ImpossibleCastling(player, side) => write!(
+                f,
+                "{:?} can't castle {:?}, the king or rook has moved",
+                player, side
+            ),
+            FenError::ImpossibleEnPassant(index) => {
+                write!(f, "en passant on {} doesn't follow a pawn skip", index)
+            }
+        }
+    }
+}
+
+impl From<FenError> for Error {
+    fn from(e: FenError) -> Self {
+        err(&format!("invalid fen: {}", e))
+    }
+}
+
 pub struct FenDefinition {
     pub bitboards: Bitboards,
     pub player: Player,
     pub can_castle: ForPlayer<CanCastleOnSide>,
+    pub castling_files: ForPlayer<CastlingFiles>,
     pub en_passant: Option<BoardIndex>,
     pub half_moves_since_pawn_or_capture: usize,
     pub full_moves_total: usize,
@@ -64,6 +126,7 @@ impl FenDefinition {
             bitboards: Bitboards::new(),
             player: Player::White,
             can_castle: ForPlayer::new(CanCastleOnSide::default(), CanCastleOnSide::default()),
+            castling_files: ForPlayer::new(STANDARD_CASTLING_FILES, STANDARD_CASTLING_FILES),
             en_passant: None,
             half_moves_since_pawn_or_capture: 0,
             full_moves_total: 0,
@@ -84,8 +147,10 @@ impl FenDefinition {
             return Ok(definition);
         }
 
-        let can_castle_on_side_for_player = ForPlayer::<CanCastleOnSide>::from_str(split[2]);
-        definition.can_castle = can_castle_on_side_for_player?;
+        let (can_castle, castling_files) =
+            ForPlayer::<CanCastleOnSide>::from_fen(split[2], &definition.bitboards)?;
+        definition.can_castle = can_castle;
+        definition.castling_files = castling_files;
 
         if split.len() <= 3 {
             return Ok(definition);
@@ -126,4 +191,143 @@ impl FenDefinition {
 
         Ok(definition)
     }
+
+    pub fn parse(fen: &str, strictness: FenStrictness) -> Result<FenDefinition, FenError> {
+        let definition = FenDefinition::from(fen).map_err(|e| {
+            FenError::Syntax(e.msg.lines().next().unwrap_or_default().to_string())
+        })?;
+        if strictness == FenStrictness::Strict {
+            definition.validate()?;
+        }
+        Ok(definition)
+    }
+
+    // Checks that the position could come up in a game. Returns the first problem found.
+    pub fn validate(&self) -> Result<(), FenError> {
+        let bitboards = &self.bitboards;
+
+        for player in [Player::White, Player::Black] {
+            let pieces = &bitboards.pieces[player];
+            match pieces[Piece::King].count_ones() {
+                0 => return Err(FenError::MissingKing(player)),
+                1 => {}
+                _ => return Err(FenError::TooManyKings(player)),
+            }
+            if pieces[Piece::Pawn].count_ones() > 8 {
+                return Err(FenError::TooManyPawns(player));
+            }
+            if bitboards.occupied[player].count_ones() > 16 {
+                return Err(FenError::TooManyPieces(player));
+            }
+
+            let pawns_on_back_rank = pieces[Piece::Pawn] & BACK_RANKS;
+            if pawns_on_back_rank != 0 {
+                let index = BoardIndex::from(pawns_on_back_rank.trailing_zeros() as usize);
+                return Err(FenError::PawnOnBackRank(index));
+            }
+        }
+
+        let waiting = self.player.other();
+        let waiting_king = bitboards.index_of_piece(waiting, Piece::King);
+        let in_check = index_in_danger(waiting, waiting_king, bitboards)
+            .map_err(|e| FenError::Syntax(e.msg))?;
+        if in_check {
+            return Err(FenError::SideNotToMoveInCheck(waiting));
+        }
+
+        for player in [Player::White, Player::Black] {
+            for &side in CASTLING_SIDES.iter() {
+                if !self.can_castle[player][side] {
+                    continue;
+                }
+                let requirements = castling_requirements(player, side, self.castling_files[player]);
+                let king = bitboards.piece_at_index(requirements.king_start);
+                let rook = bitboards.piece_at_index(requirements.rook_start);
+                if king != Some(PlayerPiece::new(player, Piece::King))
+                    || rook != Some(PlayerPiece::new(player, Piece::Rook))
+                {
+                    return Err(FenError::ImpossibleCastling(player, side));
+                }
+            }
+        }
+
+        if let Some(en_passant) = self.en_passant {
+            // The pawn that skipped belongs to the side that just moved, and sits one square
+            // past the skipped square
+            let (skipped_rank, pawn_rank, start_rank) = match self.player {
+                Player::White => (5, 4, 6),
+                Player::Black => (2, 3, 1),
+            };
+            let file = en_passant.file();
+            let pawn = bitboards.piece_at_index(BoardIndex::from_file_rank(file, pawn_rank));
+            let valid = en_passant.rank() == skipped_rank
+                && !bitboards.is_occupied(en_passant)
+                && !bitboards.is_occupied(BoardIndex::from_file_rank(file, start_rank))
+                && pawn == Some(PlayerPiece::new(waiting, Piece::Pawn));
+            if !valid {
+                return Err(FenError::ImpossibleEnPassant(en_passant));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_strict_fen_validation() {
+    let strict = |fen: &str| FenDefinition::parse(fen, FenStrictness::Strict).err();
+
+    let valid = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3",
+        "rnbqkbnr/pppp1ppp/8/3Pp3/8/8/PPP1PPPP/RNBQKBNR w KQkq e6 0 3",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/8/8/8/8/8/8/K6k w - - 0 1",
+        "k6R/8/8/8/8/8/8/K7 b - - 0 1",
+    ];
+    for fen in valid {
+        assert_eq!(strict(fen), None, "{}", fen);
+    }
+
+    use crate::bitboard::unwrap_index_from_file_rank_str as index;
+    let cases = [
+        ("8/8/8/8/8/8/8/K7 w - - 0 1", FenError::MissingKing(Player::Black)),
+        ("k6k/8/8/8/8/8/8/K7 w - - 0 1", FenError::TooManyKings(Player::Black)),
+        (
+            "k7/8/8/8/8/P7/PPPPPPPP/K7 w - - 0 1",
+            FenError::TooManyPawns(Player::White),
+        ),
+        (
+            "kP6/8/8/8/8/8/8/K7 w - - 0 1",
+            FenError::PawnOnBackRank(index("b8")),
+        ),
+        (
+            "k6R/8/8/8/8/8/8/K7 w - - 0 1",
+            FenError::SideNotToMoveInCheck(Player::Black),
+        ),
+        (
+            "r3k2r/8/8/8/8/8/8/R3K3 w KQkq - 0 1",
+            FenError::ImpossibleCastling(Player::White, CastlingSide::Kingside),
+        ),
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e6 0 1",
+            FenError::ImpossibleEnPassant(index("e6")),
+        ),
+        (
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e6 0 1",
+            FenError::ImpossibleEnPassant(index("e6")),
+        ),
+        ("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w", FenError::Syntax(String::new())),
+    ];
+    for (fen, expected) in cases {
+        let error = strict(fen).unwrap();
+        match expected {
+            FenError::Syntax(_) => assert!(matches!(error, FenError::Syntax(_)), "{}", fen),
+            _ => assert_eq!(error, expected, "{}", fen),
+        }
+
+        // Lenient parsing only cares about syntax
+        let lenient = FenDefinition::parse(fen, FenStrictness::Lenient);
+        assert_eq!(lenient.is_err(), matches!(expected, FenError::Syntax(_)), "{}", fen);
+    }
 }
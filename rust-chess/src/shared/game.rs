@@ -4,18 +4,22 @@ use crate::bitboard::{
     matches_castling, pawn_push_direction_for_player, single_bitboard, starting_pawns_mask,
 };
 use crate::board::Board;
-use crate::fen::FenDefinition;
+use crate::fen::{FenDefinition, FenStrictness};
+use crate::legal_moves::{legal_move_count, legal_moves, legal_moves_from};
 use crate::moves::{can_castle_on_side, walk_potential_bb};
 use crate::simple_move::SimpleMove;
 
 use super::bitboard::FileRank;
-use super::bitboard::{self, castling_allowed_after_move, Bitboards, BoardIndex};
+use super::bitboard::{
+    self, back_rank, castling_allowed_after_move, rank_span, Bitboard, Bitboards, BoardIndex,
+    CastlingFiles, STANDARD_CASTLING_FILES,
+};
 use super::bitboard::{index_from_file_rank_str, ForPlayer};
 use super::danger::Danger;
 use super::helpers::*;
 use super::moves::{all_moves, index_in_danger, Capture, Move, MoveOptions, MoveType, Quiet};
 use super::types::{self, CastlingSide, Piece, Player, PlayerPiece, CASTLING_SIDES};
-use super::zobrist::ZobristHash;
+use super::zobrist::{IsDraw, ZobristHash, ZobristHistory};
 use derive_getters::Getters;
 
 #[derive(Debug, Default, Copy, Clone)]
@@ -44,45 +48,101 @@ impl std::ops::IndexMut<CastlingSide> for CanCastleOnSide {
 }
 
 impl ForPlayer<CanCastleOnSide> {
-    pub fn from_str(str: &str) -> ErrorResult<ForPlayer<CanCastleOnSide>> {
-        Ok(match str {
-            "-" => ForPlayer {
-                white: Default::default(),
-                black: Default::default(),
-            },
-            _ => {
-                let mut can_castle_on_side_for_player: ForPlayer<CanCastleOnSide> = ForPlayer {
-                    white: Default::default(),
-                    black: Default::default(),
-                };
-                for c in str.chars() {
-                    match c {
-                        'K' => can_castle_on_side_for_player.white.kingside = true,
-                        'Q' => can_castle_on_side_for_player.white.queenside = true,
-                        'k' => can_castle_on_side_for_player.black.kingside = true,
-                        'q' => can_castle_on_side_for_player.black.queenside = true,
-                        _ => return err_result(&format!("invalid castling side {}", c)),
-                    }
-                }
-                can_castle_on_side_for_player
-            }
-        })
+    // Besides the usual KQkq this accepts Shredder-FEN, which names the rook's file (HAha), and
+    // X-FEN, where KQkq stand for the outermost rook on that side of the king.
+    pub fn from_fen(
+        str: &str,
+        bitboards: &Bitboards,
+    ) -> ErrorResult<(ForPlayer<CanCastleOnSide>, ForPlayer<CastlingFiles>)> {
+        let mut can_castle: ForPlayer<CanCastleOnSide> = ForPlayer {
+            white: Default::default(),
+            black: Default::default(),
+        };
+        let mut files = ForPlayer::new(STANDARD_CASTLING_FILES, STANDARD_CASTLING_FILES);
+        if str == "-" {
+            return Ok((can_castle, files));
+        }
+
+        for c in str.chars() {
+            let player = if c.is_ascii_uppercase() {
+                Player::White
+            } else {
+                Player::Black
+            };
+            let rank = back_rank(player);
+            let rook = PlayerPiece::new(player, Piece::Rook);
+
+            let king_bb = bitboards.pieces[player][Piece::King] & rank_span(rank, 0, 7);
+            let king_file = match king_bb {
+                0 => STANDARD_CASTLING_FILES.king as usize,
+                _ => bitboard::first_index_of_one(king_bb).file(),
+            };
+            let rook_files = (0..8).filter(|&file| {
+                bitboards.piece_at_index(BoardIndex::from_file_rank(file, rank)) == Some(rook)
+            });
+
+            let (side, rook_file) = match c.to_ascii_lowercase() {
+                'k' => (
+                    CastlingSide::Kingside,
+                    rook_files.filter(|&file| file > king_file).max(),
+                ),
+                'q' => (
+                    CastlingSide::Queenside,
+                    rook_files.filter(|&file| file < king_file).min(),
+                ),
+                file_char => match bitboard::file_from_char(file_char) {
+                    Some(file) if file > king_file => (CastlingSide::Kingside, Some(file)),
+                    Some(file) => (CastlingSide::Queenside, Some(file)),
+                    None => return err_result(&format!("invalid castling side {}", c)),
+                },
+            };
+
+            can_castle[player][side] = true;
+            files[player].king = king_file as u8;
+            let rook_file = rook_file.unwrap_or(STANDARD_CASTLING_FILES.rook(side));
+            files[player].set_rook(side, rook_file);
+        }
+
+        Ok((can_castle, files))
     }
 
-    pub fn to_fen(&self) -> String {
+    // Writes X-FEN, which is plain KQkq unless there is another rook between the king and the
+    // castling rook
+    pub fn to_fen(&self, files: &ForPlayer<CastlingFiles>, bitboards: &Bitboards) -> String {
         let mut fen = String::new();
-        if self.white.kingside {
-            fen.push('K');
-        }
-        if self.white.queenside {
-            fen.push('Q');
-        }
-        if self.black.kingside {
-            fen.push('k');
-        }
-        if self.black.queenside {
-            fen.push('q');
+        for player in [Player::White, Player::Black] {
+            let rank = back_rank(player);
+            let rook = PlayerPiece::new(player, Piece::Rook);
+            let king_file = files[player].king as usize;
+
+            for side in CASTLING_SIDES {
+                if !self[player][side] {
+                    continue;
+                }
+
+                let rook_file = files[player].rook(side);
+                let beyond = match side {
+                    CastlingSide::Kingside => rook_file + 1..8,
+                    CastlingSide::Queenside => 0..rook_file,
+                };
+                let outermost = (rook_file > king_file) == (side == CastlingSide::Kingside)
+                    && beyond.into_iter().all(|file| {
+                        bitboards.piece_at_index(BoardIndex::from_file_rank(file, rank))
+                            != Some(rook)
+                    });
+
+                let c = match (outermost, side) {
+                    (true, CastlingSide::Kingside) => 'k',
+                    (true, CastlingSide::Queenside) => 'q',
+                    (false, _) => bitboard::file_to_char(rook_file),
+                };
+                fen.push(match player {
+                    Player::White => c.to_ascii_uppercase(),
+                    Player::Black => c,
+                });
+            }
         }
+
         if fen.is_empty() {
             "-".to_string()
         } else {
@@ -97,6 +157,68 @@ pub enum Legal {
     Yes,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GameStatus {
+    Ongoing,
+    Checkmate { winner: Player },
+    Stalemate,
+    ThreefoldRepetition,
+    FiftyMoveRule,
+    InsufficientMaterial,
+}
+
+impl GameStatus {
+    pub fn is_over(&self) -> bool {
+        *self != GameStatus::Ongoing
+    }
+
+    pub fn winner(&self) -> Option<Player> {
+        match self {
+            GameStatus::Checkmate { winner } => Some(*winner),
+            _ => None,
+        }
+    }
+
+    // The result as written in PGN
+    pub fn result(&self) -> &'static str {
+        match self {
+            GameStatus::Ongoing => "*",
+            GameStatus::Checkmate {
+                winner: Player::White,
+            } => "1-0",
+            GameStatus::Checkmate {
+                winner: Player::Black,
+            } => "0-1",
+            _ => "1/2-1/2",
+        }
+    }
+}
+
+impl std::fmt::Display for GameStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            GameStatus::Ongoing => "ongoing",
+            GameStatus::Checkmate { .. } => "checkmate",
+            GameStatus::Stalemate => "stalemate",
+            GameStatus::ThreefoldRepetition => "threefold repetition",
+            GameStatus::FiftyMoveRule => "fifty-move rule",
+            GameStatus::InsufficientMaterial => "insufficient material",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+// Everything `make_move` loses that the move itself doesn't record. A captured piece is part of
+// the move already, in `Capture::Take`.
+#[derive(Debug, Copy, Clone)]
+pub struct Undo {
+    pub m: Move,
+    can_castle: ForPlayer<CanCastleOnSide>,
+    en_passant: Option<BoardIndex>,
+    half_moves_since_pawn_or_capture: usize,
+    zobrist: ZobristHash,
+}
+
 #[derive(Copy, Clone)]
 pub struct Game {
     board: Board,
@@ -142,9 +264,10 @@ impl Default for Game {
             white: Default::default(),
             black: Default::default(),
         };
+        let castling_files = ForPlayer::new(STANDARD_CASTLING_FILES, STANDARD_CASTLING_FILES);
         let en_passant = None;
         Self {
-            board: Board::new(bitboards, player, can_castle, en_passant),
+            board: Board::new(bitboards, player, can_castle, castling_files, en_passant),
             half_moves_since_pawn_or_capture: 0,
             full_moves_total: 1,
         }
@@ -177,6 +300,9 @@ impl Game {
     pub fn can_castle(&self) -> &ForPlayer<CanCastleOnSide> {
         self.board.can_castle()
     }
+    pub fn castling_files(&self) -> &ForPlayer<CastlingFiles> {
+        self.board.castling_files()
+    }
     pub fn en_passant(&self) -> Option<BoardIndex> {
         *self.board.en_passant()
     }
@@ -194,8 +320,9 @@ impl Game {
             if position_str == "startpos" {
                 Game::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
             } else if position_str.starts_with("fen") {
+                // Illegal positions would crash the search later on
                 let fen_part = &position_str["fen".len()..].trim();
-                Game::from_fen(fen_part)
+                Game::from_fen_with(fen_part, FenStrictness::Strict)
             } else {
                 err_result(&format!("invalid position '{}'", position_str))
             }
@@ -219,49 +346,138 @@ impl Game {
             return Game::from_fen(&fen["fen ".len()..]);
         }
 
-        let definition = FenDefinition::from(fen)?;
-        Ok(Game {
+        Ok(Game::from_definition(FenDefinition::from(fen)?))
+    }
+
+    pub fn from_fen_with(fen: &str, strictness: FenStrictness) -> ErrorResult<Game> {
+        if fen == "startpos" {
+            return Game::from_fen(fen);
+        }
+        let fen = fen.strip_prefix("fen ").unwrap_or(fen);
+        Ok(Game::from_definition(FenDefinition::parse(fen, strictness)?))
+    }
+
+    fn from_definition(definition: FenDefinition) -> Game {
+        Game {
             board: Board::new(
                 definition.bitboards,
                 definition.player,
                 definition.can_castle,
+                definition.castling_files,
                 definition.en_passant,
             ),
             half_moves_since_pawn_or_capture: definition.half_moves_since_pawn_or_capture,
             full_moves_total: definition.full_moves_total,
-        })
+        }
     }
 
     pub fn move_from_str(&self, move_str: &str) -> Option<Move> {
-        let mut moves_buffer = vec![];
-        all_moves(
-            &mut moves_buffer,
-            self.player(),
-            &self,
-            MoveOptions::default(),
-        )
-        .unwrap();
+        let moves = self.legal_moves().unwrap();
+        moves
+            .into_iter()
+            .find(|m| m.to_uci() == move_str || m.to_uci_with(true) == move_str)
+    }
+
+    // The legal move for `m`, with the piece, capture and castling details a SimpleMove leaves out
+    pub fn legal_move(&self, m: SimpleMove) -> ErrorResult<Move> {
+        let moves = self.legal_moves_from(m.start)?;
+        match moves.into_iter().find(|legal| SimpleMove::from(legal) == m) {
+            Some(legal) => Ok(legal),
+            None => err_result(&format!("{} isn't legal in {}", m, self.to_fen())),
+        }
+    }
 
-        for &m in moves_buffer.iter() {
-            let mut next_game = *self;
-            next_game.make_move(m).unwrap();
+    // Plays out `line` from this position, writing each move in UCI notation. With `chess960`
+    // castling is written as the king taking its own rook.
+    pub fn line_to_uci(&self, line: &[SimpleMove], chess960: bool) -> ErrorResult<Vec<String>> {
+        let mut game = *self;
+        let mut ucis = vec![];
+        for &m in line {
+            let m = game.legal_move(m)?;
+            ucis.push(m.to_uci_with(chess960));
+            game.make_move(m)?;
+        }
+        Ok(ucis)
+    }
 
-            let king_index = next_game
-                .bitboards()
-                .index_of_piece(self.player(), Piece::King);
-            let illegal_move =
-                index_in_danger(self.player(), king_index, &next_game.bitboards()).unwrap();
+    // Fills `buffer` with the legal moves, reusing its allocation
+    pub fn legal_moves_into(&self, buffer: &mut Vec<Move>) -> ErrorResult<()> {
+        legal_moves(buffer, self.player(), self, MoveOptions::default())
+    }
 
-            if illegal_move {
-                continue;
-            }
+    pub fn legal_moves(&self) -> ErrorResult<Vec<Move>> {
+        let mut moves = vec![];
+        self.legal_moves_into(&mut moves)?;
+        Ok(moves)
+    }
+
+    // Fills `buffer` with the legal moves of the piece on `start`
+    pub fn legal_moves_from_into(
+        &self,
+        start: BoardIndex,
+        buffer: &mut Vec<Move>,
+    ) -> ErrorResult<()> {
+        let from = single_bitboard(start);
+        legal_moves_from(buffer, self.player(), self, MoveOptions::default(), from)
+    }
+
+    pub fn legal_moves_from(&self, start: BoardIndex) -> ErrorResult<Vec<Move>> {
+        let mut moves = vec![];
+        self.legal_moves_from_into(start, &mut moves)?;
+        Ok(moves)
+    }
+
+    pub fn legal_move_count(&self) -> ErrorResult<usize> {
+        legal_move_count(self.player(), self)
+    }
+
+    // Repetitions can't be seen from a single position, so they come from `history`, which
+    // should end with this position. Checkmate takes priority over the draw rules.
+    pub fn status(&self, history: &ZobristHistory) -> ErrorResult<GameStatus> {
+        if !self.has_legal_move()? {
+            let danger = Danger::from(self.player(), self.bitboards())?;
+            return Ok(if danger.check {
+                GameStatus::Checkmate {
+                    winner: self.player().other(),
+                }
+            } else {
+                GameStatus::Stalemate
+            });
+        }
+
+        if self.half_moves_since_pawn_or_capture >= 100 {
+            Ok(GameStatus::FiftyMoveRule)
+        } else if history.is_draw() == IsDraw::Yes {
+            Ok(GameStatus::ThreefoldRepetition)
+        } else if self.has_insufficient_material() {
+            Ok(GameStatus::InsufficientMaterial)
+        } else {
+            Ok(GameStatus::Ongoing)
+        }
+    }
 
-            if m.to_uci() == move_str {
-                return Some(m);
+    pub fn has_legal_move(&self) -> ErrorResult<bool> {
+        Ok(self.legal_move_count()? > 0)
+    }
+
+    // Neither side can mate with any sequence of moves: bare kings, a single minor piece, or
+    // only bishops that all stand on the same square color
+    pub fn has_insufficient_material(&self) -> bool {
+        let bitboards = self.bitboards();
+        let mut minors = 0;
+        let mut bishops: Bitboard = 0;
+        for player in [Player::White, Player::Black] {
+            let pieces = &bitboards.pieces[player];
+            if pieces[Piece::Pawn] | pieces[Piece::Rook] | pieces[Piece::Queen] != 0 {
+                return false;
             }
+            minors += (pieces[Piece::Knight] | pieces[Piece::Bishop]).count_ones();
+            bishops |= pieces[Piece::Bishop];
         }
 
-        None
+        const LIGHT_SQUARES: Bitboard = 0x55AA_55AA_55AA_55AA;
+        let same_color_bishops = bishops & LIGHT_SQUARES == 0 || bishops & !LIGHT_SQUARES == 0;
+        minors <= 1 || (minors == bishops.count_ones() && same_color_bishops)
     }
 
     pub fn move_legality(&self, m: &Move, previous_danger: &Danger) -> Legal {
@@ -306,7 +522,12 @@ impl Game {
             self.board.update_castling(
                 player,
                 castling_side,
-                castling_allowed_after_move(player, castling_side, m.start_index),
+                castling_allowed_after_move(
+                    player,
+                    castling_side,
+                    self.castling_files()[player],
+                    m.start_index,
+                ),
             );
         }
 
@@ -319,7 +540,12 @@ impl Game {
                 self.board.update_castling(
                     enemy,
                     castling_side,
-                    castling_allowed_after_move(enemy, castling_side, m.end_index),
+                    castling_allowed_after_move(
+                        enemy,
+                        castling_side,
+                        self.castling_files()[enemy],
+                        m.end_index,
+                    ),
                 );
             }
         }
@@ -331,7 +557,9 @@ impl Game {
                 return self.err(&format!("invalid move ({:?})", m));
             }
             MoveType::Quiet(q) => {
-                if self.bitboards().is_occupied(m.end_index) {
+                // In Chess960 the king may castle onto a square its rook is leaving
+                let castle = matches!(q, Quiet::Castle { .. });
+                if !castle && self.bitboards().is_occupied(m.end_index) {
                     return self.err(&format!(
                         "invalid quiet move ({:?}): end index {} is occupied",
                         m, m.end_index
@@ -367,10 +595,10 @@ impl Game {
                         }
 
                         self.board.clear_square(m.start_index, m.piece)?;
-                        self.board.set_square(m.end_index, m.piece)?;
-
                         self.board
                             .clear_square(rook_start, PlayerPiece::new(player, Piece::Rook))?;
+
+                        self.board.set_square(m.end_index, m.piece)?;
                         self.board
                             .set_square(rook_end, PlayerPiece::new(player, Piece::Rook))?;
                     }
@@ -427,13 +655,83 @@ impl Game {
         }
 
         self.board.update_player(enemy)?;
-        self.half_moves_since_pawn_or_capture += 1;
+        if m.piece.piece == Piece::Pawn || !m.is_quiet() {
+            self.half_moves_since_pawn_or_capture = 0;
+        } else {
+            self.half_moves_since_pawn_or_capture += 1;
+        }
         if self.player() == Player::White {
             self.full_moves_total += 1;
         }
 
         Ok(())
     }
+
+    // Like `make_move`, returning what `unmake_move` needs to take it back
+    pub fn make_move_with_undo(&mut self, m: Move) -> ErrorResult<Undo> {
+        let undo = Undo {
+            m,
+            can_castle: *self.can_castle(),
+            en_passant: self.en_passant(),
+            half_moves_since_pawn_or_capture: self.half_moves_since_pawn_or_capture,
+            zobrist: self.zobrist(),
+        };
+        self.make_move(m)?;
+        Ok(undo)
+    }
+
+    // Takes back the last move made. Undo records have to be unmade in reverse order.
+    pub fn unmake_move(&mut self, undo: &Undo) -> ErrorResult<()> {
+        let m = undo.m;
+        let player = m.piece.player;
+        if self.player() == player {
+            return self.err(&format!("can't unmake {:?}, it wasn't the last move", m));
+        }
+
+        let moved_piece = match m.promotion {
+            Some(promo_piece) => PlayerPiece::new(player, promo_piece),
+            None => m.piece,
+        };
+        if self.bitboards().piece_at_index(m.end_index) != Some(moved_piece) {
+            return self.err(&format!(
+                "can't unmake {:?}, piece isn't at end index {}",
+                m, m.end_index
+            ));
+        }
+        self.board.clear_square_unhashed(m.end_index, moved_piece);
+
+        match m.move_type {
+            MoveType::Invalid => {
+                return self.err(&format!("invalid move ({:?})", m));
+            }
+            MoveType::Quiet(Quiet::Castle {
+                rook_start,
+                rook_end,
+            }) => {
+                let rook = PlayerPiece::new(player, Piece::Rook);
+                self.board.clear_square_unhashed(rook_end, rook);
+                self.board.set_square_unhashed(rook_start, rook);
+            }
+            MoveType::Quiet(_) => {}
+            MoveType::Capture(Capture::EnPassant { taken_index }) => {
+                let taken_piece = PlayerPiece::new(player.other(), Piece::Pawn);
+                self.board.set_square_unhashed(taken_index, taken_piece);
+            }
+            MoveType::Capture(Capture::Take { taken_piece }) => {
+                self.board.set_square_unhashed(m.end_index, taken_piece);
+            }
+        }
+        self.board.set_square_unhashed(m.start_index, m.piece);
+
+        self.board
+            .restore(player, undo.can_castle, undo.en_passant, undo.zobrist);
+        self.half_moves_since_pawn_or_capture = undo.half_moves_since_pawn_or_capture;
+        if player == Player::Black {
+            self.full_moves_total -= 1;
+        }
+
+        Ok(())
+    }
 }
 
 #[test]
@@ -560,3 +858,136 @@ fn test_should_discard_invalid_simple_moves() {
         );
     }
 }
+
+#[test]
+fn test_position_rejects_illegal_fen() {
+    assert!(Game::from_position_uci("position fen 8/8/8/8/8/8/8/K7 w - - 0 1").is_err());
+    assert!(Game::from_position_uci("position fen 8/8/8/8/8/8/8/K6k w - - 0 1").is_ok());
+    assert!(Game::from_fen("8/8/8/8/8/8/8/K7 w - - 0 1").is_ok());
+}
+
+#[test]
+fn test_game_status() {
+    let status = |uci: &str| {
+        let (position, moves) = FenDefinition::split_uci(uci).unwrap();
+        let game = Game::from_position_and_moves(&position, &moves).unwrap();
+        let mut history = ZobristHistory::new();
+        history.update(position, &moves);
+        game.status(&history).unwrap()
+    };
+
+    assert_eq!(status("position startpos"), GameStatus::Ongoing);
+    let fools_mate = status("position startpos moves f2f3 e7e5 g2g4 d8h4");
+    assert_eq!(
+        fools_mate,
+        GameStatus::Checkmate {
+            winner: Player::Black
+        }
+    );
+    assert_eq!(fools_mate.result(), "0-1");
+    assert_eq!(
+        status("position fen 7k/5Q2/6K1/8/8/8/8/8 b - - 0 1"),
+        GameStatus::Stalemate
+    );
+    assert_eq!(
+        status("position startpos moves g1f3 g8f6 f3g1 f6g8 g1f3 g8f6 f3g1 f6g8"),
+        GameStatus::ThreefoldRepetition
+    );
+    assert_eq!(
+        status("position fen 8/8/3k4/8/8/8/3K4/7R w - - 99 80 moves h1h2"),
+        GameStatus::FiftyMoveRule
+    );
+    assert_eq!(
+        status("position fen 8/8/3k4/8/8/8/3K4/7R w - - 99 80 moves d2d3"),
+        GameStatus::FiftyMoveRule
+    );
+
+    for fen in [
+        "8/8/3k4/8/8/8/3K4/8 w - - 0 1",
+        "8/8/3k4/8/8/8/3K4/6N1 w - - 0 1",
+        "2b5/8/3k4/8/8/8/3K4/5B2 w - - 0 1",
+    ] {
+        let status = status(&format!("position fen {}", fen));
+        assert_eq!(status, GameStatus::InsufficientMaterial, "{}", fen);
+        assert_eq!(status.result(), "1/2-1/2");
+    }
+    for fen in [
+        "1b6/8/3k4/8/8/8/3K4/5B2 w - - 0 1",
+        "8/8/3k4/8/8/8/3K4/5BN1 w - - 0 1",
+        "8/8/3k4/8/8/8/3KP3/8 w - - 0 1",
+    ] {
+        assert_eq!(status(&format!("position fen {}", fen)), GameStatus::Ongoing, "{}", fen);
+    }
+}
+
+#[test]
+fn test_half_move_clock() {
+    let game = Game::from_position_uci("position startpos moves g1f3 g8f6 f3g1").unwrap();
+    assert_eq!(game.half_moves_since_pawn_or_capture, 3);
+    let game = Game::from_position_uci("position startpos moves g1f3 g8f6 e2e4").unwrap();
+    assert_eq!(game.half_moves_since_pawn_or_capture, 0);
+    let game = Game::from_position_uci("position startpos moves g1f3 e7e5 f3e5 b8c6").unwrap();
+    assert_eq!(game.half_moves_since_pawn_or_capture, 1);
+}
+
+#[test]
+fn test_legal_moves() {
+    let game = Game::from_fen("startpos").unwrap();
+    assert_eq!(game.legal_move_count().unwrap(), 20);
+    let knight_moves = game.legal_moves_from(BoardIndex::from_str("g1").unwrap()).unwrap();
+    let knight_moves: Vec<_> = knight_moves.iter().map(|m| m.to_uci()).collect();
+    assert_eq!(knight_moves, ["g1f3", "g1h3"]);
+    assert!(game
+        .legal_moves_from(BoardIndex::from_str("e4").unwrap())
+        .unwrap()
+        .is_empty());
+
+    // The knight is pinned and the king can't step onto the e-file
+    let game = Game::from_fen("4k3/4r3/8/8/8/8/4N3/4K3 w - - 0 1").unwrap();
+    assert!(game
+        .legal_moves_from(BoardIndex::from_str("e2").unwrap())
+        .unwrap()
+        .is_empty());
+    assert_eq!(game.legal_move_count().unwrap(), 4);
+
+    let mut buffer = Vec::with_capacity(256);
+    game.legal_moves_into(&mut buffer).unwrap();
+    assert_eq!(buffer, game.legal_moves().unwrap());
+    assert_eq!(buffer.capacity(), 256);
+}
+
+#[test]
+fn test_unmake_move() {
+    fn walk(game: &mut Game, depth: usize) {
+        if depth == 0 {
+            return;
+        }
+        for m in game.legal_moves().unwrap() {
+            let (fen, zobrist, full_moves) = (game.to_fen(), game.zobrist(), game.full_moves_total);
+
+            let undo = game.make_move_with_undo(m).unwrap();
+            walk(game, depth - 1);
+            game.unmake_move(&undo).unwrap();
+
+            assert_eq!(game.to_fen(), fen, "{}", m.to_uci());
+            assert_eq!(game.zobrist(), zobrist);
+            assert_eq!(game.full_moves_total, full_moves);
+        }
+    }
+
+    for fen in [
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1",
+        // Chess960, where the king can castle onto its rook's square
+        "r4kr1/8/8/8/8/8/8/RK5R w AHag - 0 1",
+    ] {
+        walk(&mut Game::from_fen(fen).unwrap(), 2);
+    }
+
+    // Undo records only apply to the position right after their move
+    let mut game = Game::from_fen("startpos").unwrap();
+    let undo = game.make_move_with_undo(game.move_from_str("e2e4").unwrap()).unwrap();
+    game.unmake_move(&undo).unwrap();
+    assert!(game.unmake_move(&undo).is_err());
+}
@@ -11,16 +11,21 @@ use std::{cell::RefCell, fmt::Display, iter, rc::Rc};
 use num_format::{Locale, ToFormattedString};
 
 use crate::{
-    alphabeta::{AlphaBetaOptions, AlphaBetaStack, LoopResult},
+    alphabeta::{AlphaBetaOptions, AlphaBetaStack, LoopResult, RootMoves},
     game::Game,
     helpers::{ErrorResult, Joinable},
     move_ordering::capture_sort,
     moves::Move,
+    score::{Contempt, Score, DEFAULT_CONTEMPT},
     simple_move::SimpleMove,
     transposition_table::TranspositionTable,
     zobrist::ZobristHistory,
 };
 
+// The transposition table keeps the remaining depth in a byte. Without a depth limit, a search
+// where every line quickly ends in a repetition finishes each depth instantly and would pass it.
+pub const MAX_SEARCH_DEPTH: usize = u8::MAX as usize;
+
 #[derive(Debug, Clone)]
 pub struct IterativeSearchOptions {
     pub skip_quiescence: bool,
@@ -29,6 +34,18 @@ pub struct IterativeSearchOptions {
     pub skip_sibling_beta_cutoff_sort: bool,
     pub skip_aspiration_window: bool,
     pub skip_null_move_pruning: bool,
+    pub skip_reverse_futility_pruning: bool,
+    pub skip_futility_pruning: bool,
+    pub skip_razoring: bool,
+    pub skip_late_move_pruning: bool,
+    pub root_moves: RootMoves,
+    pub score_root_moves: bool,
+    // Stop deepening once either limit is reached, keeping the last completed depth's result
+    pub max_depth: Option<usize>,
+    pub max_nodes: Option<usize>,
+    pub contempt: isize,
+    // Write castling in log lines as the king taking its own rook
+    pub chess960: bool,
     pub starting_history: ZobristHistory,
     pub transposition_table: Option<Rc<RefCell<TranspositionTable>>>,
 }
@@ -42,6 +59,16 @@ impl Default for IterativeSearchOptions {
             skip_sibling_beta_cutoff_sort: false,
             skip_aspiration_window: false,
             skip_null_move_pruning: false,
+            skip_reverse_futility_pruning: false,
+            skip_futility_pruning: false,
+            skip_razoring: false,
+            skip_late_move_pruning: false,
+            root_moves: RootMoves::default(),
+            score_root_moves: false,
+            max_depth: None,
+            max_nodes: None,
+            contempt: DEFAULT_CONTEMPT,
+            chess960: false,
             starting_history: ZobristHistory::new(),
             transposition_table: None,
         }
@@ -66,12 +93,39 @@ impl Display for IterativeSearchOptions {
         if !self.skip_null_move_pruning {
             options.push("null_move_pruning".to_string());
         }
+        if !self.skip_reverse_futility_pruning {
+            options.push("reverse_futility_pruning".to_string());
+        }
+        if !self.skip_futility_pruning {
+            options.push("futility_pruning".to_string());
+        }
+        if !self.skip_razoring {
+            options.push("razoring".to_string());
+        }
+        if !self.skip_late_move_pruning {
+            options.push("late_move_pruning".to_string());
+        }
         if !self.skip_aspiration_window {
             options.push("aspiration_window".to_string());
         }
         if self.transposition_table.is_some() {
             options.push("transposition_table".to_string());
         }
+        if self.root_moves.is_restricted() {
+            options.push("root_moves".to_string());
+        }
+        if self.score_root_moves {
+            options.push("score_root_moves".to_string());
+        }
+        if let Some(max_depth) = self.max_depth {
+            options.push(format!("max_depth {}", max_depth));
+        }
+        if let Some(max_nodes) = self.max_nodes {
+            options.push(format!("max_nodes {}", max_nodes));
+        }
+        if self.contempt != DEFAULT_CONTEMPT {
+            options.push(format!("contempt {}", self.contempt));
+        }
         write!(f, "{{ {} }}", options.join_vec(", "))
     }
 }
@@ -80,10 +134,14 @@ pub struct IterativeSearch {
     start_game: Game,
 
     best_variations_per_depth: Vec<Vec<SimpleMove>>,
+    best_score: Option<Score>,
+    root_move_scores: Vec<(SimpleMove, Score)>,
 
     options: IterativeSearchOptions,
 
     no_moves_found: bool,
+    limit_reached: bool,
+    num_nodes_searched: usize,
 }
 
 impl IterativeSearch {
@@ -100,6 +158,14 @@ impl IterativeSearch {
             skip_quiescence: options.skip_quiescence,
             skip_sibling_beta_cutoff_sort: options.skip_sibling_beta_cutoff_sort,
             skip_null_move_pruning: options.skip_null_move_pruning,
+            skip_reverse_futility_pruning: options.skip_reverse_futility_pruning,
+            skip_futility_pruning: options.skip_futility_pruning,
+            skip_razoring: options.skip_razoring,
+            skip_late_move_pruning: options.skip_late_move_pruning,
+            only_mates: false,
+            root_moves: options.root_moves.clone(),
+            score_root_moves: options.score_root_moves,
+            contempt: options.contempt,
             starting_history: options.starting_history.clone(),
             transposition_table: options.transposition_table.clone(),
 
@@ -111,8 +177,12 @@ impl IterativeSearch {
             alpha_beta: search,
             start_game: game,
             best_variations_per_depth: vec![],
+            best_score: None,
+            root_move_scores: vec![],
             options,
             no_moves_found: false,
+            limit_reached: false,
+            num_nodes_searched: 0,
         })
     }
 
@@ -133,8 +203,28 @@ impl IterativeSearch {
         }
     }
 
+    // Score of the best move at the last completed depth
+    pub fn best_score(&self) -> Option<Score> {
+        self.best_score
+    }
+
+    // Exact scores for each searched root move at the last completed depth, best first.
+    // Only filled in when `score_root_moves` is set.
+    pub fn root_move_scores(&self) -> &[(SimpleMove, Score)] {
+        &self.root_move_scores
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.num_nodes_searched + self.alpha_beta.num_nodes
+    }
+
+    // True once there's nothing left to search, or the depth/node limits were hit
+    pub fn done(&self) -> bool {
+        self.no_moves_found || self.limit_reached
+    }
+
     pub fn iterate<F: FnMut(&str)>(&mut self, log: &mut F) -> ErrorResult<()> {
-        if self.no_moves_found {
+        if self.done() {
             return Ok(());
         }
 
@@ -160,6 +250,7 @@ impl IterativeSearch {
                             ));
                             let mut alpha_beta_options = self.alpha_beta.options.clone();
                             alpha_beta_options.aspiration_window = None;
+                            self.num_nodes_searched += self.alpha_beta.num_nodes;
 
                             self.alpha_beta = AlphaBetaStack::with(
                                 self.start_game.clone(),
@@ -174,25 +265,49 @@ impl IterativeSearch {
                     }
                     Some((variation, score)) => {
                         let depth = self.alpha_beta.evaluate_at_depth;
+                        let ucis = self.start_game.line_to_uci(&variation, self.options.chess960)?;
                         log(&format!(
-                            "at depth {}: bestmove {} ponder {} ({}), β-cuts {}, evals {}",
+                            "at depth {}: bestmove {} ponder {} ({}), β-cuts {}, evals {}, pruned {}",
                             depth,
-                            variation[0].to_string(),
-                            variation[1..]
-                                .iter()
-                                .map(|m| m.to_string())
-                                .collect::<Vec<_>>()
-                                .join_vec(" "),
+                            ucis[0],
+                            ucis[1..].join(" "),
                             score,
                             self.alpha_beta.num_beta_cutoffs,
                             self.alpha_beta.num_evaluations,
+                            self.alpha_beta.num_pruned,
                         ));
 
                         self.best_variations_per_depth.push(variation);
+                        self.best_score = Some(score);
+                        self.num_nodes_searched += self.alpha_beta.num_nodes;
+
+                        if depth >= MAX_SEARCH_DEPTH
+                            || self.options.max_depth.is_some_and(|max_depth| depth >= max_depth)
+                        {
+                            self.limit_reached = true;
+                        }
+
+                        if self.options.score_root_moves {
+                            let player = self.start_game.player();
+                            let mut scores = self.alpha_beta.root_move_scores.clone();
+                            scores.sort_by(|(_, a), (_, b)| Score::compare(player, *b, *a).ordering());
+                            let chess960 = self.options.chess960;
+                            let mut described = vec![];
+                            for (m, score) in &scores {
+                                let uci = self.start_game.line_to_uci(&[*m], chess960)?;
+                                described.push(format!("{} ({})", uci[0], score));
+                            }
+                            log(&format!(
+                                "at depth {}: root move scores {}",
+                                depth,
+                                described.join(", "),
+                            ));
+                            self.root_move_scores = scores;
+                        }
 
                         let mut alpha_beta_options = self.alpha_beta.options.clone();
                         alpha_beta_options.aspiration_window =
-                            if self.options.skip_aspiration_window {
+                            if self.options.skip_aspiration_window || self.options.score_root_moves {
                                 None
                             } else {
                                 Some(score.aspiration_window(self.start_game.player()))
@@ -206,7 +321,19 @@ impl IterativeSearch {
                     }
                 }
             }
-            LoopResult::Continue => {}
+            LoopResult::Continue => {
+                // Always finish the first depth so that we have a move to play
+                if let Some(max_nodes) = self.options.max_nodes {
+                    if self.num_nodes() >= max_nodes && !self.best_variations_per_depth.is_empty() {
+                        log(&format!(
+                            "node limit reached at depth {}, nodes {}",
+                            self.alpha_beta.evaluate_at_depth,
+                            self.num_nodes()
+                        ));
+                        self.limit_reached = true;
+                    }
+                }
+            }
         }
 
         Ok(())
@@ -248,6 +375,10 @@ fn test_iterative_deepening_for_depth() {
         skip_capture_sort: true,
         skip_sibling_beta_cutoff_sort: true,
         skip_null_move_pruning: true,
+        skip_reverse_futility_pruning: true,
+        skip_futility_pruning: true,
+        skip_razoring: true,
+        skip_late_move_pruning: true,
         transposition_table: None,
         ..IterativeSearchOptions::default()
     };
@@ -283,6 +414,22 @@ fn test_iterative_deepening_for_depth() {
             skip_null_move_pruning: false,
             ..skip_all.clone()
         },
+        IterativeSearchOptions {
+            skip_reverse_futility_pruning: false,
+            ..skip_all.clone()
+        },
+        IterativeSearchOptions {
+            skip_futility_pruning: false,
+            ..skip_all.clone()
+        },
+        IterativeSearchOptions {
+            skip_razoring: false,
+            ..skip_all.clone()
+        },
+        IterativeSearchOptions {
+            skip_late_move_pruning: false,
+            ..skip_all.clone()
+        },
         IterativeSearchOptions {
             transposition_table: Some(Rc::new(RefCell::new(TranspositionTable::new()))),
             ..IterativeSearchOptions::default()
@@ -415,3 +562,152 @@ fn test_iterative_deepening_transposition_table() {
         total_time.as_millis().to_formatted_string(&Locale::en)
     );
 }
+
+// Positions with a single tactical solution that the full search finds by depth 4
+const TACTICAL_SUITE: [(&str, &str); 7] = [
+    ("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", "d1d8"),
+    ("3q4/6k1/8/8/5N2/8/8/6K1 w - - 0 1", "f4e6"),
+    ("5rk1/1ppb3p/p1pb4/6q1/3P1p1r/2P1R2P/PP1BQ1P1/5RKN w - - 0 1", "e3g3"),
+    ("r1bq2rk/pp3pbp/2p1p1pQ/7P/3P4/2PB1N2/PP3PPR/2KR4 w - - 0 1", "h6h7"),
+    ("5k2/6pp/p1qN4/1p1p4/3P4/2PKP2Q/PP3r2/3R4 b - - 0 1", "c6c4"),
+    ("2br2k1/2q3rn/p2NppQ1/2p1P3/Pp5R/4P3/1P3PPP/3R2K1 w - - 0 1", "h4h7"),
+    ("r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 0", "d5f6"),
+];
+
+#[test]
+fn test_forward_pruning_tactical_suite() {
+    let max_depth = 4;
+
+    let no_pruning = IterativeSearchOptions {
+        skip_reverse_futility_pruning: true,
+        skip_futility_pruning: true,
+        skip_razoring: true,
+        skip_late_move_pruning: true,
+        ..IterativeSearchOptions::default()
+    };
+
+    for options in [IterativeSearchOptions::default(), no_pruning] {
+        println!("{}", options);
+
+        for (fen, expected) in TACTICAL_SUITE {
+            let mut search =
+                IterativeSearch::new(Game::from_fen(fen).unwrap(), options.clone()).unwrap();
+
+            let start_time = std::time::Instant::now();
+            while search.max_depth() <= max_depth {
+                search.iterate(&mut |_| {}).unwrap();
+            }
+
+            let (bestmove, _) = search.bestmove().unwrap();
+            println!(
+                "{:>5} ms {} {}",
+                start_time.elapsed().as_millis(),
+                bestmove,
+                fen
+            );
+            assert_eq!(bestmove.to_string(), expected, "failed tactic for {}", fen);
+        }
+    }
+}
+
+#[cfg(test)]
+fn search_to_depth(fen: &str, max_depth: usize, options: IterativeSearchOptions) -> IterativeSearch {
+    let mut search = IterativeSearch::new(Game::from_fen(fen).unwrap(), options).unwrap();
+    while search.max_depth() <= max_depth {
+        search.iterate(&mut |line| println!("{}", line)).unwrap();
+    }
+    search
+}
+
+#[test]
+fn test_root_moves_exclude_best_move() {
+    let fen = "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1";
+    let mate = SimpleMove::from_str("d1d8").unwrap();
+
+    let search = search_to_depth(fen, 3, IterativeSearchOptions::default());
+    assert_eq!(search.bestmove().unwrap().0, mate);
+
+    let search = search_to_depth(
+        fen,
+        3,
+        IterativeSearchOptions {
+            root_moves: RootMoves {
+                include: None,
+                exclude: vec![mate],
+            },
+            ..IterativeSearchOptions::default()
+        },
+    );
+    assert_ne!(search.bestmove().unwrap().0, mate);
+}
+
+#[test]
+fn test_score_root_moves() {
+    let fen = "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1";
+    let include: Vec<SimpleMove> = ["f2f3", "d1d8", "d1d7"]
+        .iter()
+        .map(|m| SimpleMove::from_str(m).unwrap())
+        .collect();
+
+    let search = search_to_depth(
+        fen,
+        3,
+        IterativeSearchOptions {
+            root_moves: RootMoves {
+                include: Some(include.clone()),
+                exclude: vec![],
+            },
+            score_root_moves: true,
+            ..IterativeSearchOptions::default()
+        },
+    );
+
+    let scores = search.root_move_scores();
+    assert_eq!(scores.len(), 3);
+    assert_eq!(scores[0], (include[1], Score::WinInN(crate::types::Player::White, 1)));
+    for (m, score) in &scores[1..] {
+        assert!(include.contains(m));
+        assert!(matches!(score, Score::Centipawns(..)));
+    }
+}
+
+#[test]
+fn test_contempt_stalemate() {
+    // Qg6 and Qf7 both stalemate
+    let fen = "7k/8/8/5Q2/8/8/8/K7 w - - 0 1";
+    let stalemates = ["f5g6", "f5f7"];
+
+    let search = search_to_depth(fen, 3, IterativeSearchOptions::default());
+    let (bestmove, _) = search.bestmove().unwrap();
+    assert!(!stalemates.contains(&bestmove.to_string().as_str()));
+
+    // Desperate enough for a draw to give up a queen
+    let search = search_to_depth(
+        fen,
+        3,
+        IterativeSearchOptions {
+            contempt: -2000,
+            ..IterativeSearchOptions::default()
+        },
+    );
+    let (bestmove, _) = search.bestmove().unwrap();
+    assert!(stalemates.contains(&bestmove.to_string().as_str()));
+
+    let search = search_to_depth(
+        fen,
+        3,
+        IterativeSearchOptions {
+            root_moves: RootMoves {
+                include: Some(vec![SimpleMove::from_str("f5g6").unwrap()]),
+                exclude: vec![],
+            },
+            score_root_moves: true,
+            contempt: 30,
+            ..IterativeSearchOptions::default()
+        },
+    );
+    assert_eq!(
+        search.root_move_scores()[0].1,
+        Score::DrawInN(Contempt::new(crate::types::Player::White, 30), 1)
+    );
+}
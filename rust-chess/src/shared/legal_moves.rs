@@ -0,0 +1,457 @@
+/*
+Legal move generation.
+
+`all_moves` generates pseudo-legal moves, which have to be made before the king's safety can be
+checked. Here the restrictions are worked out once per position, from the king's point of view:
+
+- In double check only the king can move. In single check every other move has to capture the
+  checker or block between it and the king.
+- A pinned piece can only move along the line through its king and the pinner.
+- The king can't step onto an attacked square. Attacks are computed with the king removed, so it
+  can't retreat along the ray of a slider that checks it.
+
+En passant removes two pieces from a rank at once, which can expose the king in ways a pin mask
+doesn't catch, and a Chess960 castle moves the rook out of the way of the king's destination. Both
+are rare, so those moves are still made and tested.
+*/
+
+use crate::{
+    bitboard::{
+        each_index_of_one, line_through, moves_bb_for_piece_and_blockers,
+        pawn_capture_directions_for_player, pawn_push_direction_for_player, pre_move_mask,
+        rotate_toward_index_63, single_bitboard, squares_between, starting_pawns_mask, Bitboard,
+        Bitboards, BoardIndex, WalkType, PAWN_PROMOTION_BITBOARD, STANDARD_CASTLING_FILES,
+    },
+    game::Game,
+    helpers::ErrorResult,
+    moves::{
+        castling_moves, en_passant_move, index_in_danger, jumping_bitboard, pawn_attacking_bb,
+        pawn_moves, potential_bb_to_moves, walk_potential_bb, JumpingPiece, Move, MoveOptions,
+        OnlyCaptures, OnlyQueenPromotion, OnlyQuiets,
+    },
+    types::{Piece, Player, PlayerPiece},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LegalMasks {
+    pub player: Player,
+    pub king: BoardIndex,
+    pub checkers: Bitboard,
+    // Where pieces other than the king may move to: anywhere when not in check
+    pub check_mask: Bitboard,
+    pub pinned: Bitboard,
+}
+
+// Enemy pieces attacking `target`, with sliders blocked by `occupied`
+pub fn attackers(
+    target: BoardIndex,
+    player: Player,
+    occupied: Bitboard,
+    bitboards: &Bitboards,
+) -> Bitboard {
+    let enemy_pieces = &bitboards.pieces[player.other()];
+    let target_bb = single_bitboard(target);
+
+    let mut attackers = jumping_bitboard(target, JumpingPiece::Knight) & enemy_pieces[Piece::Knight];
+    attackers |= jumping_bitboard(target, JumpingPiece::King) & enemy_pieces[Piece::King];
+    for capture_dir in pawn_capture_directions_for_player(player) {
+        attackers |= pawn_attacking_bb(target_bb, *capture_dir) & enemy_pieces[Piece::Pawn];
+    }
+    for (_, sliders) in slider_attackers(target, occupied, bitboards, player.other()) {
+        attackers |= sliders;
+    }
+
+    attackers
+}
+
+fn slider_attackers(
+    target: BoardIndex,
+    occupied: Bitboard,
+    bitboards: &Bitboards,
+    enemy: Player,
+) -> [(WalkType, Bitboard); 2] {
+    let enemy_pieces = &bitboards.pieces[enemy];
+    let queens = enemy_pieces[Piece::Queen];
+    [
+        (WalkType::Bishop, enemy_pieces[Piece::Bishop] | queens),
+        (WalkType::Rook, enemy_pieces[Piece::Rook] | queens),
+    ]
+    .map(|(walk_type, sliders)| {
+        let rays = moves_bb_for_piece_and_blockers(target, walk_type, occupied);
+        (walk_type, rays & sliders)
+    })
+}
+
+impl LegalMasks {
+    pub fn from(player: Player, bitboards: &Bitboards) -> LegalMasks {
+        let enemy = player.other();
+        let king = bitboards.index_of_piece(player, Piece::King);
+        let occupied = bitboards.all_occupied();
+
+        let checkers = attackers(king, player, occupied, bitboards);
+
+        // Looking through our own pieces, a slider with exactly one of ours in between pins it
+        let mut pinned = 0;
+        let enemy_occupied = bitboards.occupied[enemy];
+        for (_, pinners) in slider_attackers(king, enemy_occupied, bitboards, enemy) {
+            for pinner in each_index_of_one(pinners & !checkers) {
+                let blockers = squares_between(king, pinner) & occupied;
+                if blockers.count_ones() == 1 && blockers & bitboards.occupied[player] != 0 {
+                    pinned |= blockers;
+                }
+            }
+        }
+
+        let check_mask = match checkers.count_ones() {
+            0 => !0,
+            1 => {
+                let checker = BoardIndex::from(checkers.trailing_zeros() as usize);
+                checkers | squares_between(king, checker)
+            }
+            _ => 0,
+        };
+
+        LegalMasks {
+            player,
+            king,
+            checkers,
+            check_mask,
+            pinned,
+        }
+    }
+
+    pub fn check(&self) -> bool {
+        self.checkers != 0
+    }
+
+    pub fn double_check(&self) -> bool {
+        self.checkers.count_ones() > 1
+    }
+
+    // Squares the piece on `index` may move to without exposing its king
+    pub fn allowed(&self, index: BoardIndex) -> Bitboard {
+        if self.pinned & single_bitboard(index) != 0 {
+            self.check_mask & line_through(self.king, index)
+        } else {
+            self.check_mask
+        }
+    }
+
+    // Squares the king can step to. Attacks are tested with the king off the board, so it
+    // can't retreat along the ray of a slider that checks it.
+    pub fn king_moves(&self, bitboards: &Bitboards) -> Bitboard {
+        let occupied = bitboards.all_occupied() & !single_bitboard(self.king);
+        let candidates =
+            jumping_bitboard(self.king, JumpingPiece::King) & !bitboards.occupied[self.player];
+
+        let mut safe = 0;
+        for index in each_index_of_one(candidates) {
+            if attackers(index, self.player, occupied, bitboards) == 0 {
+                safe |= single_bitboard(index);
+            }
+        }
+        safe
+    }
+}
+
+fn retain_king_safe(
+    buffer: &mut Vec<Move>,
+    from: usize,
+    player: Player,
+    game: &Game,
+) -> ErrorResult<()> {
+    let mut kept = from;
+    for i in from..buffer.len() {
+        let m = buffer[i];
+        let mut next_game = *game;
+        next_game.make_move(m)?;
+        let king = next_game.bitboards().index_of_piece(player, Piece::King);
+        if !index_in_danger(player, king, next_game.bitboards())? {
+            buffer[kept] = m;
+            kept += 1;
+        }
+    }
+    buffer.truncate(kept);
+    Ok(())
+}
+
+// Like `all_moves`, in the same order, but only legal moves
+pub fn legal_moves(
+    buffer: &mut Vec<Move>,
+    player: Player,
+    game: &Game,
+    options: MoveOptions,
+) -> ErrorResult<()> {
+    legal_moves_from(buffer, player, game, options, !0)
+}
+
+// Like `legal_moves`, but only for the pieces standing on `from`
+pub fn legal_moves_from(
+    buffer: &mut Vec<Move>,
+    player: Player,
+    game: &Game,
+    options: MoveOptions,
+    from: Bitboard,
+) -> ErrorResult<()> {
+    let bitboards = game.bitboards();
+    let pieces = &bitboards.pieces[player];
+    let masks = LegalMasks::from(player, bitboards);
+    let king_moves = single_bitboard(masks.king) & from != 0;
+    let only_quiets = options.only_quiets == OnlyQuiets::Yes;
+    // Quiet moves never land on an enemy piece
+    let targets = match only_quiets {
+        true => !bitboards.occupied[player.other()],
+        false => !0,
+    };
+    buffer.clear();
+
+    if !masks.double_check() {
+        if pieces[Piece::Pawn] & from != 0 {
+            pawn_moves(
+                buffer,
+                player,
+                bitboards,
+                options.only_captures,
+                options.only_queen_promotion,
+            )?;
+            if masks.check() || masks.pinned != 0 || only_quiets {
+                buffer.retain(|m| {
+                    masks.allowed(m.start_index) & targets & single_bitboard(m.end_index) != 0
+                });
+            }
+        }
+
+        for index in each_index_of_one(pieces[Piece::Knight] & !masks.pinned & from) {
+            potential_bb_to_moves(
+                buffer,
+                PlayerPiece::new(player, Piece::Knight),
+                index,
+                jumping_bitboard(index, JumpingPiece::Knight) & masks.check_mask & targets,
+                bitboards,
+                options.only_captures,
+            )?;
+        }
+    }
+
+    if king_moves {
+        potential_bb_to_moves(
+            buffer,
+            PlayerPiece::new(player, Piece::King),
+            masks.king,
+            masks.king_moves(bitboards) & targets,
+            bitboards,
+            options.only_captures,
+        )?;
+    }
+
+    if masks.double_check() {
+        return Ok(());
+    }
+
+    for piece in [Piece::Bishop, Piece::Rook, Piece::Queen] {
+        for index in each_index_of_one(pieces[piece] & from) {
+            let potential = walk_potential_bb(index, bitboards.all_occupied(), piece)?;
+            potential_bb_to_moves(
+                buffer,
+                PlayerPiece::new(player, piece),
+                index,
+                potential & masks.allowed(index) & targets,
+                bitboards,
+                options.only_captures,
+            )?;
+        }
+    }
+
+    // Standard castling only needs the squares the king crosses to be safe, which
+    // `castling_moves` checks already
+    let chess960 = game.castling_files()[player] != STANDARD_CASTLING_FILES;
+    let mut unchecked = buffer.len();
+    if options.only_captures == OnlyCaptures::No && !masks.check() && king_moves {
+        castling_moves(buffer, player, game)?;
+        if !chess960 {
+            unchecked = buffer.len();
+        }
+    }
+    if pieces[Piece::Pawn] & from != 0 && !only_quiets {
+        en_passant_move(buffer, player, bitboards, game.en_passant())?;
+    }
+    retain_king_safe(buffer, unchecked, player, game)?;
+
+    // Pawn moves are generated all at once
+    if from != !0 {
+        buffer.retain(|m| single_bitboard(m.start_index) & from != 0);
+    }
+
+    Ok(())
+}
+
+// Pawn moves from `pawns` that end on `allowed`, counting each promotion piece separately
+fn pawn_move_count(
+    player: Player,
+    pawns: Bitboard,
+    allowed: Bitboard,
+    bitboards: &Bitboards,
+) -> u32 {
+    let empty = !bitboards.all_occupied();
+    let push_dir = pawn_push_direction_for_player(player);
+    let push = |bb: Bitboard| {
+        rotate_toward_index_63(bb & pre_move_mask(push_dir), push_dir.offset()) & empty
+    };
+
+    let [left, right] = pawn_capture_directions_for_player(player)
+        .map(|dir| pawn_attacking_bb(pawns, dir) & bitboards.occupied[player.other()]);
+    let targets = [
+        push(pawns),
+        push(push(pawns & *starting_pawns_mask(player))),
+        left,
+        right,
+    ];
+
+    let promotions = OnlyQueenPromotion::No.pieces().len() as u32;
+    targets
+        .iter()
+        .map(|&bb| bb & allowed)
+        .map(|bb| {
+            (bb & !*PAWN_PROMOTION_BITBOARD).count_ones()
+                + promotions * (bb & *PAWN_PROMOTION_BITBOARD).count_ones()
+        })
+        .sum()
+}
+
+// The number of moves `legal_moves` would generate, counted on bitboards. Only castling and
+// en passant are generated, into a buffer that stays unallocated when there are none.
+pub fn legal_move_count(player: Player, game: &Game) -> ErrorResult<usize> {
+    let bitboards = game.bitboards();
+    let pieces = &bitboards.pieces[player];
+    let own = bitboards.occupied[player];
+    let masks = LegalMasks::from(player, bitboards);
+
+    let mut count = masks.king_moves(bitboards).count_ones();
+    if masks.double_check() {
+        return Ok(count as usize);
+    }
+
+    let pawns = pieces[Piece::Pawn];
+    count += pawn_move_count(player, pawns & !masks.pinned, masks.check_mask, bitboards);
+    for index in each_index_of_one(pawns & masks.pinned) {
+        count += pawn_move_count(player, single_bitboard(index), masks.allowed(index), bitboards);
+    }
+
+    for index in each_index_of_one(pieces[Piece::Knight] & !masks.pinned) {
+        let potential = jumping_bitboard(index, JumpingPiece::Knight) & masks.check_mask;
+        count += (potential & !own).count_ones();
+    }
+    for piece in [Piece::Bishop, Piece::Rook, Piece::Queen] {
+        for index in each_index_of_one(pieces[piece]) {
+            let potential = walk_potential_bb(index, bitboards.all_occupied(), piece)?;
+            count += (potential & masks.allowed(index) & !own).count_ones();
+        }
+    }
+
+    let chess960 = game.castling_files()[player] != STANDARD_CASTLING_FILES;
+    let mut special = vec![];
+    if !masks.check() {
+        castling_moves(&mut special, player, game)?;
+        if !chess960 {
+            count += special.len() as u32;
+            special.clear();
+        }
+    }
+    en_passant_move(&mut special, player, bitboards, game.en_passant())?;
+    retain_king_safe(&mut special, 0, player, game)?;
+
+    Ok(count as usize + special.len())
+}
+
+#[cfg(test)]
+fn assert_matches_pseudo_legal(fen: &str) {
+    use crate::{danger::Danger, game::Legal, moves::all_moves};
+    use std::collections::HashSet;
+
+    let game = Game::from_fen(fen).unwrap();
+    let danger = Danger::from(game.player(), game.bitboards()).unwrap();
+
+    let mut pseudo_legal = vec![];
+    all_moves(&mut pseudo_legal, game.player(), &game, MoveOptions::default()).unwrap();
+    let expected: HashSet<String> = pseudo_legal
+        .into_iter()
+        .filter(|m| {
+            let mut next_game = game;
+            next_game.make_move(*m).unwrap();
+            next_game.move_legality(m, &danger) == Legal::Yes
+        })
+        .map(|m| m.to_uci())
+        .collect();
+
+    let mut legal = vec![];
+    legal_moves(&mut legal, game.player(), &game, MoveOptions::default()).unwrap();
+    let actual: HashSet<String> = legal.iter().map(|m| m.to_uci()).collect();
+
+    assert_eq!(actual.len(), legal.len(), "duplicate moves for {}", fen);
+    assert_eq!(actual, expected, "{}", fen);
+    assert_eq!(legal_move_count(game.player(), &game).unwrap(), legal.len(), "{}", fen);
+
+    let quiet_options = MoveOptions {
+        only_quiets: OnlyQuiets::Yes,
+        ..MoveOptions::default()
+    };
+    let mut quiets = vec![];
+    legal_moves(&mut quiets, game.player(), &game, quiet_options).unwrap();
+    let expected: Vec<_> = legal.iter().filter(|m| m.is_quiet()).collect();
+    assert_eq!(quiets.iter().collect::<Vec<_>>(), expected, "{}", fen);
+
+    let mut from_square = vec![];
+    for i in 0..64 {
+        let from = single_bitboard(BoardIndex::from(i));
+        legal_moves_from(&mut from_square, game.player(), &game, MoveOptions::default(), from)
+            .unwrap();
+        let expected: Vec<_> = legal.iter().filter(|m| m.start_index.i == i).collect();
+        assert_eq!(from_square.iter().collect::<Vec<_>>(), expected, "{} from {}", fen, i);
+    }
+}
+
+#[test]
+fn test_legal_masks() {
+    use crate::bitboard::bitboard_with_file_rank_strs_set;
+
+    let index = |s: &str| BoardIndex::from_str(s).unwrap();
+
+    // Knight on d5 and bishop on e4 are pinned, the queen on a3 checks
+    let bitboards = Bitboards::from_fen("2k5/3r4/6b1/1N1N4/4N3/q2K4/8/8").unwrap();
+    let masks = LegalMasks::from(Player::White, &bitboards);
+    assert_eq!(masks.checkers, single_bitboard(index("a3")));
+    assert_eq!(
+        masks.pinned,
+        single_bitboard(index("d5")) | single_bitboard(index("e4"))
+    );
+    assert_eq!(
+        masks.check_mask,
+        single_bitboard(index("a3")) | single_bitboard(index("b3")) | single_bitboard(index("c3"))
+    );
+    // The king can't retreat along the checking rank
+    assert_eq!(
+        masks.king_moves(&bitboards),
+        bitboard_with_file_rank_strs_set(&["c2", "c4", "d2", "d4", "e2"])
+    );
+}
+
+#[test]
+fn test_legal_moves_match_pseudo_legal() {
+    for fen in [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        // En passant that would expose the king along the rank
+        "8/8/8/K2pP2r/8/8/8/7k w - d6 0 1",
+        // Double check
+        "4k3/8/8/8/8/5n2/8/4K2r w - - 0 1",
+        // Check by a pawn that can be taken en passant
+        "8/8/8/2k5/3Pp3/8/8/4K3 b - d3 0 1",
+        // Pinned pawn that can only capture its pinner
+        "4k3/8/8/8/8/2b5/3P4/4K3 w - - 0 1",
+    ] {
+        assert_matches_pseudo_legal(fen);
+    }
+}
@@ -0,0 +1,272 @@
+use std::fmt::Display;
+
+use crate::{
+    alphabeta::{AlphaBetaOptions, AlphaBetaStack, LoopResult},
+    game::Game,
+    helpers::{err_result, ErrorResult, Joinable},
+    move_ordering::capture_sort,
+    moves::Move,
+    proof_number::{ProofNumberSearch, ProofResult},
+    score::Score,
+    simple_move::SimpleMove,
+    zobrist::ZobristHistory,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MateResult {
+    // The forced line, starting with the searching player's move and ending in mate
+    Mate(Vec<SimpleMove>),
+
+    // Every line was searched and none of them mate in time
+    NoMate,
+
+    // The proof number search ran out of nodes before it could decide
+    Unknown,
+}
+
+impl MateResult {
+    pub fn mate_in(&self) -> Option<usize> {
+        match self {
+            MateResult::Mate(variation) => Some(variation.len().div_ceil(2)),
+            _ => None,
+        }
+    }
+}
+
+impl Display for MateResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MateResult::Mate(variation) => write!(
+                f,
+                "mate in {}: {}",
+                self.mate_in().unwrap(),
+                variation.join_vec(" ")
+            ),
+            MateResult::NoMate => write!(f, "no mate"),
+            MateResult::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+// Alpha-beta has to search every line to full depth, which stops being practical past mate in 3
+pub const PROOF_NUMBER_SEARCH_MIN_MOVES: usize = 4;
+
+#[derive(Debug, Clone)]
+pub struct MateSearchOptions {
+    // Alpha-beta finds the shortest mate, proof number search finds deep mates much faster
+    pub use_proof_number_search: bool,
+    pub max_proof_number_nodes: usize,
+    pub starting_history: ZobristHistory,
+    // Write castling in log lines as the king taking its own rook
+    pub chess960: bool,
+}
+
+impl Default for MateSearchOptions {
+    fn default() -> Self {
+        Self {
+            use_proof_number_search: false,
+            max_proof_number_nodes: 1_000_000,
+            starting_history: ZobristHistory::new(),
+            chess960: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum MateSearchMethod {
+    AlphaBeta(AlphaBetaStack),
+    ProofNumber(ProofNumberSearch),
+}
+
+pub struct MateSearch {
+    start_game: Game,
+    max_moves: usize,
+    options: MateSearchOptions,
+
+    method: MateSearchMethod,
+    result: Option<MateResult>,
+}
+
+fn mate_only_alpha_beta(
+    game: Game,
+    moves: usize,
+    options: &MateSearchOptions,
+) -> ErrorResult<AlphaBetaStack> {
+    let player = game.player();
+
+    // The defender gets one extra ply so that mate on the attacker's last move is seen.
+    // Alpha starts above every non-mate score, so the root only returns a move if it mates.
+    AlphaBetaStack::with(
+        game,
+        2 * moves,
+        AlphaBetaOptions {
+            skip_quiescence: true,
+            skip_null_move_pruning: true,
+            skip_reverse_futility_pruning: true,
+            skip_futility_pruning: true,
+            skip_razoring: true,
+            skip_late_move_pruning: true,
+            only_mates: true,
+            aspiration_window: Some((
+                Score::Centipawns(player, isize::MAX),
+                Score::WinInN(player, 0),
+            )),
+            starting_history: options.starting_history.clone(),
+            ..AlphaBetaOptions::default()
+        },
+    )
+}
+
+impl MateSearch {
+    pub fn new(game: Game, max_moves: usize, options: MateSearchOptions) -> ErrorResult<Self> {
+        if max_moves == 0 {
+            return err_result("mate search needs at least one move");
+        }
+
+        let method = if options.use_proof_number_search {
+            MateSearchMethod::ProofNumber(ProofNumberSearch::new(
+                game,
+                max_moves,
+                options.max_proof_number_nodes,
+                options.starting_history.clone(),
+            )?)
+        } else {
+            MateSearchMethod::AlphaBeta(mate_only_alpha_beta(game, 1, &options)?)
+        };
+
+        Ok(Self {
+            start_game: game,
+            max_moves,
+            options,
+            method,
+            result: None,
+        })
+    }
+
+    pub fn result(&self) -> Option<&MateResult> {
+        self.result.as_ref()
+    }
+
+    pub fn iterate<F: FnMut(&str)>(&mut self, log: &mut F) -> ErrorResult<LoopResult> {
+        if self.result.is_some() {
+            return Ok(LoopResult::Done);
+        }
+
+        match &mut self.method {
+            MateSearchMethod::AlphaBeta(alpha_beta) => {
+                let sorter = |_: &Game, moves: &mut [Move]| capture_sort(moves);
+                if alpha_beta.iterate(sorter)? == LoopResult::Continue {
+                    return Ok(LoopResult::Continue);
+                }
+
+                // Deepen one move at a time so that the first mate we find is the shortest
+                let moves = alpha_beta.evaluate_at_depth / 2;
+                match alpha_beta.bestmove() {
+                    Some((variation, _)) => {
+                        self.result = Some(MateResult::Mate(variation));
+                    }
+                    None if moves >= self.max_moves => {
+                        self.result = Some(MateResult::NoMate);
+                    }
+                    None => {
+                        log(&format!(
+                            "no mate in {}, β-cuts {}, evals {}",
+                            moves, alpha_beta.num_beta_cutoffs, alpha_beta.num_evaluations
+                        ));
+                        *alpha_beta =
+                            mate_only_alpha_beta(self.start_game, moves + 1, &self.options)?;
+                        return Ok(LoopResult::Continue);
+                    }
+                }
+            }
+            MateSearchMethod::ProofNumber(proof_number) => {
+                if proof_number.iterate()? == LoopResult::Continue {
+                    return Ok(LoopResult::Continue);
+                }
+
+                log(&format!(
+                    "proof number search: expanded {}, nodes {}",
+                    proof_number.num_expanded,
+                    proof_number.num_nodes(),
+                ));
+                self.result = Some(match proof_number.result() {
+                    ProofResult::Proven => {
+                        MateResult::Mate(proof_number.mating_variation().unwrap())
+                    }
+                    ProofResult::Disproven => MateResult::NoMate,
+                    ProofResult::Unknown => MateResult::Unknown,
+                });
+            }
+        }
+
+        let result = match self.result.as_ref().unwrap() {
+            result @ MateResult::Mate(variation) => format!(
+                "mate in {}: {}",
+                result.mate_in().unwrap(),
+                self.start_game.line_to_uci(variation, self.options.chess960)?.join(" ")
+            ),
+            result => result.to_string(),
+        };
+        log(&format!("{} (searched up to mate in {})", result, self.max_moves));
+        Ok(LoopResult::Done)
+    }
+}
+
+#[cfg(test)]
+fn run_mate_search(fen: &str, max_moves: usize, use_proof_number_search: bool) -> MateResult {
+    let mut search = MateSearch::new(
+        Game::from_fen(fen).unwrap(),
+        max_moves,
+        MateSearchOptions {
+            use_proof_number_search,
+            ..MateSearchOptions::default()
+        },
+    )
+    .unwrap();
+
+    let start_time = std::time::Instant::now();
+    while search.iterate(&mut |line| println!("{}", line)).unwrap() == LoopResult::Continue {}
+    println!("{} ms", start_time.elapsed().as_millis());
+
+    search.result().unwrap().clone()
+}
+
+#[test]
+fn test_mate_search_finds_shortest_mate() {
+    for use_proof_number_search in [false, true] {
+        let result = run_mate_search(
+            "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+            3,
+            use_proof_number_search,
+        );
+        assert_eq!(result.mate_in(), Some(1));
+        assert_eq!(
+            result,
+            MateResult::Mate(vec![SimpleMove::from_str("d1d8").unwrap()])
+        );
+    }
+}
+
+#[test]
+fn test_mate_search_proves_no_mate() {
+    for use_proof_number_search in [false, true] {
+        let result = run_mate_search("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", 2, use_proof_number_search);
+        assert_eq!(result, MateResult::NoMate);
+    }
+}
+
+#[test]
+fn test_mate_search_methods_agree() {
+    let fen = "3r4/pR2N3/2pkb3/5p2/8/2B5/qP3PPP/4R1K1 w - - 1 0";
+    let alpha_beta = run_mate_search(fen, 3, false);
+    let proof_number = run_mate_search(fen, 3, true);
+
+    assert_eq!(alpha_beta.mate_in(), Some(3));
+    assert_eq!(proof_number.mate_in(), Some(3));
+    for result in [alpha_beta, proof_number] {
+        match result {
+            MateResult::Mate(variation) => assert_eq!(variation[0].to_string(), "c3e5"),
+            _ => panic!("expected mate"),
+        }
+    }
+}
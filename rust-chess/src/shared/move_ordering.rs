@@ -3,8 +3,10 @@ use std::{cmp::Ordering, collections::HashSet};
 use lazy_static::lazy_static;
 
 use crate::{
+    bitboard::{each_index_of_one, single_bitboard, Bitboards},
     game::Game,
     helpers::ErrorResult,
+    legal_moves::attackers,
     moves::{all_moves, Capture, Move, MoveOptions, MoveType},
     types::Piece,
 };
@@ -28,6 +30,55 @@ pub fn capture_sort(moves: &mut [Move]) -> ErrorResult<()> {
     Ok(())
 }
 
+const EXCHANGE_ORDER: [Piece; 6] = [
+    Piece::Pawn,
+    Piece::Knight,
+    Piece::Bishop,
+    Piece::Rook,
+    Piece::Queen,
+    Piece::King,
+];
+
+// The material `m` wins once both sides have recaptured on its end square for as long as it pays
+// off, always with their least valuable piece. Sliders lined up behind a capturing piece join in
+// as it leaves. Pins aren't considered.
+pub fn static_exchange(m: &Move, bitboards: &Bitboards) -> isize {
+    let target = m.end_index;
+    let mut occupied = bitboards.all_occupied() & !single_bitboard(m.start_index);
+    if let MoveType::Capture(Capture::EnPassant { taken_index }) = m.move_type {
+        occupied &= !single_bitboard(taken_index);
+    }
+
+    let mut gains = vec![m.target_piece().map_or(0, |piece| piece.centipawns())];
+    let mut on_target = m.promotion.unwrap_or(m.piece.piece);
+    let mut side = m.piece.player.other();
+
+    loop {
+        // `attackers` looks for the enemies of the player it's given
+        let side_attackers = attackers(target, side.other(), occupied, bitboards) & occupied;
+        let least_valuable = EXCHANGE_ORDER.iter().find_map(|&piece| {
+            let pieces = side_attackers & bitboards.pieces[side][piece];
+            each_index_of_one(pieces).next().map(|index| (piece, index))
+        });
+        let Some((piece, index)) = least_valuable else {
+            break;
+        };
+
+        gains.push(on_target.centipawns() - gains[gains.len() - 1]);
+        occupied &= !single_bitboard(index);
+        on_target = piece;
+        side = side.other();
+    }
+
+    // Either side can stop recapturing when it would lose out
+    while gains.len() > 1 {
+        let last = gains.pop().unwrap();
+        let previous = gains.len() - 1;
+        gains[previous] = -isize::max(-gains[previous], last);
+    }
+    gains[0]
+}
+
 #[test]
 fn test_capture_sort() {
     let fen = "8/2p1k3/3R4/3r4/4nq2/2p5/3Q4/2K5 b";
@@ -59,3 +110,24 @@ fn test_capture_sort_2() {
     }
     assert_eq!(moves_set.len(), moves.len());
 }
+
+#[test]
+fn test_static_exchange() {
+    let see = |fen: &str, uci: &str| -> isize {
+        let game = Game::from_fen(fen).unwrap();
+        static_exchange(&game.move_from_str(uci).unwrap(), game.bitboards())
+    };
+
+    // Undefended pawn
+    assert_eq!(see("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5"), 100);
+    // Defended pawn, with the rook behind the queen joining in
+    assert_eq!(
+        see("1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1", "d3e5"),
+        -200
+    );
+    // Queen takes a pawn defended by a pawn
+    assert_eq!(see("4k3/8/3p4/4p3/8/8/8/4QK2 w - - 0 1", "e1e5"), -800);
+    // Equal trade
+    assert_eq!(see("4k3/8/8/3n4/8/8/8/3RK3 w - - 0 1", "d1d5"), 300);
+    assert_eq!(see("4k3/3r4/8/3n4/8/8/8/3RK3 w - - 0 1", "d1d5"), -200);
+}
@@ -1,11 +1,12 @@
 use std::fmt::{Debug, Formatter};
 
-use super::{bitboard::*, game::Game, helpers::*, types::*};
+use super::{bitboard::*, game::Game, helpers::*, legal_moves::legal_moves, types::*};
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct MoveOptions {
     pub only_captures: OnlyCaptures,
     pub only_queen_promotion: OnlyQueenPromotion,
+    pub only_quiets: OnlyQuiets,
 }
 
 impl Default for MoveOptions {
@@ -13,6 +14,7 @@ impl Default for MoveOptions {
         Self {
             only_captures: Default::default(),
             only_queen_promotion: Default::default(),
+            only_quiets: Default::default(),
         }
     }
 }
@@ -29,6 +31,14 @@ impl Default for OnlyCaptures {
     }
 }
 
+// The opposite of `OnlyCaptures`, for generating the quiet moves after the captures were tried
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum OnlyQuiets {
+    #[default]
+    No,
+    Yes,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum OnlyQueenPromotion {
     No,
@@ -102,9 +112,21 @@ impl Move {
     }
 
     pub fn to_uci(&self) -> String {
+        self.to_uci_with(false)
+    }
+
+    // With UCI_Chess960 castling is always written as the king taking its own rook
+    pub fn to_uci_with(&self, chess960: bool) -> String {
         let promo = self.promotion.map(|p| p.to_uci());
         let promo = promo.unwrap_or(&"");
-        format!("{}{}{}", self.start_index, self.end_index, promo)
+        let end = match self.move_type {
+            MoveType::Quiet(Quiet::Castle { rook_start, .. }) if chess960 => rook_start,
+            MoveType::Quiet(Quiet::Castle { rook_start, .. }) => {
+                castling_notation_end(self.start_index, self.end_index, rook_start)
+            }
+            _ => self.end_index,
+        };
+        format!("{}{}{}", self.start_index, end, promo)
     }
 
     pub fn to_pretty_str(&self) -> String {
@@ -500,13 +522,11 @@ pub fn can_castle_on_side(
         return Ok(false);
     }
 
-    for &empty_index in &req.require_empty {
-        if state.bitboards().piece_at_index(empty_index).is_some() {
-            return Ok(false);
-        }
+    if req.require_empty & state.bitboards().all_occupied() != 0 {
+        return Ok(false);
     }
 
-    for &safe_index in &req.require_safe {
+    for safe_index in each_index_of_one(req.require_safe) {
         if index_in_danger(player, safe_index, state.bitboards())? {
             return Ok(false);
         }
@@ -517,9 +537,9 @@ pub fn can_castle_on_side(
 
 pub fn castling_moves(buffer: &mut Vec<Move>, player: Player, state: &Game) -> ErrorResult<()> {
     for side in CASTLING_SIDES {
-        let req = castling_requirements(player, side);
+        let req = castling_requirements(player, side, state.castling_files()[player]);
 
-        if can_castle_on_side(side, player, state, req)? {
+        if can_castle_on_side(side, player, state, &req)? {
             buffer.push(Move {
                 piece: PlayerPiece::new(player, Piece::King),
                 start_index: req.king_start,
@@ -589,6 +609,10 @@ pub fn all_moves<'game>(
 
     en_passant_move(buffer, player, state.bitboards(), state.en_passant())?;
 
+    if options.only_quiets == OnlyQuiets::Yes {
+        buffer.retain(|m| m.is_quiet());
+    }
+
     Ok(())
 }
 
@@ -756,7 +780,7 @@ impl LazyMoves {
     {
         if self.buffer.is_none() {
             self.buffer.update(&mut |buffer| -> ErrorResult<()> {
-                all_moves(buffer, state.player(), state, options)?;
+                legal_moves(buffer, state.player(), state, options)?;
                 sorter(state, buffer)?;
                 Ok(())
             })?;
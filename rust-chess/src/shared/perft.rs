@@ -1,14 +1,16 @@
 use std::{collections::HashMap, default};
 
 use crate::{
-    bitboard::warm_magic_cache, moves::all_moves, traversal::{null_move_sort, TraversalData}, zobrist::ZobristHistory,
+    bitboard::warm_magic_cache, legal_moves::legal_moves, traversal::{null_move_sort, TraversalData}, zobrist::ZobristHistory,
 };
 
 use super::{
-    danger::Danger,
+    bitboard::single_bitboard,
     game::{Game, Legal},
-    helpers::{err_result, indent, ErrorResult},
-    moves::{Move, MoveOptions},
+    helpers::{err_result, indent, pad_left, ErrorResult, OptionResult},
+    legal_moves::LegalMasks,
+    moves::{Capture, Move, MoveOptions, MoveType, Quiet},
+    perft_table::PerftTable,
     traversal::TraversalStack,
 };
 
@@ -104,19 +106,13 @@ pub fn traverse_game_callback(
         return Ok(());
     }
 
-    let danger = Danger::from(game.player(), game.bitboards())?;
-
     let mut moves = vec![];
-    all_moves(&mut moves, game.player(), game, MoveOptions::default())?;
+    legal_moves(&mut moves, game.player(), game, MoveOptions::default())?;
 
     for &m in moves.iter() {
         let mut next_game = game.clone();
         next_game.make_move(m)?;
 
-        if next_game.move_legality(&m, &danger) == Legal::No {
-            continue;
-        }
-
         moves_stack.push(m);
         traverse_game_callback(moves_stack, &next_game, depth + 1, max_depth, callback)?;
         moves_stack.pop();
@@ -126,21 +122,7 @@ pub fn traverse_game_callback(
 }
 
 pub fn run_perft(game: &Game, max_depth: usize) -> ErrorResult<usize> {
-    let mut perft_overall = 0;
-
-    let mut moves_stack = vec![];
-
-    traverse_game_callback(&mut moves_stack, &game, 0, max_depth, &mut |params| {
-        if params.depth == max_depth {
-            perft_overall += 1;
-
-            if params.depth == 0 {
-                return;
-            }
-        }
-    })?;
-
-    Ok(perft_overall)
+    run_perft_bulk(game, max_depth)
 }
 
 pub fn run_perft_counting_first_move(
@@ -150,19 +132,13 @@ pub fn run_perft_counting_first_move(
     let mut total_count = 0;
     let mut count_per_move: HashMap<String, usize> = HashMap::new();
 
-    let danger = Danger::from(game.player(), game.bitboards())?;
-
     let mut moves = vec![];
-    all_moves(&mut moves, game.player(), game, MoveOptions::default())?;
+    legal_moves(&mut moves, game.player(), game, MoveOptions::default())?;
 
     for &next_move in moves.iter() {
         let mut next_game = game.clone();
         next_game.make_move(next_move)?;
 
-        if next_game.move_legality(&next_move, &danger) == Legal::No {
-            continue;
-        }
-
         let move_str = next_move.to_uci();
         let count = count_per_move.entry(move_str).or_insert(0);
 
@@ -318,7 +294,7 @@ pub fn run_perft_iteratively<const N: usize>(game: Game) -> ErrorResult<usize> {
         if let Some(next_move) = next_move {
             let (current, next) = data.current_and_next_mut()?;
 
-            let result = next.setup(current, &next_move)?;
+            let result = next.setup(current, &next_move, true)?;
             if result == Legal::No {
                 continue;
             } else {
@@ -356,6 +332,341 @@ pub fn run_perft_iteratively_to_depth(game: Game, max_depth: usize) -> ErrorResu
     }
 }
 
+// Copy-make keeps a `Game` per ply and never has to restore anything. Make/unmake keeps a single
+// `Game` and an undo record per ply, so it touches less memory but does more work per move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MakeStrategy {
+    CopyMake,
+    MakeUnmake,
+}
+
+pub const MAKE_STRATEGIES: [MakeStrategy; 2] = [MakeStrategy::CopyMake, MakeStrategy::MakeUnmake];
+
+// Copy-make is the faster of the two both natively and in wasm, so it's the default. Best of nine
+// `run_perft_with` runs, in ms, for startpos depth 5, Kiwipete depth 4 and position 3 depth 6:
+//
+//                 native (release)    wasm32 in Node 20
+//   copy-make     264 / 218 / 767     464 / 432 / 663
+//   make/unmake   271 / 251 / 933     555 / 491 / 737
+//
+// `PerftForJs::compare_make_strategies` repeats the comparison in the browser.
+pub const DEFAULT_MAKE_STRATEGY: MakeStrategy = MakeStrategy::CopyMake;
+
+impl std::fmt::Display for MakeStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MakeStrategy::CopyMake => write!(f, "copy-make"),
+            MakeStrategy::MakeUnmake => write!(f, "make-unmake"),
+        }
+    }
+}
+
+// With `bulk` counting, the moves at the last depth are counted rather than made
+fn perft_copy_make(
+    game: &Game,
+    depth: usize,
+    bulk: bool,
+    buffers: &mut [Vec<Move>],
+) -> ErrorResult<usize> {
+    if depth == 0 {
+        return Ok(1);
+    }
+    let (moves, rest) = buffers.split_first_mut().as_result()?;
+    legal_moves(moves, game.player(), game, MoveOptions::default())?;
+    if bulk && depth == 1 {
+        return Ok(moves.len());
+    }
+
+    let mut count = 0;
+    for &m in moves.iter() {
+        let mut next_game = *game;
+        next_game.make_move(m)?;
+        count += perft_copy_make(&next_game, depth - 1, bulk, rest)?;
+    }
+    Ok(count)
+}
+
+fn perft_make_unmake(
+    game: &mut Game,
+    depth: usize,
+    bulk: bool,
+    buffers: &mut [Vec<Move>],
+) -> ErrorResult<usize> {
+    if depth == 0 {
+        return Ok(1);
+    }
+    let (moves, rest) = buffers.split_first_mut().as_result()?;
+    legal_moves(moves, game.player(), game, MoveOptions::default())?;
+    if bulk && depth == 1 {
+        return Ok(moves.len());
+    }
+
+    let mut count = 0;
+    for &m in moves.iter() {
+        let undo = game.make_move_with_undo(m)?;
+        count += perft_make_unmake(game, depth - 1, bulk, rest)?;
+        game.unmake_move(&undo)?;
+    }
+    Ok(count)
+}
+
+fn perft_hashed(
+    game: &Game,
+    depth: usize,
+    table: &mut PerftTable,
+    buffers: &mut [Vec<Move>],
+) -> ErrorResult<usize> {
+    if depth <= 1 {
+        return perft_copy_make(game, depth, true, buffers);
+    }
+    if let Some(nodes) = table.get(game.zobrist(), depth) {
+        return Ok(nodes);
+    }
+
+    let (moves, rest) = buffers.split_first_mut().as_result()?;
+    legal_moves(moves, game.player(), game, MoveOptions::default())?;
+
+    let mut count = 0;
+    for &m in moves.iter() {
+        let mut next_game = *game;
+        next_game.make_move(m)?;
+        count += perft_hashed(&next_game, depth - 1, table, rest)?;
+    }
+    table.insert(game.zobrist(), depth, count);
+    Ok(count)
+}
+
+fn perft_with(
+    game: &Game,
+    max_depth: usize,
+    strategy: MakeStrategy,
+    bulk: bool,
+) -> ErrorResult<usize> {
+    let mut buffers = vec![vec![]; max_depth];
+    match strategy {
+        MakeStrategy::CopyMake => perft_copy_make(game, max_depth, bulk, &mut buffers),
+        MakeStrategy::MakeUnmake => {
+            perft_make_unmake(&mut game.clone(), max_depth, bulk, &mut buffers)
+        }
+    }
+}
+
+// Makes every move down to the leaves. Move buffers are allocated once per ply, so the strategies
+// only differ in how they make moves.
+pub fn run_perft_with(
+    game: &Game,
+    max_depth: usize,
+    strategy: MakeStrategy,
+) -> ErrorResult<usize> {
+    perft_with(game, max_depth, strategy, false)
+}
+
+// Counts the legal moves at the last depth instead of making them, which skips most of the work
+// since the leaves outnumber all the other nodes together
+pub fn run_perft_bulk(game: &Game, max_depth: usize) -> ErrorResult<usize> {
+    perft_with(game, max_depth, DEFAULT_MAKE_STRATEGY, true)
+}
+
+// Bulk counting, plus counts of transposed subtrees looked up in `table`. The table can be reused
+// across runs, including from other positions.
+pub fn run_perft_hashed(
+    game: &Game,
+    max_depth: usize,
+    table: &mut PerftTable,
+) -> ErrorResult<usize> {
+    let mut buffers = vec![vec![]; max_depth];
+    perft_hashed(game, max_depth, table, &mut buffers)
+}
+
+// The usual perft breakdown for the moves made at one depth
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PerftStats {
+    pub nodes: usize,
+    pub captures: usize,
+    pub en_passant: usize,
+    pub castles: usize,
+    pub promotions: usize,
+    pub checks: usize,
+    // Checks by a piece other than the one that moved
+    pub discovered_checks: usize,
+    pub double_checks: usize,
+    pub checkmates: usize,
+}
+
+pub const PERFT_STATS_HEADER: [&str; 10] = [
+    "depth",
+    "nodes",
+    "captures",
+    "e.p.",
+    "castles",
+    "promotions",
+    "checks",
+    "discovered",
+    "double",
+    "checkmates",
+];
+
+impl PerftStats {
+    fn add_move(&mut self, m: &Move, after: &Game) -> ErrorResult<()> {
+        self.nodes += 1;
+        match m.move_type {
+            MoveType::Capture(Capture::EnPassant { .. }) => {
+                self.captures += 1;
+                self.en_passant += 1;
+            }
+            MoveType::Capture(Capture::Take { .. }) => self.captures += 1,
+            MoveType::Quiet(Quiet::Castle { .. }) => self.castles += 1,
+            _ => {}
+        }
+        if m.promotion.is_some() {
+            self.promotions += 1;
+        }
+
+        let masks = LegalMasks::from(after.player(), after.bitboards());
+        if !masks.check() {
+            return Ok(());
+        }
+        self.checks += 1;
+
+        // A castling rook checks directly
+        let mut moved = single_bitboard(m.end_index);
+        if let MoveType::Quiet(Quiet::Castle { rook_end, .. }) = m.move_type {
+            moved |= single_bitboard(rook_end);
+        }
+        if masks.checkers & !moved != 0 {
+            self.discovered_checks += 1;
+        }
+        if masks.double_check() {
+            self.double_checks += 1;
+        }
+        if !after.has_legal_move()? {
+            self.checkmates += 1;
+        }
+        Ok(())
+    }
+
+    // One row of the table, in the order of `PERFT_STATS_HEADER`
+    pub fn columns(&self, depth: usize) -> [usize; 10] {
+        [
+            depth,
+            self.nodes,
+            self.captures,
+            self.en_passant,
+            self.castles,
+            self.promotions,
+            self.checks,
+            self.discovered_checks,
+            self.double_checks,
+            self.checkmates,
+        ]
+    }
+}
+
+fn perft_stats(
+    game: &Game,
+    stats: &mut [PerftStats],
+    buffers: &mut [Vec<Move>],
+) -> ErrorResult<()> {
+    let Some((depth_stats, deeper_stats)) = stats.split_first_mut() else {
+        return Ok(());
+    };
+    let (moves, rest) = buffers.split_first_mut().as_result()?;
+    legal_moves(moves, game.player(), game, MoveOptions::default())?;
+
+    for &m in moves.iter() {
+        let mut next_game = *game;
+        next_game.make_move(m)?;
+        depth_stats.add_move(&m, &next_game)?;
+        perft_stats(&next_game, deeper_stats, rest)?;
+    }
+    Ok(())
+}
+
+// Stats for every depth from 1 to `max_depth`, in one pass over the tree
+pub fn run_perft_stats(game: &Game, max_depth: usize) -> ErrorResult<Vec<PerftStats>> {
+    let mut stats = vec![PerftStats::default(); max_depth];
+    let mut buffers = vec![vec![]; max_depth];
+    perft_stats(game, &mut stats, &mut buffers)?;
+    Ok(stats)
+}
+
+pub fn perft_stats_table(stats: &[PerftStats]) -> String {
+    let widths = PERFT_STATS_HEADER.map(|title| title.len().max(10));
+    let row = |cells: Vec<String>| -> String {
+        cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| pad_left(cell, " ", width))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    let mut lines = vec![row(PERFT_STATS_HEADER.map(|title| title.to_string()).to_vec())];
+    for (i, depth_stats) in stats.iter().enumerate() {
+        let columns = depth_stats.columns(i + 1);
+        lines.push(row(columns.iter().map(|n| n.to_string()).collect()));
+    }
+    lines.join("\n")
+}
+
+#[test]
+fn test_perft_stats() {
+    let stats = |fen: &str, depth: usize| -> Vec<[usize; 10]> {
+        let game = Game::from_fen(fen).unwrap();
+        let stats = run_perft_stats(&game, depth).unwrap();
+        stats
+            .iter()
+            .enumerate()
+            .map(|(i, s)| s.columns(i + 1))
+            .collect()
+    };
+
+    assert_eq!(
+        stats("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 4),
+        [
+            [1, 20, 0, 0, 0, 0, 0, 0, 0, 0],
+            [2, 400, 0, 0, 0, 0, 0, 0, 0, 0],
+            [3, 8902, 34, 0, 0, 0, 12, 0, 0, 0],
+            [4, 197281, 1576, 0, 0, 0, 469, 0, 0, 8],
+        ]
+    );
+    assert_eq!(
+        stats("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 3),
+        [
+            [1, 48, 8, 0, 2, 0, 0, 0, 0, 0],
+            [2, 2039, 351, 1, 91, 0, 3, 0, 0, 0],
+            [3, 97862, 17102, 45, 3162, 0, 993, 0, 0, 1],
+        ]
+    );
+    assert_eq!(
+        stats("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 4)[3],
+        [4, 43238, 3348, 123, 0, 0, 1680, 106, 0, 17]
+    );
+
+    // Moving the bishop off the e-file always checks, and twice from c6 and g6
+    assert_eq!(
+        stats("4k3/8/8/8/4B3/8/8/K3R3 w - - 0 1", 1)[0],
+        [1, 24, 0, 0, 0, 0, 13, 13, 2, 0]
+    );
+}
+
+#[test]
+fn test_perft_make_strategies() {
+    let positions = [
+        ("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 97862),
+        ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", 2812),
+        ("r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1", 9467),
+        ("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", 12189),
+    ];
+    for (fen, expected) in positions {
+        let game = Game::from_fen(fen).unwrap();
+        for strategy in MAKE_STRATEGIES {
+            let count = run_perft_with(&game, 3, strategy).unwrap();
+            assert_eq!(count, expected, "{} {}", strategy, fen);
+        }
+    }
+}
+
 #[test]
 fn test_perft_start_board() {
     let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
@@ -435,6 +746,8 @@ pub struct PerftLoop {
     pub count: usize,
     pub max_depth: usize,
     pub start_fen: String,
+    // Count the moves of nodes just above the leaves without making them
+    pub bulk_counting: bool,
 
     loop_count: usize,
 }
@@ -449,6 +762,7 @@ impl PerftLoop {
             stack,
             count: 0,
             max_depth,
+            bulk_counting: true,
             loop_count: LOOP_COUNT,
             start_fen: fen.to_string(),
         }
@@ -465,6 +779,20 @@ impl PerftLoop {
             return Ok(PerftLoopResult::Continue);
         }
 
+        if self.bulk_counting && traversal.depth() + 2 == self.max_depth {
+            let (current, _) = traversal.current_mut()?;
+            let moves = current
+                .moves
+                .get(&current.game, MoveOptions::default(), null_move_sort)?;
+            self.count += moves.len();
+
+            if traversal.depth() == 0 {
+                return Ok(PerftLoopResult::Done);
+            }
+            traversal.decrement_depth()?;
+            return Ok(PerftLoopResult::Continue);
+        }
+
         // We have moves to traverse, dig deeper
         let (current, _) = traversal.current_mut().unwrap();
         let current_moves = &mut current.moves;
@@ -475,7 +803,7 @@ impl PerftLoop {
         if let Some(next_move) = next_move {
             let (current, next) = traversal.current_and_next_mut().unwrap();
 
-            let result = next.setup(current, &next_move).unwrap();
+            let result = next.setup(current, &next_move, true).unwrap();
             if result == Legal::No {
                 return Ok(PerftLoopResult::Continue);
             } else {
@@ -504,3 +832,36 @@ impl PerftLoop {
         Ok(PerftLoopResult::Continue)
     }
 }
+
+#[test]
+fn test_perft_bulk_and_hashed() {
+    let positions = [
+        (
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            [1, 6, 264, 9467, 422333],
+        ),
+        (
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            [1, 44, 1486, 62379, 2103487],
+        ),
+    ];
+
+    // One table for everything, so entries from other positions and depths get in the way
+    let mut table = PerftTable::new(12);
+    for (fen, expected) in positions {
+        let game = Game::from_fen(fen).unwrap();
+        for (depth, &count) in expected.iter().enumerate() {
+            assert_eq!(run_perft_bulk(&game, depth).unwrap(), count, "{}", fen);
+            assert_eq!(run_perft_hashed(&game, depth, &mut table).unwrap(), count, "{}", fen);
+        }
+    }
+    assert!(table.stats.hits > 0);
+
+    // The loop's `max_depth` counts the root as well
+    for bulk_counting in [false, true] {
+        let mut perft = PerftLoop::new(positions[0].0, 4);
+        perft.bulk_counting = bulk_counting;
+        while perft.iterate_loop().unwrap() == PerftLoopResult::Continue {}
+        assert_eq!(perft.count, 9467);
+    }
+}
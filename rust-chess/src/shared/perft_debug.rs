@@ -0,0 +1,219 @@
+/*
+Perft debugging against a reference engine.
+
+Any UCI engine that understands `go perft <depth>` and prints one `<move>: <count>` line per root
+move followed by `Nodes searched: <total>` (as Stockfish does) can act as the reference. Both
+engines divide the same position, and the first move whose counts disagree is made on both sides
+and divided again one ply shallower. This ends at the position where the two produce different
+moves, or at no position at all if every count agrees.
+*/
+
+use std::collections::HashMap;
+
+use crate::{
+    game::Game,
+    helpers::{err_result, ErrorResult, OptionResult},
+    perft::run_perft_counting_first_move,
+    perft_suite::PerftDivergence,
+    uci_engine::UciEngine,
+};
+
+pub type PerftDivide = HashMap<String, usize>;
+
+// Reads the `<move>: <count>` lines, checking them against the `Nodes searched` total
+pub fn parse_perft_divide<S: AsRef<str>>(lines: &[S]) -> ErrorResult<PerftDivide> {
+    let mut divide = PerftDivide::new();
+    let mut total = None;
+
+    for line in lines {
+        let Some((left, right)) = line.as_ref().split_once(':') else {
+            continue;
+        };
+        let Ok(count) = right.trim().parse::<usize>() else {
+            continue;
+        };
+        if left == "Nodes searched" {
+            total = Some(count);
+        } else if (4..=5).contains(&left.len()) && !left.contains(' ') {
+            divide.insert(left.to_string(), count);
+        }
+    }
+
+    let total = match total {
+        Some(total) => total,
+        None => return err_result("perft output has no 'Nodes searched' line"),
+    };
+    let sum: usize = divide.values().sum();
+    if sum != total {
+        return err_result(&format!(
+            "perft output lists {} nodes per move but {} in total",
+            sum, total
+        ));
+    }
+    Ok(divide)
+}
+
+// Asks `engine` to divide the position at `fen`
+pub fn perft_divide(engine: &mut UciEngine, fen: &str, depth: usize) -> ErrorResult<PerftDivide> {
+    engine.send(&format!("position fen {}", fen))?;
+    engine.send(&format!("go perft {}", depth))?;
+    let lines = engine.read_until(|line| line.starts_with("Nodes searched"))?;
+    parse_perft_divide(&lines)
+}
+
+fn own_perft_divide(game: &Game, depth: usize) -> ErrorResult<PerftDivide> {
+    let (_, divide) = run_perft_counting_first_move(game, depth)?;
+    Ok(divide)
+}
+
+fn sorted_keys_missing_from(a: &PerftDivide, b: &PerftDivide) -> Vec<String> {
+    let mut keys: Vec<String> = a.keys().filter(|m| !b.contains_key(*m)).cloned().collect();
+    keys.sort();
+    keys
+}
+
+// Divides with `reference` at every ply on the way down, logging the moves that disagree
+pub fn find_divergence_against(
+    game: &Game,
+    depth: usize,
+    reference: &mut dyn FnMut(&Game, usize) -> ErrorResult<PerftDivide>,
+    log: &mut dyn FnMut(&str),
+) -> ErrorResult<Option<PerftDivergence>> {
+    let mut game = *game;
+    let mut moves_so_far = vec![];
+
+    for depth in (1..=depth).rev() {
+        let ours = own_perft_divide(&game, depth)?;
+        let theirs = reference(&game, depth)?;
+
+        let missing = sorted_keys_missing_from(&theirs, &ours);
+        let extra = sorted_keys_missing_from(&ours, &theirs);
+        if !missing.is_empty() || !extra.is_empty() {
+            return Ok(Some(PerftDivergence {
+                moves: moves_so_far,
+                fen: game.to_fen(),
+                missing,
+                extra,
+            }));
+        }
+
+        let mut mismatches: Vec<(&String, usize, usize)> = ours
+            .iter()
+            .filter(|(m, count)| theirs[*m] != **count)
+            .map(|(m, &count)| (m, count, theirs[m]))
+            .collect();
+        mismatches.sort();
+        let Some(&(diverging, ours_count, theirs_count)) = mismatches.first() else {
+            return Ok(None);
+        };
+        log(&format!(
+            "D{} after [{}]: {} of {} moves disagree, following {} ({} vs {})",
+            depth,
+            moves_so_far.join(" "),
+            mismatches.len(),
+            ours.len(),
+            diverging,
+            ours_count,
+            theirs_count
+        ));
+
+        let m = game
+            .legal_moves()?
+            .into_iter()
+            .find(|m| m.to_uci() == *diverging)
+            .as_result()?;
+        game.make_move(m)?;
+        moves_so_far.push(diverging.clone());
+    }
+    Ok(None)
+}
+
+// Runs `go perft` on `engine_path` and on our own move generator, recursing into the first move
+// whose counts differ
+pub fn perft_debug(
+    engine_path: &str,
+    game: &Game,
+    depth: usize,
+    log: &mut dyn FnMut(&str),
+) -> ErrorResult<Option<PerftDivergence>> {
+    let mut engine = UciEngine::spawn(engine_path)?;
+    find_divergence_against(
+        game,
+        depth,
+        &mut |game, depth| perft_divide(&mut engine, &game.to_fen(), depth),
+        log,
+    )
+}
+
+#[test]
+fn test_parse_perft_divide() {
+    let output = [
+        "info string NNUE evaluation using nn-b1a57edbea57.nnue",
+        "a2a3: 380",
+        "b7b8q: 24",
+        "",
+        "Nodes searched: 404",
+    ];
+    let divide = parse_perft_divide(&output).unwrap();
+    assert_eq!(divide.len(), 2);
+    assert_eq!(divide["a2a3"], 380);
+    assert_eq!(divide["b7b8q"], 24);
+
+    assert!(parse_perft_divide(&output[..3]).is_err());
+    assert!(parse_perft_divide(&["a2a3: 380", "Nodes searched: 400"]).is_err());
+}
+
+#[test]
+fn test_find_divergence_against() {
+    // Divide output from a reference engine that doesn't know about en passant
+    let transcripts = [
+        (
+            "4k3/3p4/8/4P3/8/8/8/4K3 b - - 0 1",
+            vec![
+                "d7d6: 7",
+                "d7d5: 6",
+                "e8d8: 6",
+                "e8e7: 6",
+                "e8f7: 6",
+                "e8f8: 6",
+                "",
+                "Nodes searched: 37",
+            ],
+        ),
+        (
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2",
+            vec![
+                "e5e6: 1",
+                "e1d1: 1",
+                "e1d2: 1",
+                "e1e2: 1",
+                "e1f1: 1",
+                "e1f2: 1",
+                "",
+                "Nodes searched: 6",
+            ],
+        ),
+    ];
+    let mut reference = |game: &Game, _: usize| {
+        let fen = game.to_fen();
+        match transcripts.iter().find(|(transcript_fen, _)| *transcript_fen == fen) {
+            Some((_, lines)) => parse_perft_divide(lines),
+            None => err_result(&format!("no transcript for {}", fen)),
+        }
+    };
+
+    let game = Game::from_fen(transcripts[0].0).unwrap();
+    let mut lines = vec![];
+    let divergence =
+        find_divergence_against(&game, 2, &mut reference, &mut |line| lines.push(line.to_string()))
+            .unwrap()
+            .unwrap();
+    assert_eq!(divergence.moves, ["d7d5"]);
+    assert_eq!(divergence.fen, transcripts[1].0);
+    assert_eq!(divergence.missing, Vec::<String>::new());
+    assert_eq!(divergence.extra, ["e5d6"]);
+    assert_eq!(lines, ["D2 after []: 1 of 6 moves disagree, following d7d5 (7 vs 6)"]);
+
+    let mut own = |game: &Game, depth: usize| own_perft_divide(game, depth);
+    assert_eq!(find_divergence_against(&game, 3, &mut own, &mut |_| {}).unwrap(), None);
+}
@@ -0,0 +1,316 @@
+/*
+Perft regression suites.
+
+A suite is an EPD file with the expected node count for each depth as D<depth> opcodes, e.g.
+
+    rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - ;D1 20 ;D2 400 ;D3 8902
+
+Every position is run to each listed depth up to a maximum. An EPD only has totals, so when a count
+is off the runner drills down on its own: it compares against a reference that generates
+pseudo-legal moves and makes each one to test it, which is how moves were generated before
+`legal_moves`. Following the first move whose subtree counts disagree leads to the position where
+the two produce different moves.
+*/
+
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    danger::Danger,
+    epd::{parse_epd_lines, Epd},
+    game::{Game, Legal},
+    helpers::{err_result, ErrorResult},
+    moves::{all_moves, Move, MoveOptions},
+    perft::run_perft,
+};
+
+// The positions from the Chess Programming Wiki's perft results page
+pub const STANDARD_PERFT_SUITE: &str = "\
+rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - ;D1 20 ;D2 400 ;D3 8902 ;D4 197281 ;D5 4865609 ;D6 119060324
+r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - ;D1 48 ;D2 2039 ;D3 97862 ;D4 4085603 ;D5 193690690
+8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - ;D1 14 ;D2 191 ;D3 2812 ;D4 43238 ;D5 674624 ;D6 11030083
+r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - ;D1 6 ;D2 264 ;D3 9467 ;D4 422333 ;D5 15833292
+rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - ;D1 44 ;D2 1486 ;D3 62379 ;D4 2103487 ;D5 89941194
+r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - ;D1 46 ;D2 2079 ;D3 89890 ;D4 3894594 ;D5 164075551
+";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PerftDivergence {
+    // Moves from the suite position to the position where the move lists differ
+    pub moves: Vec<String>,
+    pub fen: String,
+    // Moves only the reference generates, and moves only `legal_moves` generates
+    pub missing: Vec<String>,
+    pub extra: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PerftSuiteResult {
+    pub fen: String,
+    pub depth: usize,
+    pub expected: usize,
+    pub actual: usize,
+    pub elapsed: Duration,
+    // Only looked for when the count is wrong, and `None` if both generators agree
+    pub divergence: Option<PerftDivergence>,
+}
+
+impl PerftSuiteResult {
+    pub fn passed(&self) -> bool {
+        self.expected == self.actual
+    }
+}
+
+impl Display for PerftSuiteResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let elapsed = self.elapsed.as_millis();
+        if self.passed() {
+            return write!(
+                f,
+                "ok   D{} {} in {}ms: {}",
+                self.depth, self.actual, elapsed, self.fen
+            );
+        }
+
+        write!(
+            f,
+            "FAIL D{} expected {}, got {} in {}ms: {}",
+            self.depth, self.expected, self.actual, elapsed, self.fen
+        )?;
+        match &self.divergence {
+            Some(divergence) => write!(
+                f,
+                "\n  after [{}] ({}): missing [{}], extra [{}]",
+                divergence.moves.join(" "),
+                divergence.fen,
+                divergence.missing.join(" "),
+                divergence.extra.join(" "),
+            ),
+            None => write!(f, "\n  the reference generator agrees with this count"),
+        }
+    }
+}
+
+pub fn reference_moves(game: &Game) -> ErrorResult<Vec<Move>> {
+    let danger = Danger::from(game.player(), game.bitboards())?;
+
+    let mut moves = vec![];
+    all_moves(&mut moves, game.player(), game, MoveOptions::default())?;
+
+    let mut legal = vec![];
+    for m in moves {
+        let mut next_game = *game;
+        next_game.make_move(m)?;
+        if next_game.move_legality(&m, &danger) == Legal::Yes {
+            legal.push(m);
+        }
+    }
+    Ok(legal)
+}
+
+type MoveGenerator = fn(&Game) -> ErrorResult<Vec<Move>>;
+
+fn perft_with_generator(
+    game: &Game,
+    depth: usize,
+    generate: MoveGenerator,
+) -> ErrorResult<usize> {
+    if depth == 0 {
+        return Ok(1);
+    }
+    let mut count = 0;
+    for m in generate(game)? {
+        let mut next_game = *game;
+        next_game.make_move(m)?;
+        count += perft_with_generator(&next_game, depth - 1, generate)?;
+    }
+    Ok(count)
+}
+
+fn uci_set(moves: &[Move]) -> HashSet<String> {
+    moves.iter().map(|m| m.to_uci()).collect()
+}
+
+fn sorted_difference(a: &HashSet<String>, b: &HashSet<String>) -> Vec<String> {
+    let mut difference: Vec<String> = a.difference(b).cloned().collect();
+    difference.sort();
+    difference
+}
+
+fn find_divergence_with(
+    game: &Game,
+    depth: usize,
+    reference: MoveGenerator,
+) -> ErrorResult<Option<PerftDivergence>> {
+    let mut game = *game;
+    let mut moves_so_far = vec![];
+
+    for depth in (1..=depth).rev() {
+        let ours = game.legal_moves()?;
+        let ours_set = uci_set(&ours);
+        let reference_set = uci_set(&reference(&game)?);
+
+        if ours_set != reference_set || ours.len() != ours_set.len() {
+            return Ok(Some(PerftDivergence {
+                moves: moves_so_far,
+                fen: game.to_fen(),
+                missing: sorted_difference(&reference_set, &ours_set),
+                extra: sorted_difference(&ours_set, &reference_set),
+            }));
+        }
+
+        let mut diverging = None;
+        for m in ours {
+            let mut next_game = game;
+            next_game.make_move(m)?;
+            let ours_count = perft_with_generator(&next_game, depth - 1, Game::legal_moves)?;
+            let reference_count = perft_with_generator(&next_game, depth - 1, reference)?;
+            if ours_count != reference_count {
+                diverging = Some((m, next_game));
+                break;
+            }
+        }
+
+        match diverging {
+            Some((m, next_game)) => {
+                moves_so_far.push(m.to_uci());
+                game = next_game;
+            }
+            None => return Ok(None),
+        }
+    }
+    Ok(None)
+}
+
+// Follows the first move whose perft counts differ from the reference down to the position where
+// the generated moves differ
+pub fn find_divergence(game: &Game, depth: usize) -> ErrorResult<Option<PerftDivergence>> {
+    find_divergence_with(game, depth, reference_moves)
+}
+
+fn run_epd(
+    epd: &Epd,
+    max_depth: usize,
+    log: &mut dyn FnMut(&str),
+) -> ErrorResult<Vec<PerftSuiteResult>> {
+    let game = epd.game()?;
+    let mut results = vec![];
+
+    for (depth, expected) in epd.perft_counts()? {
+        if depth > max_depth {
+            break;
+        }
+
+        let start = Instant::now();
+        let actual = run_perft(&game, depth)?;
+        let elapsed = start.elapsed();
+
+        let mut result = PerftSuiteResult {
+            fen: game.to_fen(),
+            depth,
+            expected,
+            actual,
+            elapsed,
+            divergence: None,
+        };
+        let passed = result.passed();
+        if !passed {
+            result.divergence = find_divergence(&game, depth)?;
+        }
+        log(&result.to_string());
+        results.push(result);
+
+        // Deeper depths would only take longer to show the same problem
+        if !passed {
+            break;
+        }
+    }
+    Ok(results)
+}
+
+// Runs every position in the suite to each of its depths up to `max_depth`, logging each result
+pub fn run_perft_suite(
+    text: &str,
+    max_depth: usize,
+    log: &mut dyn FnMut(&str),
+) -> ErrorResult<Vec<PerftSuiteResult>> {
+    let mut results = vec![];
+    for epd in parse_epd_lines(text)? {
+        results.extend(run_epd(&epd, max_depth, log)?);
+    }
+    Ok(results)
+}
+
+pub fn run_perft_suite_file(
+    path: &str,
+    max_depth: usize,
+    log: &mut dyn FnMut(&str),
+) -> ErrorResult<Vec<PerftSuiteResult>> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => run_perft_suite(&text, max_depth, log),
+        Err(e) => err_result(&format!("couldn't read perft suite {}: {}", path, e)),
+    }
+}
+
+pub fn perft_suite_summary(results: &[PerftSuiteResult]) -> String {
+    let failed = results.iter().filter(|result| !result.passed()).count();
+    let nodes: usize = results.iter().map(|result| result.actual).sum();
+    let elapsed: Duration = results.iter().map(|result| result.elapsed).sum();
+    let nps = nodes as f64 / elapsed.as_secs_f64().max(0.001);
+    format!(
+        "{} passed, {} failed, {} nodes in {}ms ({:.0} nps)",
+        results.len() - failed,
+        failed,
+        nodes,
+        elapsed.as_millis(),
+        nps
+    )
+}
+
+#[test]
+fn test_perft_suite() {
+    let mut lines = vec![];
+    let results = run_perft_suite(STANDARD_PERFT_SUITE, 3, &mut |line| {
+        lines.push(line.to_string())
+    })
+    .unwrap();
+    assert_eq!(results.len(), 18);
+    assert!(results.iter().all(|result| result.passed()));
+    assert_eq!(lines.len(), 18);
+    println!("{}", perft_suite_summary(&results));
+
+    // A wrong count that both generators agree on has nothing to drill into
+    let suite = "4k3/8/8/8/8/8/8/4K2R w K - ;D1 15 ;D2 70 ;D3 1";
+    let results = run_perft_suite(suite, 3, &mut |_| {}).unwrap();
+    assert_eq!(results.len(), 2);
+    assert!(results[0].passed());
+    assert_eq!((results[1].expected, results[1].actual), (70, 66));
+    assert_eq!(results[1].divergence, None);
+    assert!(results[1].to_string().starts_with("FAIL D2 expected 70, got 66"));
+}
+
+#[test]
+fn test_find_divergence() {
+    use crate::types::Piece;
+
+    // A reference that forgets underpromotions
+    fn queen_promotions_only(game: &Game) -> ErrorResult<Vec<Move>> {
+        let mut moves = reference_moves(game)?;
+        moves.retain(|m| matches!(m.promotion, None | Some(Piece::Queen)));
+        Ok(moves)
+    }
+
+    let game = Game::from_fen("4k3/8/8/8/8/8/1p6/4K3 w - - 0 1").unwrap();
+    let divergence = find_divergence_with(&game, 2, queen_promotions_only)
+        .unwrap()
+        .unwrap();
+    assert_eq!(divergence.moves, ["e1d1"]);
+    assert_eq!(divergence.fen, "4k3/8/8/8/8/8/1p6/3K4 b - - 1 1");
+    assert_eq!(divergence.missing, Vec::<String>::new());
+    assert_eq!(divergence.extra, ["b2b1b", "b2b1n", "b2b1r"]);
+
+    assert_eq!(find_divergence(&game, 3).unwrap(), None);
+}
@@ -0,0 +1,77 @@
+/*
+A hash table of perft subtree counts.
+
+The same position is reached through many move orders, and its count below a given depth never
+changes, so each (position, depth) only has to be counted once. Entries are keyed by the full
+Zobrist hash plus the remaining depth, with the low bits of the hash mixed with the depth picking
+the slot. A new entry always replaces the old one.
+*/
+
+use crate::zobrist::ZobristHash;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct PerftEntry {
+    hash: u64,
+    // Zero for an empty slot, counts at depth 0 and 1 aren't worth storing
+    depth: usize,
+    nodes: usize,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PerftTableStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub stores: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct PerftTable {
+    table: Vec<PerftEntry>,
+    mask: u64,
+    pub stats: PerftTableStats,
+}
+
+// 2^20 entries of 24 bytes each, 24mb
+pub const DEFAULT_PERFT_TABLE_BITS: u32 = 20;
+
+// Spreads consecutive depths of the same position over different slots
+const DEPTH_MIX: u64 = 0x9E37_79B9_7F4A_7C15;
+
+impl PerftTable {
+    pub fn new(bits: u32) -> Self {
+        Self {
+            table: vec![PerftEntry::default(); 1 << bits],
+            mask: (1 << bits) - 1,
+            stats: PerftTableStats::default(),
+        }
+    }
+
+    fn slot(&self, hash: u64, depth: usize) -> usize {
+        ((hash ^ (depth as u64).wrapping_mul(DEPTH_MIX)) & self.mask) as usize
+    }
+
+    pub fn get(&mut self, zobrist: ZobristHash, depth: usize) -> Option<usize> {
+        let hash = zobrist.value();
+        let entry = self.table[self.slot(hash, depth)];
+        if entry.hash == hash && entry.depth == depth {
+            self.stats.hits += 1;
+            Some(entry.nodes)
+        } else {
+            self.stats.misses += 1;
+            None
+        }
+    }
+
+    pub fn insert(&mut self, zobrist: ZobristHash, depth: usize, nodes: usize) {
+        let hash = zobrist.value();
+        let slot = self.slot(hash, depth);
+        self.table[slot] = PerftEntry { hash, depth, nodes };
+        self.stats.stores += 1;
+    }
+}
+
+impl Default for PerftTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_PERFT_TABLE_BITS)
+    }
+}
@@ -0,0 +1,784 @@
+/*
+Portable Game Notation.
+
+A game is stored as a tree of positions. Node 0 is the starting position, every other node
+holds the move that led to it and the position after it. The first child of a node is the main
+line, further children are variations. Comments and NAGs hang off the node of the move they
+follow.
+
+`PgnReader` splits a multi-game file into games one at a time, so large databases can be
+processed straight from disk.
+*/
+
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{BufRead, BufReader, Lines, Write},
+};
+
+use crate::{
+    game::Game,
+    helpers::{err, err_result, ErrorResult},
+    moves::Move,
+    san::{move_from_san, move_to_san},
+    types::Player,
+};
+
+pub const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+// The seven tag roster, written first and in this order
+const REQUIRED_TAGS: [&str; 7] = ["Event", "Site", "Date", "Round", "White", "Black", "Result"];
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+const MAX_LINE_LENGTH: usize = 80;
+
+#[derive(Debug, Clone)]
+pub struct PgnNode {
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+
+    pub history_move: Option<Move>,
+    pub san: String,
+    pub game: Game,
+
+    pub nags: Vec<u8>,
+    // Comments in front of the move, only found at the start of a variation
+    pub comments_before: Vec<String>,
+    pub comments: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub nodes: Vec<PgnNode>,
+    pub result: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    StartVariation,
+    EndVariation,
+    Result(String),
+    San(String),
+}
+
+fn suffix_nag(suffix: &str) -> Option<u8> {
+    match suffix {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    }
+}
+
+fn is_symbol_end(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '{' | '}' | '(' | ')' | ';' | '$' | '[' | ']')
+}
+
+fn tokenize(text: &str) -> ErrorResult<Vec<Token>> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    let read_until = |i: &mut usize, end: char| -> String {
+        let start = *i;
+        while *i < chars.len() && chars[*i] != end {
+            *i += 1;
+        }
+        let s: String = chars[start..*i].iter().collect();
+        *i += 1;
+        s
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let at_line_start = i == 0 || chars[i - 1] == '\n';
+
+        match c {
+            _ if c.is_whitespace() => i += 1,
+            '%' if at_line_start => {
+                read_until(&mut i, '\n');
+            }
+            '{' => {
+                i += 1;
+                let comment = read_until(&mut i, '}');
+                tokens.push(Token::Comment(
+                    comment.split_whitespace().collect::<Vec<_>>().join(" "),
+                ));
+            }
+            ';' => {
+                i += 1;
+                tokens.push(Token::Comment(read_until(&mut i, '\n').trim().to_string()));
+            }
+            '(' => {
+                i += 1;
+                tokens.push(Token::StartVariation);
+            }
+            ')' => {
+                i += 1;
+                tokens.push(Token::EndVariation);
+            }
+            '[' => {
+                i += 1;
+                let name_start = i;
+                while i < chars.len() && !chars[i].is_whitespace() && chars[i] != '"' {
+                    i += 1;
+                }
+                let name: String = chars[name_start..i].iter().collect();
+                while i < chars.len() && chars[i] != '"' {
+                    i += 1;
+                }
+                i += 1;
+
+                let mut value = String::new();
+                while i < chars.len() && chars[i] != '"' {
+                    if chars[i] == '\\' && i + 1 < chars.len() {
+                        i += 1;
+                    }
+                    value.push(chars[i]);
+                    i += 1;
+                }
+                read_until(&mut i, ']');
+
+                if name.is_empty() {
+                    return err_result(&format!("tag without a name: {}", value));
+                }
+                tokens.push(Token::Tag(name, value));
+            }
+            '$' => {
+                i += 1;
+                let start = i;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let nag: String = chars[start..i].iter().collect();
+                match nag.parse::<u8>() {
+                    Ok(nag) => tokens.push(Token::Nag(nag)),
+                    Err(_) => return err_result(&format!("invalid NAG ${}", nag)),
+                }
+            }
+            _ => {
+                let start = i;
+                while i < chars.len() && !is_symbol_end(chars[i]) {
+                    i += 1;
+                }
+                if start == i {
+                    return err_result(&format!("unexpected '{}' in PGN", c));
+                }
+                let symbol: String = chars[start..i].iter().collect();
+
+                if RESULTS.contains(&symbol.as_str()) {
+                    tokens.push(Token::Result(symbol));
+                    continue;
+                }
+
+                // Move numbers, possibly glued to the move as in "12.e4" or "12...Nf6"
+                let after_digits = symbol.trim_start_matches(|c: char| c.is_ascii_digit());
+                let symbol = if after_digits.len() < symbol.len() && after_digits.starts_with('.') {
+                    after_digits.trim_start_matches('.')
+                } else {
+                    symbol.as_str()
+                };
+                if symbol.is_empty() {
+                    continue;
+                }
+
+                let san = symbol.trim_end_matches(['!', '?']);
+                tokens.push(Token::San(san.to_string()));
+                if let Some(nag) = suffix_nag(&symbol[san.len()..]) {
+                    tokens.push(Token::Nag(nag));
+                }
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn escape_tag_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// Collects words and wraps them into lines no longer than MAX_LINE_LENGTH
+struct LineWrapper {
+    lines: Vec<String>,
+    line: String,
+    open_variation: bool,
+}
+
+impl LineWrapper {
+    fn push(&mut self, word: &str) {
+        let word = if self.open_variation {
+            self.open_variation = false;
+            format!("({}", word)
+        } else {
+            word.to_string()
+        };
+
+        if !self.line.is_empty() && self.line.len() + 1 + word.len() > MAX_LINE_LENGTH {
+            self.lines.push(std::mem::take(&mut self.line));
+        }
+        if !self.line.is_empty() {
+            self.line.push(' ');
+        }
+        self.line.push_str(&word);
+    }
+
+    fn start_variation(&mut self) {
+        self.open_variation = true;
+    }
+
+    fn end_variation(&mut self) {
+        match self.line.is_empty() {
+            true => self.lines.last_mut().unwrap().push(')'),
+            false => self.line.push(')'),
+        }
+    }
+
+    fn push_comment(&mut self, comment: &str) {
+        let comment = comment.replace('}', "");
+        let mut words = comment.split_whitespace().peekable();
+        if words.peek().is_none() {
+            self.push("{}");
+            return;
+        }
+
+        let mut first = true;
+        while let Some(word) = words.next() {
+            let open = if first { "{" } else { "" };
+            let close = if words.peek().is_none() { "}" } else { "" };
+            self.push(&format!("{}{}{}", open, word, close));
+            first = false;
+        }
+    }
+
+    fn finish(mut self) -> Vec<String> {
+        if !self.line.is_empty() {
+            self.lines.push(self.line);
+        }
+        self.lines
+    }
+}
+
+impl PgnGame {
+    pub fn new(start: Game) -> Self {
+        let mut tags = vec![];
+        if start.to_fen() != STARTING_FEN {
+            tags.push(("SetUp".to_string(), "1".to_string()));
+            tags.push(("FEN".to_string(), start.to_fen()));
+        }
+
+        Self {
+            tags,
+            nodes: vec![PgnNode {
+                parent: None,
+                children: vec![],
+                history_move: None,
+                san: String::new(),
+                game: start,
+                nags: vec![],
+                comments_before: vec![],
+                comments: vec![],
+            }],
+            result: "*".to_string(),
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag_name, _)| tag_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag_name, _)| tag_name == name) {
+            Some((_, tag_value)) => *tag_value = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn start(&self) -> &Game {
+        &self.nodes[0].game
+    }
+
+    // Plays `m` after the node at `parent`, reusing the existing node if the move is already
+    // in the tree. New moves become the main line if there is none yet, a variation otherwise.
+    pub fn add_move(&mut self, parent: usize, m: Move) -> ErrorResult<usize> {
+        let existing = self.nodes[parent]
+            .children
+            .iter()
+            .find(|&&child| self.nodes[child].history_move == Some(m));
+        if let Some(&existing) = existing {
+            return Ok(existing);
+        }
+
+        let before = self.nodes[parent].game;
+        let san = move_to_san(&before, &m)?;
+        let mut game = before;
+        game.make_move(m)?;
+
+        let index = self.nodes.len();
+        self.nodes.push(PgnNode {
+            parent: Some(parent),
+            children: vec![],
+            history_move: Some(m),
+            san,
+            game,
+            nags: vec![],
+            comments_before: vec![],
+            comments: vec![],
+        });
+        self.nodes[parent].children.push(index);
+        Ok(index)
+    }
+
+    pub fn mainline(&self) -> Vec<usize> {
+        let mut line = vec![];
+        let mut index = 0;
+        while let Some(&child) = self.nodes[index].children.first() {
+            line.push(child);
+            index = child;
+        }
+        line
+    }
+
+    pub fn mainline_moves(&self) -> Vec<Move> {
+        self.mainline()
+            .iter()
+            .filter_map(|&i| self.nodes[i].history_move)
+            .collect()
+    }
+
+    // The position at the end of the main line
+    pub fn end(&self) -> &Game {
+        let last = self.mainline().last().copied().unwrap_or(0);
+        &self.nodes[last].game
+    }
+
+    pub fn from_pgn(text: &str) -> ErrorResult<Self> {
+        let tokens = tokenize(text)?;
+
+        let tags: Vec<(String, String)> = tokens
+            .iter()
+            .filter_map(|token| match token {
+                Token::Tag(name, value) => Some((name.clone(), value.clone())),
+                _ => None,
+            })
+            .collect();
+
+        let fen = tags
+            .iter()
+            .find(|(name, _)| name == "FEN")
+            .map(|(_, fen)| fen.as_str());
+        let start = Game::from_fen(fen.unwrap_or(STARTING_FEN))?;
+
+        let mut pgn = PgnGame::new(start);
+        pgn.tags = tags;
+        if let Some(result) = pgn.tag("Result") {
+            pgn.result = result.to_string();
+        }
+
+        let mut current = 0;
+        let mut variations: Vec<usize> = vec![];
+        let mut comments_before: Vec<String> = vec![];
+        let mut at_variation_start = false;
+
+        for token in tokens {
+            match token {
+                Token::Tag(..) => {}
+                Token::San(san) => {
+                    let game = pgn.nodes[current].game;
+                    let m = move_from_san(&game, &san).map_err(|e| {
+                        err(&format!(
+                            "invalid move {} after {}: {}",
+                            san,
+                            pgn.line_to(current).join(" "),
+                            e.msg
+                        ))
+                    })?;
+                    current = pgn.add_move(current, m)?;
+                    pgn.nodes[current]
+                        .comments_before
+                        .append(&mut comments_before);
+                    at_variation_start = false;
+                }
+                Token::Comment(comment) => {
+                    if at_variation_start {
+                        comments_before.push(comment);
+                    } else {
+                        pgn.nodes[current].comments.push(comment);
+                    }
+                }
+                Token::Nag(nag) => pgn.nodes[current].nags.push(nag),
+                Token::StartVariation => {
+                    let parent = match pgn.nodes[current].parent {
+                        Some(parent) => parent,
+                        None => return err_result("variation before the first move"),
+                    };
+                    variations.push(current);
+                    current = parent;
+                    at_variation_start = true;
+                }
+                Token::EndVariation => {
+                    current = match variations.pop() {
+                        Some(current) => current,
+                        None => return err_result("unmatched ')' in PGN"),
+                    };
+                    at_variation_start = false;
+                }
+                Token::Result(result) => {
+                    pgn.result = result;
+                    break;
+                }
+            }
+        }
+
+        if !variations.is_empty() {
+            return err_result("unterminated variation in PGN");
+        }
+        Ok(pgn)
+    }
+
+    fn line_to(&self, index: usize) -> Vec<String> {
+        let mut line = vec![];
+        let mut index = index;
+        while let Some(parent) = self.nodes[index].parent {
+            line.push(self.nodes[index].san.clone());
+            index = parent;
+        }
+        line.reverse();
+        line
+    }
+
+    fn write_move(&self, words: &mut LineWrapper, index: usize, force_number: bool) {
+        let node = &self.nodes[index];
+        let before = &self.nodes[node.parent.unwrap()].game;
+
+        for comment in &node.comments_before {
+            words.push_comment(comment);
+        }
+
+        let number = before.full_moves_total;
+        if before.player() == Player::White {
+            words.push(&format!("{}.", number));
+        } else if force_number || !node.comments_before.is_empty() {
+            words.push(&format!("{}...", number));
+        }
+        words.push(&node.san);
+
+        for nag in &node.nags {
+            words.push(&format!("${}", nag));
+        }
+        for comment in &node.comments {
+            words.push_comment(comment);
+        }
+    }
+
+    // Writes the main line from `index` onwards, with each move's alternatives in parentheses
+    // right after it
+    fn write_line(&self, words: &mut LineWrapper, index: usize, force_number: bool) {
+        let mut index = index;
+        let mut force_number = force_number;
+        while let Some((&main, variations)) = self.nodes[index].children.split_first() {
+            self.write_move(words, main, force_number);
+            force_number = !self.nodes[main].comments.is_empty();
+
+            for &variation in variations {
+                words.start_variation();
+                self.write_move(words, variation, true);
+                self.write_line(words, variation, !self.nodes[variation].comments.is_empty());
+                words.end_variation();
+                force_number = true;
+            }
+
+            index = main;
+        }
+    }
+
+    pub fn to_pgn(&self) -> String {
+        let mut out = String::new();
+
+        for name in REQUIRED_TAGS {
+            let value = match name {
+                "Result" => self.result.as_str(),
+                _ => self.tag(name).unwrap_or("?"),
+            };
+            out.push_str(&format!("[{} \"{}\"]\n", name, escape_tag_value(value)));
+        }
+
+        let start_fen = self.start().to_fen();
+        let mut tags: Vec<(String, String)> = self
+            .tags
+            .iter()
+            .filter(|(name, _)| !REQUIRED_TAGS.contains(&name.as_str()))
+            .cloned()
+            .collect();
+        if start_fen != STARTING_FEN && self.tag("FEN").is_none() {
+            tags.push(("SetUp".to_string(), "1".to_string()));
+            tags.push(("FEN".to_string(), start_fen));
+        }
+        for (name, value) in tags {
+            out.push_str(&format!("[{} \"{}\"]\n", name, escape_tag_value(&value)));
+        }
+        out.push('\n');
+
+        let mut words = LineWrapper {
+            lines: vec![],
+            line: String::new(),
+            open_variation: false,
+        };
+        for comment in &self.nodes[0].comments {
+            words.push_comment(comment);
+        }
+        self.write_line(&mut words, 0, true);
+        words.push(&self.result);
+
+        for line in words.finish() {
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> ErrorResult<()> {
+        writeln!(writer, "{}", self.to_pgn())
+            .map_err(|e| err(&format!("couldn't write PGN: {}", e)))
+    }
+}
+
+impl Display for PgnGame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_pgn())
+    }
+}
+
+// Reads games from a multi-game PGN one at a time. A new game starts at the first tag line that
+// follows movetext; tag-like lines inside comments are skipped over.
+pub struct PgnReader<R: BufRead> {
+    lines: Lines<R>,
+    pending_line: Option<String>,
+}
+
+impl PgnReader<BufReader<File>> {
+    pub fn open(path: &str) -> ErrorResult<Self> {
+        let file = File::open(path).map_err(|e| err(&format!("couldn't open {}: {}", path, e)))?;
+        Ok(Self::new(BufReader::new(file)))
+    }
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            lines: reader.lines(),
+            pending_line: None,
+        }
+    }
+
+    fn next_line(&mut self) -> ErrorResult<Option<String>> {
+        if let Some(line) = self.pending_line.take() {
+            return Ok(Some(line));
+        }
+        match self.lines.next() {
+            Some(Ok(line)) => Ok(Some(line)),
+            Some(Err(e)) => err_result(&format!("couldn't read PGN: {}", e)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn next_game_text(&mut self) -> ErrorResult<Option<String>> {
+        let mut text = String::new();
+        let mut in_movetext = false;
+        let mut in_comment = false;
+
+        while let Some(line) = self.next_line()? {
+            let trimmed = line.trim();
+            if !in_comment && trimmed.starts_with('[') {
+                if in_movetext {
+                    self.pending_line = Some(line);
+                    break;
+                }
+            } else if !trimmed.is_empty() && !trimmed.starts_with('%') {
+                in_movetext = true;
+                for c in trimmed.chars() {
+                    match c {
+                        '{' if !in_comment => in_comment = true,
+                        '}' if in_comment => in_comment = false,
+                        ';' if !in_comment => break,
+                        _ => {}
+                    }
+                }
+            }
+
+            text.push_str(&line);
+            text.push('\n');
+        }
+
+        if text.trim().is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(text))
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = ErrorResult<PgnGame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_game_text() {
+            Ok(Some(text)) => Some(PgnGame::from_pgn(&text)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+const IMMORTAL_GAME: &str = r#"[Event "London"]
+[Site "London ENG"]
+[Date "1851.06.21"]
+[Round "?"]
+[White "Adolf Anderssen"]
+[Black "Lionel Kieseritzky"]
+[Result "1-0"]
+
+1.e4 e5 2.f4 exf4 3.Bc4 Qh4+ 4.Kf1 b5 5.Bxb5 Nf6 6.Nf3 Qh6 7.d3 Nh5 8.Nh4 Qg5
+9.Nf5 c6 10.g4 Nf6 11.Rg1 cxb5 12.h4 Qg6 13.h5 Qg5 14.Qf3 Ng8 15.Bxf4 Qf6
+16.Nc3 Bc5 17.Nd5 Qxb2 18.Bd6 Bxg1 19.e5 Qxa1+ 20.Ke2 Na6 21.Nxg7+ Kd8
+22.Qf6+ Nxf6 23.Be7# 1-0
+"#;
+
+#[test]
+fn test_pgn_mainline() {
+    let pgn = PgnGame::from_pgn(IMMORTAL_GAME).unwrap();
+    assert_eq!(pgn.tag("White"), Some("Adolf Anderssen"));
+    assert_eq!(pgn.result, "1-0");
+    assert_eq!(pgn.mainline().len(), 45);
+
+    let last = *pgn.mainline().last().unwrap();
+    assert_eq!(pgn.nodes[last].san, "Be7#");
+    assert_eq!(
+        pgn.end().board().to_fen(),
+        "r1bk3r/p2pBpNp/n4n2/1p1NP2P/6P1/3P4/P1P1K3/q5b1 b - -"
+    );
+}
+
+#[test]
+fn test_pgn_variations_comments_and_nags() {
+    let text = r#"[Event "Test"]
+[Result "*"]
+
+{Opening} 1. e4 $1 {best by test} (1. d4 d5 (1... Nf6 2. c4) 2. c4 {Queen's Gambit}) 1... c5!?
+(; a line comment
+1... e5 2. Nf3) 2. Nf3 *"#;
+
+    let pgn = PgnGame::from_pgn(text).unwrap();
+    assert_eq!(pgn.nodes[0].comments, vec!["Opening"]);
+    assert_eq!(pgn.mainline().len(), 3);
+
+    let e4 = pgn.mainline()[0];
+    assert_eq!(pgn.nodes[e4].nags, vec![1]);
+    assert_eq!(pgn.nodes[e4].comments, vec!["best by test"]);
+
+    let c5 = pgn.mainline()[1];
+    assert_eq!(pgn.nodes[c5].nags, vec![5]);
+    assert_eq!(pgn.nodes[e4].children.len(), 2);
+    let e5 = pgn.nodes[e4].children[1];
+    assert_eq!(pgn.nodes[e5].san, "e5");
+    assert_eq!(pgn.nodes[e5].comments_before, vec!["a line comment"]);
+
+    let d4 = pgn.nodes[0].children[1];
+    let d5 = pgn.nodes[d4].children[0];
+    assert_eq!(pgn.nodes[d4].children.len(), 2);
+    assert_eq!(
+        pgn.nodes[pgn.nodes[d5].children[0]].comments,
+        vec!["Queen's Gambit"]
+    );
+
+    let written = pgn.to_pgn();
+    assert!(written.contains("[Event \"Test\"]\n[Site \"?\"]"));
+    let movetext = written.replace('\n', " ");
+    assert!(movetext.contains(
+        "{Opening} 1. e4 $1 {best by test} (1. d4 d5 (1... Nf6 2. c4) 2. c4 {Queen's Gambit}) 1... c5 $5"
+    ));
+    assert!(movetext.contains("({a line comment} 1... e5 2. Nf3) 2. Nf3 *"));
+}
+
+#[test]
+fn test_pgn_castling_with_zeros() {
+    // The move number is glued to castling written with zeros
+    let pgn = PgnGame::from_pgn("1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4.0-0 *").unwrap();
+    assert_eq!(pgn.mainline().len(), 7);
+    assert_eq!(
+        pgn.end().board().to_fen(),
+        "r1bqk1nr/pppp1ppp/2n5/2b1p3/2B1P3/5N2/PPPP1PPP/RNBQ1RK1 b kq -"
+    );
+}
+
+#[test]
+fn test_pgn_round_trip() {
+    let pgn = PgnGame::from_pgn(IMMORTAL_GAME).unwrap();
+    let written = pgn.to_pgn();
+    for line in written.lines() {
+        assert!(line.len() <= MAX_LINE_LENGTH, "line too long: {}", line);
+    }
+
+    let reread = PgnGame::from_pgn(&written).unwrap();
+    assert_eq!(reread.to_pgn(), written);
+    assert_eq!(reread.mainline_moves(), pgn.mainline_moves());
+}
+
+#[test]
+fn test_pgn_from_position() {
+    let text = r#"[FEN "4k3/8/8/8/8/8/4P3/4K3 b - - 0 40"]
+[SetUp "1"]
+
+40... Kd7 41. e4 Kc6 *"#;
+
+    let pgn = PgnGame::from_pgn(text).unwrap();
+    assert_eq!(pgn.mainline().len(), 3);
+    let written = pgn.to_pgn();
+    assert!(written.contains("[FEN \"4k3/8/8/8/8/8/4P3/4K3 b - - 0 40\"]"));
+    assert!(written.contains("40... Kd7 41. e4 Kc6 *"));
+
+    let start = Game::from_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+    let mut pgn = PgnGame::new(start);
+    let m = start.move_from_str("e2e4").unwrap();
+    let e4 = pgn.add_move(0, m).unwrap();
+    assert_eq!(pgn.add_move(0, m).unwrap(), e4);
+    assert!(pgn
+        .to_pgn()
+        .contains("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 w - - 0 1\"]"));
+    assert!(pgn.to_pgn().ends_with("1. e4 *\n"));
+}
+
+#[test]
+fn test_pgn_reader_streams_games() {
+    let text = format!(
+        "{}\n{}\n[Event \"Broken\"]\n\n1. e4 e4 *\n\n{}",
+        IMMORTAL_GAME,
+        "[Event \"Short\"]\n\n1. e4 {[not a tag]\n[still not a tag]} e5 1/2-1/2\n",
+        IMMORTAL_GAME
+    );
+
+    let games: Vec<ErrorResult<PgnGame>> = PgnReader::new(std::io::Cursor::new(text)).collect();
+    assert_eq!(games.len(), 4);
+
+    assert_eq!(games[0].as_ref().unwrap().mainline().len(), 45);
+    let short = games[1].as_ref().unwrap();
+    assert_eq!(short.result, "1/2-1/2");
+    assert_eq!(short.mainline().len(), 2);
+    assert!(games[2].is_err());
+    assert_eq!(
+        games[3].as_ref().unwrap().tag("Black"),
+        Some("Lionel Kieseritzky")
+    );
+}
@@ -0,0 +1,366 @@
+/*
+Proof-number search for forced mates.
+
+Every node tracks how many leaves still have to be proven (the proof number) or disproven
+(the disproof number) before we know whether the attacker can mate from it. Each iteration
+walks down to the most-proving node, expands it, and pushes the new numbers back up. Forcing
+lines have small proof numbers, so deep mates get explored long before alpha-beta would reach
+their depth.
+
+The tree is limited to `max_moves` attacker moves. That makes a disproven root a proof that no
+mate in `max_moves` exists.
+*/
+
+use std::ops::Range;
+
+use crate::{
+    alphabeta::LoopResult,
+    danger::Danger,
+    game::Game,
+    helpers::{err_result, ErrorResult},
+    legal_moves::legal_moves as generate_legal_moves,
+    moves::{Move, MoveOptions},
+    simple_move::SimpleMove,
+    types::Player,
+    zobrist::{IsDraw, ZobristHistory},
+};
+
+const INFINITY: u32 = u32::MAX;
+
+#[derive(Debug, Clone)]
+struct ProofNode {
+    parent: usize,
+    ply: usize,
+    history_move: Option<Move>,
+    children: Option<Range<usize>>,
+    proof: u32,
+    disproof: u32,
+}
+
+impl ProofNode {
+    fn is_proven(&self) -> bool {
+        self.proof == 0
+    }
+    fn is_disproven(&self) -> bool {
+        self.disproof == 0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofResult {
+    Proven,
+    Disproven,
+    Unknown,
+}
+
+#[derive(Debug)]
+pub struct ProofNumberSearch {
+    start_game: Game,
+    attacker: Player,
+    max_plies: usize,
+    max_nodes: usize,
+
+    nodes: Vec<ProofNode>,
+    history: ZobristHistory,
+
+    pub num_expanded: usize,
+}
+
+fn legal_moves(game: &Game) -> ErrorResult<(Vec<Move>, Danger)> {
+    let danger = Danger::from(game.player(), game.bitboards())?;
+
+    let mut moves = vec![];
+    generate_legal_moves(&mut moves, game.player(), game, MoveOptions::default())?;
+
+    Ok((moves, danger))
+}
+
+impl ProofNumberSearch {
+    pub fn new(
+        game: Game,
+        max_moves: usize,
+        max_nodes: usize,
+        starting_history: ZobristHistory,
+    ) -> ErrorResult<Self> {
+        if max_moves == 0 {
+            return err_result("proof number search needs at least one move");
+        }
+
+        let mut search = Self {
+            start_game: game,
+            attacker: game.player(),
+            max_plies: 2 * max_moves - 1,
+            max_nodes,
+            nodes: vec![],
+            history: starting_history,
+            num_expanded: 0,
+        };
+
+        let (proof, disproof) = search.initial_numbers(&game, 0)?;
+        search.nodes.push(ProofNode {
+            parent: 0,
+            ply: 0,
+            history_move: None,
+            children: None,
+            proof,
+            disproof,
+        });
+
+        Ok(search)
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn result(&self) -> ProofResult {
+        let root = &self.nodes[0];
+        if root.is_proven() {
+            ProofResult::Proven
+        } else if root.is_disproven() {
+            ProofResult::Disproven
+        } else {
+            ProofResult::Unknown
+        }
+    }
+
+    // Proof and disproof numbers for a freshly created node. Terminal positions are decided
+    // immediately, everything else is seeded with its mobility so that positions with few
+    // replies get explored first.
+    fn initial_numbers(&self, game: &Game, ply: usize) -> ErrorResult<(u32, u32)> {
+        if ply > 0 && self.history.is_draw() == IsDraw::Yes {
+            return Ok((INFINITY, 0));
+        }
+
+        let (moves, danger) = legal_moves(game)?;
+        let attacker_to_move = game.player() == self.attacker;
+
+        if moves.is_empty() {
+            if danger.check && !attacker_to_move {
+                return Ok((0, INFINITY));
+            }
+            return Ok((INFINITY, 0));
+        }
+
+        // The attacker has used up all of their moves
+        if ply >= self.max_plies {
+            return Ok((INFINITY, 0));
+        }
+
+        let mobility = moves.len() as u32;
+        if attacker_to_move {
+            Ok((1, mobility))
+        } else {
+            Ok((mobility, 1))
+        }
+    }
+
+    fn is_attacker_node(&self, index: usize) -> bool {
+        self.nodes[index].ply.is_multiple_of(2)
+    }
+
+    fn select_child(&self, index: usize) -> ErrorResult<usize> {
+        let node = &self.nodes[index];
+        let children = match &node.children {
+            Some(children) if !children.is_empty() => children.clone(),
+            _ => return err_result("cannot select a child of an unexpanded node"),
+        };
+
+        let child = if self.is_attacker_node(index) {
+            children.min_by_key(|&i| self.nodes[i].proof)
+        } else {
+            children.min_by_key(|&i| self.nodes[i].disproof)
+        };
+        Ok(child.unwrap())
+    }
+
+    fn expand(&mut self, index: usize, game: &Game) -> ErrorResult<()> {
+        let (moves, _) = legal_moves(game)?;
+        let ply = self.nodes[index].ply + 1;
+
+        let start = self.nodes.len();
+        for m in moves {
+            let mut next_game = *game;
+            next_game.make_move(m)?;
+
+            self.history.add(next_game.zobrist());
+            let numbers = self.initial_numbers(&next_game, ply);
+            self.history.pop()?;
+            let (proof, disproof) = numbers?;
+
+            self.nodes.push(ProofNode {
+                parent: index,
+                ply,
+                history_move: Some(m),
+                children: None,
+                proof,
+                disproof,
+            });
+        }
+
+        self.nodes[index].children = Some(start..self.nodes.len());
+        self.num_expanded += 1;
+        Ok(())
+    }
+
+    fn update_numbers(&mut self, index: usize) {
+        let children = match &self.nodes[index].children {
+            Some(children) => children.clone(),
+            None => return,
+        };
+
+        let children = &self.nodes[children];
+        let sum = |numbers: &mut dyn Iterator<Item = u32>| {
+            numbers.fold(0, |total: u32, n| total.saturating_add(n))
+        };
+        let min = |numbers: &mut dyn Iterator<Item = u32>| numbers.min().unwrap_or(INFINITY);
+
+        let (proof, disproof) = if self.is_attacker_node(index) {
+            (
+                min(&mut children.iter().map(|c| c.proof)),
+                sum(&mut children.iter().map(|c| c.disproof)),
+            )
+        } else {
+            (
+                sum(&mut children.iter().map(|c| c.proof)),
+                min(&mut children.iter().map(|c| c.disproof)),
+            )
+        };
+
+        let node = &mut self.nodes[index];
+        node.proof = proof;
+        node.disproof = disproof;
+    }
+
+    pub fn iterate(&mut self) -> ErrorResult<LoopResult> {
+        if self.result() != ProofResult::Unknown || self.nodes.len() >= self.max_nodes {
+            return Ok(LoopResult::Done);
+        }
+
+        // Walk down to the most-proving node
+        let mut index = 0;
+        let mut game = self.start_game;
+        let mut depth = 0;
+        while self.nodes[index].children.is_some() {
+            index = self.select_child(index)?;
+
+            let history_move = self.nodes[index].history_move.unwrap();
+            game.make_move(history_move)?;
+            self.history.add(game.zobrist());
+            depth += 1;
+        }
+
+        let expanded = self.expand(index, &game);
+        for _ in 0..depth {
+            self.history.pop()?;
+        }
+        expanded?;
+
+        // Push the new numbers back up to the root
+        loop {
+            self.update_numbers(index);
+            if index == 0 {
+                break;
+            }
+            index = self.nodes[index].parent;
+        }
+
+        Ok(LoopResult::Continue)
+    }
+
+    // Plies until mate if both sides follow the proof tree: the attacker takes the quickest
+    // proven mate, the defender holds out for the longest one.
+    fn mate_distance(&self, index: usize) -> usize {
+        let children = match &self.nodes[index].children {
+            Some(children) => children.clone(),
+            None => return 0,
+        };
+
+        let distances = children
+            .filter(|&i| self.nodes[i].is_proven())
+            .map(|i| 1 + self.mate_distance(i));
+
+        if self.is_attacker_node(index) {
+            distances.min().unwrap_or(0)
+        } else {
+            distances.max().unwrap_or(0)
+        }
+    }
+
+    pub fn mating_variation(&self) -> Option<Vec<SimpleMove>> {
+        if !self.nodes[0].is_proven() {
+            return None;
+        }
+
+        let mut variation = vec![];
+        let mut index = 0;
+        while let Some(children) = self.nodes[index].children.clone() {
+            let proven = children.filter(|&i| self.nodes[i].is_proven());
+            let next = if self.is_attacker_node(index) {
+                proven.min_by_key(|&i| self.mate_distance(i))
+            } else {
+                proven.max_by_key(|&i| self.mate_distance(i))
+            };
+
+            match next {
+                Some(next) => {
+                    variation.push(SimpleMove::from(&self.nodes[next].history_move.unwrap()));
+                    index = next;
+                }
+                None => break,
+            }
+        }
+
+        Some(variation)
+    }
+}
+
+#[cfg(test)]
+fn run_proof_number_search(fen: &str, max_moves: usize) -> ProofNumberSearch {
+    let mut search = ProofNumberSearch::new(
+        Game::from_fen(fen).unwrap(),
+        max_moves,
+        1_000_000,
+        ZobristHistory::new(),
+    )
+    .unwrap();
+
+    while search.iterate().unwrap() == LoopResult::Continue {}
+    search
+}
+
+#[test]
+fn test_proof_number_back_rank_mate() {
+    let search = run_proof_number_search("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", 1);
+    assert_eq!(search.result(), ProofResult::Proven);
+
+    let variation = search.mating_variation().unwrap();
+    assert_eq!(variation.len(), 1);
+    assert_eq!(variation[0].to_string(), "d1d8");
+}
+
+#[test]
+fn test_proof_number_mate_in_three() {
+    let fen = "3r4/pR2N3/2pkb3/5p2/8/2B5/qP3PPP/4R1K1 w - - 1 0";
+    let search = run_proof_number_search(fen, 3);
+    println!("{} nodes", search.num_nodes());
+    assert_eq!(search.result(), ProofResult::Proven);
+
+    let variation = search.mating_variation().unwrap();
+    assert_eq!(variation.len(), 5);
+    assert_eq!(variation[0].to_string(), "c3e5");
+}
+
+#[test]
+fn test_proof_number_disproves_mate() {
+    // Plenty of material but no way to mate in two
+    let search = run_proof_number_search("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", 2);
+    assert_eq!(search.result(), ProofResult::Disproven);
+    assert_eq!(search.mating_variation(), None);
+}
+
+#[test]
+fn test_proof_number_stalemate_is_not_mate() {
+    let search = run_proof_number_search("7k/8/6Q1/8/8/8/8/K7 b - - 0 1", 1);
+    assert_eq!(search.result(), ProofResult::Disproven);
+}
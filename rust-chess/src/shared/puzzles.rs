@@ -0,0 +1,417 @@
+/*
+Tactics puzzles from played games.
+
+Every main-line position is searched with `score_root_moves`, which gives an exact score for each
+legal move rather than just the best one. A position is a puzzle when exactly one move wins
+decisively: the best move scores at least `win_score` for the side to move, the second best
+doesn't, and the gap between them is at least `min_gap`. Mates are judged on their own, since a
+move that wins a queen is no alternative to one that mates: a mating move is unique when no other
+move mates.
+
+The solution then follows the engine's line. After each reply the solver's next position is
+searched the same way, and the line only continues while the solver's move there is unique too,
+so every move the solver has to find is the only good one at `depth`. The line always ends on a
+solver move: at mate, after `max_solution_plies`, or before a reply that leaves several good moves.
+
+Puzzles are exported as EPD with the first move as bm and the whole line as pv, so the tactics
+suite runner can score the engine on them directly.
+*/
+
+use std::{cell::RefCell, collections::HashSet, io::Write, rc::Rc};
+
+use crate::{
+    epd::Epd,
+    game::Game,
+    helpers::{err_result, ErrorResult},
+    iterative_deepening::{IterativeSearch, IterativeSearchOptions},
+    moves::Move,
+    pgn::{PgnGame, PgnReader},
+    san::moves_to_san,
+    score::Score,
+    simple_move::SimpleMove,
+    transposition_table::TranspositionTable,
+    types::Player,
+    zobrist::{ZobristHash, ZobristHistory},
+};
+
+#[derive(Debug, Clone)]
+pub struct PuzzleOptions {
+    // Search depth for finding puzzles and for checking each move of the solution
+    pub depth: usize,
+    // The solving move must score at least this, and no other move may
+    pub win_score: isize,
+    pub min_gap: isize,
+    // Longest solution, counting both sides' moves
+    pub max_solution_plies: usize,
+    // Positions before this ply are skipped, since openings are rarely puzzles
+    pub min_ply: usize,
+}
+
+impl Default for PuzzleOptions {
+    fn default() -> Self {
+        Self {
+            depth: 5,
+            win_score: 300,
+            min_gap: 300,
+            max_solution_plies: 7,
+            min_ply: 0,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Puzzle {
+    pub game: Game,
+    // The solver's moves and the replies in between, starting and ending with the solver's
+    pub solution: Vec<Move>,
+    // Score of the first move for the side to move
+    pub score: Score,
+    // Centipawns between the first move and the second best move
+    pub gap: isize,
+    // Where the puzzle came from, e.g. "Alice - Bob, ply 23"
+    pub source: String,
+}
+
+impl Puzzle {
+    pub fn to_epd(&self, depth: usize) -> ErrorResult<Epd> {
+        let mut epd = Epd::from_game(&self.game);
+        epd.set_operands("id", vec![self.source.clone()]);
+        epd.set_best_moves(&self.solution[..1])?;
+        epd.set_principal_variation(&self.solution)?;
+        match self.score {
+            Score::WinInN(_, plies) => epd.set_operands("dm", vec![plies.div_ceil(2).to_string()]),
+            _ => epd.set_analysis(depth, self.score.centipawns_for(self.game.player())),
+        }
+        // Keep the move counters so that the full FEN survives
+        epd.set_operands("hmvc", vec![self.game.half_moves_since_pawn_or_capture.to_string()]);
+        epd.set_operands("fmvn", vec![self.game.full_moves_total.to_string()]);
+        Ok(epd)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PuzzleStats {
+    pub games: usize,
+    pub positions: usize,
+    pub puzzles: usize,
+}
+
+impl std::fmt::Display for PuzzleStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} puzzles from {} positions in {} games",
+            self.puzzles, self.positions, self.games
+        )
+    }
+}
+
+fn describe_score(player: Player, score: Score) -> String {
+    match score {
+        Score::WinInN(_, plies) => format!("mate in {}", plies.div_ceil(2)),
+        _ => format!("{}cp", score.centipawns_for(player)),
+    }
+}
+
+// The solver's only winning move
+struct UniqueWin {
+    // The move and the engine's line after it
+    variation: Vec<SimpleMove>,
+    score: Score,
+    gap: isize,
+}
+
+fn find_unique_win(
+    game: &Game,
+    history: &ZobristHistory,
+    tt: &Rc<RefCell<TranspositionTable>>,
+    options: &PuzzleOptions,
+) -> ErrorResult<Option<UniqueWin>> {
+    let mut search = IterativeSearch::new(
+        *game,
+        IterativeSearchOptions {
+            max_depth: Some(options.depth),
+            score_root_moves: true,
+            // A draw is worth nothing to either side in a puzzle
+            contempt: 0,
+            starting_history: history.clone(),
+            transposition_table: Some(tt.clone()),
+            ..IterativeSearchOptions::default()
+        },
+    )?;
+    while !search.done() {
+        search.iterate(&mut |_| {})?;
+    }
+
+    // A forced move isn't much of a puzzle
+    let [(best, score), (_, second), ..] = search.root_move_scores() else {
+        return Ok(None);
+    };
+    let player = game.player();
+    let (best_centipawns, second_centipawns) =
+        (score.centipawns_for(player), second.centipawns_for(player));
+    let gap = best_centipawns - second_centipawns;
+    let unique = match (score, second) {
+        // Any mate will do, so a mate is only unique if nothing else mates at all
+        (Score::WinInN(winner, _), Score::WinInN(second_winner, _))
+            if *winner == player && *second_winner == player =>
+        {
+            false
+        }
+        (Score::WinInN(winner, _), _) if *winner == player => true,
+        _ => {
+            best_centipawns >= options.win_score
+                && second_centipawns < options.win_score
+                && gap >= options.min_gap
+        }
+    };
+    if !unique {
+        return Ok(None);
+    }
+
+    let mut variation = vec![*best];
+    if let Some((bestmove, responses)) = search.bestmove() {
+        if bestmove == *best {
+            variation.extend(responses);
+        }
+    }
+    Ok(Some(UniqueWin {
+        variation,
+        score: *score,
+        gap,
+    }))
+}
+
+// Follows the engine's line from a unique win for as long as the solver's moves stay unique,
+// returning the solution and the positions the solver sees along the way
+fn solve(
+    game: &Game,
+    history: &ZobristHistory,
+    first: UniqueWin,
+    tt: &Rc<RefCell<TranspositionTable>>,
+    options: &PuzzleOptions,
+) -> ErrorResult<(Vec<Move>, Vec<ZobristHash>)> {
+    let mut game = *game;
+    let mut history = history.clone();
+    let mut solution = vec![];
+    let mut seen = vec![game.zobrist()];
+    let mut variation = first.variation;
+
+    loop {
+        let m = game.legal_move(variation[0])?;
+        game.make_move(m)?;
+        history.add(game.zobrist());
+        solution.push(m);
+
+        let Some(&reply) = variation.get(1) else {
+            break;
+        };
+        if solution.len() + 2 > options.max_solution_plies || game.status(&history)?.is_over() {
+            break;
+        }
+
+        let mut next = game;
+        let mut next_history = history.clone();
+        let reply = next.legal_move(reply)?;
+        next.make_move(reply)?;
+        next_history.add(next.zobrist());
+        if next.status(&next_history)?.is_over() {
+            break;
+        }
+        let Some(unique) = find_unique_win(&next, &next_history, tt, options)? else {
+            break;
+        };
+
+        solution.push(reply);
+        seen.push(next.zobrist());
+        (game, history, variation) = (next, next_history, unique.variation);
+    }
+    Ok((solution, seen))
+}
+
+fn source(game: &PgnGame, ply: usize) -> String {
+    format!(
+        "{} - {}, ply {}",
+        game.tag("White").unwrap_or("?"),
+        game.tag("Black").unwrap_or("?"),
+        ply
+    )
+}
+
+// Scans the main line of a game, returning its puzzles and the number of positions searched
+pub fn find_puzzles(
+    pgn: &PgnGame,
+    options: &PuzzleOptions,
+    log: &mut dyn FnMut(&str),
+) -> ErrorResult<(Vec<Puzzle>, usize)> {
+    // Reused for every position of the game
+    let tt = Rc::new(RefCell::new(TranspositionTable::small()));
+    let mut history = ZobristHistory::new();
+    let mut puzzles = vec![];
+    let mut positions = 0;
+    // Positions inside a puzzle's solution, which would only repeat it if played
+    let mut solved: HashSet<ZobristHash> = HashSet::new();
+
+    let mut nodes = vec![0];
+    nodes.extend(pgn.mainline());
+    for (ply, &index) in nodes.iter().enumerate() {
+        let game = pgn.nodes[index].game;
+        history.add(game.zobrist());
+        if ply < options.min_ply
+            || solved.contains(&game.zobrist())
+            || game.status(&history)?.is_over()
+        {
+            continue;
+        }
+
+        positions += 1;
+        let Some(unique) = find_unique_win(&game, &history, &tt, options)? else {
+            continue;
+        };
+        let (score, gap) = (unique.score, unique.gap);
+        let (solution, seen) = solve(&game, &history, unique, &tt, options)?;
+        solved.extend(seen);
+
+        let puzzle = Puzzle {
+            game,
+            solution,
+            score,
+            gap,
+            source: source(pgn, ply),
+        };
+        log(&format!(
+            "{}: {} ({}, gap {})",
+            puzzle.source,
+            moves_to_san(&puzzle.game, &puzzle.solution)?.join(" "),
+            describe_score(game.player(), puzzle.score),
+            puzzle.gap
+        ));
+        puzzles.push(puzzle);
+    }
+    Ok((puzzles, positions))
+}
+
+// Scans every game in a PGN file, writing each puzzle to `out` as an EPD line
+pub fn extract_puzzles(
+    path: &str,
+    options: &PuzzleOptions,
+    out: &mut dyn Write,
+    log: &mut dyn FnMut(&str),
+) -> ErrorResult<PuzzleStats> {
+    let mut stats = PuzzleStats::default();
+    for game in PgnReader::open(path)? {
+        let (puzzles, positions) = find_puzzles(&game?, options, log)?;
+        stats.games += 1;
+        stats.positions += positions;
+        stats.puzzles += puzzles.len();
+
+        for puzzle in &puzzles {
+            if let Err(e) = writeln!(out, "{}", puzzle.to_epd(options.depth)?) {
+                return err_result(&format!("couldn't write puzzle: {}", e));
+            }
+        }
+    }
+    Ok(stats)
+}
+
+#[test]
+fn test_extract_puzzles() {
+    // After Tarrasch's trap, 16. Bg5 with 17. Be7 to follow wins the exchange
+    let pgn = r#"[Event "Dresden"]
+[Date "1892.??.??"]
+[White "Siegbert Tarrasch"]
+[Black "Georg Marco"]
+[Result "1-0"]
+
+1. e4 e5 2. Nf3 Nc6 3. Bb5 d6 4. d4 Bd7 5. Nc3 Nf6 6. O-O Be7 7. Re1 O-O 8. Bxc6 Bxc6
+9. dxe5 dxe5 10. Qxd8 Raxd8 11. Nxe5 Bxe4 12. Nxe4 Nxe4 13. Nd3 f5 14. f3 Bc5+ 15. Nxc5
+Nxc5 16. Bg5 Rd5 17. Be7 Re8 18. c4 1-0
+"#;
+    let path = std::env::temp_dir().join("rust-chess-test-extract-puzzles.pgn");
+    std::fs::write(&path, pgn).unwrap();
+
+    // Only the ending, since the opening takes long to search
+    let options = PuzzleOptions {
+        win_score: 150,
+        min_gap: 100,
+        min_ply: 30,
+        ..PuzzleOptions::default()
+    };
+    let mut out = vec![];
+    let mut lines = vec![];
+    let stats = extract_puzzles(path.to_str().unwrap(), &options, &mut out, &mut |line| {
+        lines.push(line.to_string())
+    })
+    .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!((stats.games, stats.positions, stats.puzzles), (1, 6, 1));
+    assert_eq!(lines.len(), 1);
+    assert!(lines[0].starts_with("Siegbert Tarrasch - Georg Marco, ply 30: Bg5 "));
+
+    let out = String::from_utf8(out).unwrap();
+    println!("{}", out);
+    let epd = Epd::from(out.trim()).unwrap();
+    assert_eq!(
+        epd.game().unwrap().to_fen(),
+        "3r1rk1/ppp3pp/8/2n2p2/8/5P2/PPP3PP/R1B1R1K1 w - - 0 16"
+    );
+    assert_eq!(epd.best_moves().unwrap()[0].to_uci(), "c1g5");
+    assert_eq!(epd.id(), Some("Siegbert Tarrasch - Georg Marco, ply 30"));
+    // Winning material isn't a mate
+    assert_eq!(epd.direct_mate().unwrap(), None);
+}
+
+#[test]
+fn test_find_unique_win() {
+    let tt = Rc::new(RefCell::new(TranspositionTable::small()));
+    let options = PuzzleOptions {
+        depth: 3,
+        ..PuzzleOptions::default()
+    };
+    let unique = |fen: &str| {
+        let game = Game::from_fen(fen).unwrap();
+        let mut history = ZobristHistory::new();
+        history.add(game.zobrist());
+        find_unique_win(&game, &history, &tt, &options).unwrap()
+    };
+
+    // Only Rd8 mates
+    let win = unique("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1").unwrap();
+    assert_eq!(win.variation[0].to_string(), "d1d8");
+
+    // Either rook mates, so there's nothing unique to find
+    assert!(unique("6k1/5ppp/8/8/8/8/5PPP/R2R2K1 w - - 0 1").is_none());
+
+    // Re8 mates, and every other move lets black mate first
+    let win = unique("6k1/5ppp/8/8/8/2p1R3/PPqn4/K7 w - - 0 1").unwrap();
+    assert_eq!(win.variation[0].to_string(), "e3e8");
+
+    // Only the skewer Rh8+ wins the queen
+    let win = unique("q3k3/8/8/8/8/8/8/4K2R w - - 0 1").unwrap();
+    assert_eq!(win.variation[0].to_string(), "h1h8");
+    assert!(matches!(win.score, Score::Centipawns(..)));
+    assert!(win.gap >= options.min_gap);
+
+    // Level positions have no winning move at all
+    assert!(unique("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").is_none());
+}
+
+#[test]
+fn test_puzzle_solution_continues_while_unique() {
+    // Smothered mate, where the queen sacrifice and the knight mate are each the only move
+    let fen = "5r1k/6pp/7N/8/8/1Q6/6PP/6K1 w - - 0 1";
+    let pgn = PgnGame::from_pgn(&format!("[FEN \"{}\"]\n[SetUp \"1\"]\n\n*", fen)).unwrap();
+    let (puzzles, _) = find_puzzles(&pgn, &PuzzleOptions::default(), &mut |_| {}).unwrap();
+    assert_eq!(puzzles.len(), 1);
+    let sans = moves_to_san(&puzzles[0].game, &puzzles[0].solution).unwrap();
+    assert_eq!(sans, ["Qg8+", "Rxg8", "Nf7#"]);
+
+    // Too short a solution to fit the whole line keeps just the first move
+    let options = PuzzleOptions {
+        max_solution_plies: 2,
+        ..PuzzleOptions::default()
+    };
+    let (puzzles, _) = find_puzzles(&pgn, &options, &mut |_| {}).unwrap();
+    assert_eq!(puzzles[0].solution.len(), 1);
+}
@@ -0,0 +1,347 @@
+/*
+Standard Algebraic Notation.
+
+Formatting needs the position the move is played from: the moving piece is only named by its
+type, so other pieces of that type that can reach the same square force a disambiguating file
+or rank, and the check and mate suffixes depend on the replies.
+
+Parsing is tolerant of what shows up in real PGN files: missing or extra check suffixes,
+annotation glyphs, "0-0" for castling, "e.p." after en passant captures, promotions with or
+without "=", and plain coordinate moves.
+*/
+
+use crate::{
+    bitboard::{file_from_char, file_to_char, rank_from_char, rank_to_char, BoardIndex},
+    danger::Danger,
+    game::Game,
+    helpers::{err_result, ErrorResult},
+    moves::{Move, MoveType, Quiet},
+    types::{Piece, PROMOTION_PIECES},
+};
+
+fn piece_letter(piece: Piece) -> char {
+    piece.to_uci().chars().next().unwrap().to_ascii_uppercase()
+}
+
+fn piece_from_letter(c: char) -> Option<Piece> {
+    match c {
+        'N' | 'B' | 'R' | 'Q' | 'K' => Piece::from(c),
+        _ => None,
+    }
+}
+
+fn castling_san(m: &Move) -> Option<&'static str> {
+    match m.move_type {
+        // The king doesn't always move in Chess960, but the rook tells the side apart
+        MoveType::Quiet(Quiet::Castle { rook_start, .. })
+            if rook_start.file() > m.start_index.file() =>
+        {
+            Some("O-O")
+        }
+        MoveType::Quiet(Quiet::Castle { .. }) => Some("O-O-O"),
+        _ => None,
+    }
+}
+
+// Other pieces of the same type that could also move to the same square. The file is preferred,
+// then the rank, and only if neither is unique both.
+fn disambiguation(m: &Move, legal: &[Move]) -> String {
+    let rivals: Vec<&Move> = legal
+        .iter()
+        .filter(|other| {
+            other.piece == m.piece
+                && other.end_index == m.end_index
+                && other.start_index != m.start_index
+        })
+        .collect();
+
+    if rivals.is_empty() {
+        return String::new();
+    }
+
+    let file = file_to_char(m.start_index.file());
+    let rank = rank_to_char(m.start_index.rank());
+    if rivals.iter().all(|r| r.start_index.file() != m.start_index.file()) {
+        file.to_string()
+    } else if rivals.iter().all(|r| r.start_index.rank() != m.start_index.rank()) {
+        rank.to_string()
+    } else {
+        format!("{}{}", file, rank)
+    }
+}
+
+fn check_suffix(game: &Game, m: &Move) -> ErrorResult<&'static str> {
+    let mut next_game = *game;
+    next_game.make_move(*m)?;
+
+    let danger = Danger::from(next_game.player(), next_game.bitboards())?;
+    if !danger.check {
+        return Ok("");
+    }
+    if !next_game.has_legal_move()? {
+        Ok("#")
+    } else {
+        Ok("+")
+    }
+}
+
+pub fn move_to_san(game: &Game, m: &Move) -> ErrorResult<String> {
+    let legal = game.legal_moves()?;
+    if !legal.contains(m) {
+        return err_result(&format!("{} is not a legal move in {}", m, game.to_fen()));
+    }
+
+    let mut san = String::new();
+    if let Some(castle) = castling_san(m) {
+        san.push_str(castle);
+    } else {
+        let is_capture = matches!(m.move_type, MoveType::Capture(_));
+        if m.piece.piece == Piece::Pawn {
+            if is_capture {
+                san.push(file_to_char(m.start_index.file()));
+            }
+        } else {
+            san.push(piece_letter(m.piece.piece));
+            san.push_str(&disambiguation(m, &legal));
+        }
+        if is_capture {
+            san.push('x');
+        }
+        san.push_str(&m.end_index.to_string());
+        if let Some(promotion) = m.promotion {
+            san.push('=');
+            san.push(piece_letter(promotion));
+        }
+    }
+
+    san.push_str(check_suffix(game, m)?);
+    Ok(san)
+}
+
+// Turns a list of moves played from `game` into SAN, advancing the position as it goes
+pub fn moves_to_san(game: &Game, moves: &[Move]) -> ErrorResult<Vec<String>> {
+    let mut game = *game;
+    let mut sans = Vec::with_capacity(moves.len());
+    for m in moves {
+        sans.push(move_to_san(&game, m)?);
+        game.make_move(*m)?;
+    }
+    Ok(sans)
+}
+
+fn strip_annotations(san: &str) -> &str {
+    let annotations = ['+', '#', '!', '?'];
+    let san = san.trim().trim_end_matches(annotations);
+    let san = san
+        .strip_suffix("e.p.")
+        .or_else(|| san.strip_suffix("ep"))
+        .unwrap_or(san);
+    san.trim_end().trim_end_matches(annotations)
+}
+
+fn parse_castling(san: &str) -> Option<bool> {
+    let normalized: String = san
+        .chars()
+        .map(|c| if c == '0' || c == 'o' { 'O' } else { c })
+        .collect();
+    match normalized.as_str() {
+        "O-O" => Some(true),
+        "O-O-O" => Some(false),
+        _ => None,
+    }
+}
+
+pub fn move_from_san(game: &Game, san: &str) -> ErrorResult<Move> {
+    let original = san;
+    let san = strip_annotations(san);
+    let legal = game.legal_moves()?;
+
+    if let Some(m) = legal.iter().find(|m| m.to_uci() == san) {
+        return Ok(*m);
+    }
+
+    if let Some(kingside) = parse_castling(san) {
+        return match legal.iter().find(|m| match castling_san(m) {
+            Some(castle) => (castle == "O-O") == kingside,
+            None => false,
+        }) {
+            Some(m) => Ok(*m),
+            None => err_result(&format!("castling {} is not legal", original)),
+        };
+    }
+
+    let mut chars: Vec<char> = san.chars().filter(|&c| !matches!(c, 'x' | ':' | '-')).collect();
+
+    // Promotion, either as "e8=Q", "e8Q" or "e8q"
+    let mut promotion = None;
+    if let Some(&last) = chars.last() {
+        let before_last = chars.len().checked_sub(2).map(|i| chars[i]);
+        let promotion_piece = Piece::from(last).filter(|p| PROMOTION_PIECES.contains(p));
+        if promotion_piece.is_some() && matches!(before_last, Some('=' | '1' | '8')) {
+            promotion = promotion_piece;
+            chars.pop();
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+        }
+    }
+
+    let piece = match chars.first().and_then(|&c| piece_from_letter(c)) {
+        Some(piece) => {
+            chars.remove(0);
+            piece
+        }
+        None => Piece::Pawn,
+    };
+
+    if chars.len() < 2 || chars.len() > 4 {
+        return err_result(&format!("couldn't parse SAN move {}", original));
+    }
+    let end: String = chars[chars.len() - 2..].iter().collect();
+    let end = BoardIndex::from_str(&end)?;
+
+    let mut from_file = None;
+    let mut from_rank = None;
+    for &c in &chars[..chars.len() - 2] {
+        if let Some(file) = file_from_char(c) {
+            from_file = Some(file);
+        } else if let Some(rank) = rank_from_char(c) {
+            from_rank = Some(rank);
+        } else {
+            return err_result(&format!("couldn't parse SAN move {}", original));
+        }
+    }
+
+    let candidates: Vec<&Move> = legal
+        .iter()
+        .filter(|m| {
+            m.piece.piece == piece
+                && m.end_index == end
+                && m.promotion == promotion
+                && castling_san(m).is_none()
+                && from_file.is_none_or(|file| m.start_index.file() == file)
+                && from_rank.is_none_or(|rank| m.start_index.rank() == rank)
+        })
+        .collect();
+
+    match candidates[..] {
+        [m] => Ok(*m),
+        [] => err_result(&format!(
+            "{} is not a legal move in {}",
+            original,
+            game.to_fen()
+        )),
+        _ => err_result(&format!(
+            "{} is ambiguous in {}",
+            original,
+            game.to_fen()
+        )),
+    }
+}
+
+#[cfg(test)]
+fn san_for(fen: &str, uci: &str) -> String {
+    let game = Game::from_fen(fen).unwrap();
+    let m = game.move_from_str(uci).unwrap();
+    move_to_san(&game, &m).unwrap()
+}
+
+#[test]
+fn test_move_to_san() {
+    let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    assert_eq!(san_for(start, "e2e4"), "e4");
+    assert_eq!(san_for(start, "g1f3"), "Nf3");
+
+    // Castling on both sides, with check
+    let castles = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+    assert_eq!(san_for(castles, "e1g1"), "O-O");
+    assert_eq!(san_for(castles, "e1c1"), "O-O-O");
+    assert_eq!(san_for("4k3/8/8/8/8/8/8/4K2R w K - 0 1", "e1g1"), "O-O");
+    assert_eq!(san_for("5k2/8/8/8/8/8/8/4K2R w K - 0 1", "e1g1"), "O-O+");
+
+    // Pawn captures, en passant and promotions
+    let pawns = "1n2k3/P7/8/3pP3/8/8/8/4K3 w - d6 0 1";
+    assert_eq!(san_for(pawns, "e5d6"), "exd6");
+    assert_eq!(san_for(pawns, "a7a8q"), "a8=Q");
+    assert_eq!(san_for(pawns, "a7b8n"), "axb8=N");
+
+    // Mate
+    assert_eq!(san_for("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", "d1d8"), "Rd8#");
+}
+
+#[test]
+fn test_san_disambiguation() {
+    // Knights on b1 and f3 can both reach d2, rooks on a1 and a5 can both reach a3
+    let fen = "4k3/8/8/R7/8/5N2/8/RN2K3 w - - 0 1";
+    assert_eq!(san_for(fen, "b1d2"), "Nbd2");
+    assert_eq!(san_for(fen, "a1a3"), "R1a3");
+    assert_eq!(san_for(fen, "a5a3"), "R5a3");
+    assert_eq!(san_for(fen, "a5h5"), "Rh5");
+    assert_eq!(san_for("4k3/8/8/8/8/8/8/KQ1Q4 w - - 0 1", "b1c2"), "Qbc2");
+
+    // Three queens attacking e1 need the full square for one of them
+    let fen = "8/8/1k6/8/4Q2Q/8/8/K6Q w - - 0 1";
+    assert_eq!(san_for(fen, "h4e1"), "Qh4e1");
+    assert_eq!(san_for(fen, "h1e1"), "Q1e1");
+    assert_eq!(san_for(fen, "e4e1"), "Qee1");
+}
+
+#[test]
+fn test_move_from_san() {
+    let parse = |fen: &str, san: &str| {
+        let game = Game::from_fen(fen).unwrap();
+        move_from_san(&game, san).map(|m| m.to_uci())
+    };
+
+    let fen = "r3k2r/pppq1ppp/2n5/3pP3/8/5N2/PPPN1PPP/R3K2R w KQkq d6 0 1";
+    assert!(parse(fen, "Nbd7").is_err());
+    assert_eq!(parse(fen, "exd6e.p.").unwrap(), "e5d6");
+    assert_eq!(parse(fen, "exd6 e.p.").unwrap(), "e5d6");
+    assert_eq!(parse(fen, "e5xd6").unwrap(), "e5d6");
+    assert_eq!(parse(fen, "O-O-O").unwrap(), "e1c1");
+    assert_eq!(parse(fen, "0-0").unwrap(), "e1g1");
+    assert_eq!(parse(fen, "O-O+!?").unwrap(), "e1g1");
+    assert_eq!(parse(fen, "Nd4").unwrap(), "f3d4");
+    assert_eq!(parse(fen, "Nfd4").unwrap(), "f3d4");
+    assert!(parse(fen, "Nbd4").is_err());
+    assert_eq!(parse(fen, "Nb3").unwrap(), "d2b3");
+    assert!(parse(fen, "e2e4").is_err());
+    assert_eq!(parse(fen, "a2a4").unwrap(), "a2a4");
+
+    let fen = "r1bqkb1r/pppn1ppp/4pn2/3p4/2PP4/2N2N2/PP2PPPP/R1BQKB1R b KQkq - 0 1";
+    assert!(parse(fen, "Nbd7").is_err());
+    let fen = "rnbqkb1r/ppp2ppp/4pn2/3p4/2PP4/2N2N2/PP2PPPP/R1BQKB1R b KQkq - 0 1";
+    assert_eq!(parse(fen, "Nbd7").unwrap(), "b8d7");
+    assert_eq!(parse(fen, "Nfd7").unwrap(), "f6d7");
+    assert!(parse(fen, "Nd7").is_err());
+    assert_eq!(parse(fen, "dxc4").unwrap(), "d5c4");
+    assert_eq!(parse(fen, "Bb4+").unwrap(), "f8b4");
+
+    let fen = "1n2k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+    assert_eq!(parse(fen, "a8=Q").unwrap(), "a7a8q");
+    assert_eq!(parse(fen, "a8Q").unwrap(), "a7a8q");
+    assert_eq!(parse(fen, "axb8=n").unwrap(), "a7b8n");
+    assert!(parse(fen, "a8").is_err());
+}
+
+#[test]
+fn test_san_round_trip() {
+    let fens = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        "8/8/1k6/8/4Q2Q/8/8/K6Q w - - 0 1",
+    ];
+
+    for fen in fens {
+        let game = Game::from_fen(fen).unwrap();
+        let legal = game.legal_moves().unwrap();
+        let mut sans = std::collections::HashSet::new();
+        for m in &legal {
+            let san = move_to_san(&game, m).unwrap();
+            assert_eq!(move_from_san(&game, &san).unwrap(), *m, "{} in {}", san, fen);
+            assert!(sans.insert(san.clone()), "{} is not unique in {}", san, fen);
+        }
+    }
+}
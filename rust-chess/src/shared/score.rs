@@ -1,11 +1,44 @@
 use crate::types::Player;
 use std::fmt::Display;
 
+// The engine's default willingness to avoid a draw, in centipawns
+pub const DEFAULT_CONTEMPT: isize = 50;
+
+// What a mate is worth in centipawns, less a centipawn per ply so faster mates score higher
+pub const MATE_CENTIPAWNS: isize = 10_000;
+
+// Contempt is how many centipawns `player` (the engine's side) would give up to avoid a draw.
+// Positive values avoid draws and negative values seek them. The opponent sees the draw the
+// other way round, so both sides of a search agree on what it's worth.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Contempt {
+    pub player: Player,
+    // Small enough to keep `Score`, and so every transposition table entry, at 16 bytes
+    pub centipawns: i16,
+}
+
+impl Contempt {
+    pub fn new(player: Player, centipawns: isize) -> Self {
+        Self {
+            player,
+            centipawns: centipawns.clamp(i16::MIN as isize, i16::MAX as isize) as i16,
+        }
+    }
+
+    pub fn draw_centipawns(&self, for_player: Player) -> isize {
+        if for_player == self.player {
+            -(self.centipawns as isize)
+        } else {
+            self.centipawns as isize
+        }
+    }
+}
+
 #[derive(Clone, Copy, Eq, PartialEq)]
 pub enum Score {
     Centipawns(Player, isize),
     WinInN(Player, usize),
-    DrawInN(usize),
+    DrawInN(Contempt, usize),
 }
 
 impl Default for Score {
@@ -22,7 +55,7 @@ impl Display for Score {
                 Player::Black => write!(f, "{}", -score),
             },
             Score::WinInN(player, n) => write!(f, "{} wins +{} mate", player.to_fen(), n),
-            Score::DrawInN(n) => write!(f, "draw +{}", n),
+            Score::DrawInN(_, n) => write!(f, "draw +{}", n),
         }
     }
 }
@@ -47,18 +80,33 @@ impl Score {
                 Score::WinInN(for_player.other(), 0),
                 Score::WinInN(for_player, 0),
             ),
-            Score::DrawInN(_) => (
+            Score::DrawInN(..) => (
                 Score::WinInN(for_player.other(), 0),
                 Score::WinInN(for_player, 0),
             ),
         }
     }
 
+    // Centipawns from `player`'s point of view, with mates worth about `MATE_CENTIPAWNS` and
+    // draws worth nothing
+    pub fn centipawns_for(self, player: Player) -> isize {
+        let (scorer, centipawns) = match self {
+            Score::Centipawns(scorer, centipawns) => (scorer, centipawns),
+            Score::WinInN(winner, plies) => (winner, MATE_CENTIPAWNS - plies as isize),
+            Score::DrawInN(..) => return 0,
+        };
+        if scorer == player {
+            centipawns
+        } else {
+            -centipawns
+        }
+    }
+
     pub fn increment_turns(self) -> Self {
         let mut new_score = self;
         match new_score {
             Score::WinInN(_, ref mut i) => *i += 1,
-            Score::DrawInN(ref mut i) => *i += 1,
+            Score::DrawInN(_, ref mut i) => *i += 1,
             Score::Centipawns(..) => {}
         }
         new_score
@@ -83,6 +131,13 @@ impl Comparison {
     pub fn is_worse(self) -> bool {
         self == Comparison::Worse
     }
+    pub fn ordering(self) -> std::cmp::Ordering {
+        match self {
+            Comparison::Better => std::cmp::Ordering::Greater,
+            Comparison::Worse => std::cmp::Ordering::Less,
+            Comparison::Equal | Comparison::Unknown => std::cmp::Ordering::Equal,
+        }
+    }
 }
 
 impl Score {
@@ -102,13 +157,13 @@ impl Score {
                     Some((-99999 + *n as isize, 0))
                 }
             }
-            Score::DrawInN(_) => Some((0, -50)),
+            Score::DrawInN(contempt, _) => Some((0, contempt.draw_centipawns(current_player))),
         }
     }
 
     fn is_draw(&self) -> bool {
         match self {
-            Score::DrawInN(_) => true,
+            Score::DrawInN(..) => true,
             _ => false,
         }
     }
@@ -216,7 +271,7 @@ fn test_evaluation_comparison() {
     assert_eq!(
         Score::compare(
             Player::White,
-            Score::DrawInN(0),
+            Score::DrawInN(Contempt::new(Player::White, DEFAULT_CONTEMPT), 0),
             Score::Centipawns(Player::Black, 20),
         ),
         Comparison::Worse
@@ -224,7 +279,7 @@ fn test_evaluation_comparison() {
     assert_eq!(
         Score::compare(
             Player::White,
-            Score::DrawInN(0),
+            Score::DrawInN(Contempt::new(Player::White, DEFAULT_CONTEMPT), 0),
             Score::Centipawns(Player::Black, 500),
         ),
         Comparison::Better
@@ -232,7 +287,7 @@ fn test_evaluation_comparison() {
     assert_eq!(
         Score::compare(
             Player::White,
-            Score::DrawInN(0),
+            Score::DrawInN(Contempt::new(Player::White, DEFAULT_CONTEMPT), 0),
             Score::Centipawns(Player::White, -500),
         ),
         Comparison::Better
@@ -243,7 +298,7 @@ fn test_evaluation_comparison() {
         Score::compare(
             Player::Black,
             Score::Centipawns(Player::Black, 100),
-            Score::DrawInN(0),
+            Score::DrawInN(Contempt::new(Player::Black, DEFAULT_CONTEMPT), 0),
         ),
         Comparison::Better
     );
@@ -251,14 +306,14 @@ fn test_evaluation_comparison() {
         Score::compare(
             Player::Black,
             Score::Centipawns(Player::Black, 500),
-            Score::DrawInN(0),
+            Score::DrawInN(Contempt::new(Player::Black, DEFAULT_CONTEMPT), 0),
         ),
         Comparison::Better
     );
     assert_eq!(
         Score::compare(
             Player::Black,
-            Score::DrawInN(0),
+            Score::DrawInN(Contempt::new(Player::Black, DEFAULT_CONTEMPT), 0),
             Score::Centipawns(Player::Black, 500),
         ),
         Comparison::Worse
@@ -268,7 +323,7 @@ fn test_evaluation_comparison() {
     assert_eq!(
         Score::compare(
             Player::Black,
-            Score::DrawInN(0),
+            Score::DrawInN(Contempt::new(Player::Black, DEFAULT_CONTEMPT), 0),
             Score::WinInN(Player::Black, 1),
         ),
         Comparison::Worse
@@ -276,7 +331,7 @@ fn test_evaluation_comparison() {
     assert_eq!(
         Score::compare(
             Player::Black,
-            Score::DrawInN(0),
+            Score::DrawInN(Contempt::new(Player::Black, DEFAULT_CONTEMPT), 0),
             Score::WinInN(Player::White, 1),
         ),
         Comparison::Better
@@ -313,3 +368,68 @@ fn test_evaluation_increment() {
         Comparison::Worse
     );
 }
+
+#[test]
+fn test_contempt() {
+    let avoid_draws = Score::DrawInN(Contempt::new(Player::White, 100), 0);
+    let seek_draws = Score::DrawInN(Contempt::new(Player::White, -100), 0);
+
+    // White would rather be a little behind than draw, black is happy to take the draw
+    assert_eq!(
+        Score::compare(
+            Player::White,
+            Score::Centipawns(Player::White, -50),
+            avoid_draws
+        ),
+        Comparison::Better
+    );
+    assert_eq!(
+        Score::compare(
+            Player::Black,
+            Score::Centipawns(Player::Black, 50),
+            avoid_draws
+        ),
+        Comparison::Worse
+    );
+
+    // With negative contempt white takes a draw over a small advantage
+    assert_eq!(
+        Score::compare(
+            Player::White,
+            Score::Centipawns(Player::White, 50),
+            seek_draws
+        ),
+        Comparison::Worse
+    );
+    assert_eq!(
+        Score::compare(
+            Player::Black,
+            Score::Centipawns(Player::Black, -50),
+            seek_draws
+        ),
+        Comparison::Better
+    );
+
+    assert_eq!(Contempt::new(Player::Black, 100_000).centipawns, i16::MAX);
+    assert_eq!(std::mem::size_of::<Score>(), 16);
+}
+
+#[test]
+fn test_centipawns_for() {
+    let score = Score::Centipawns(Player::Black, 120);
+    assert_eq!(score.centipawns_for(Player::Black), 120);
+    assert_eq!(score.centipawns_for(Player::White), -120);
+
+    // Faster mates are worth more, and any mate beats any material edge
+    let mate_in_one = Score::WinInN(Player::White, 1);
+    let mate_in_two = Score::WinInN(Player::White, 3);
+    assert!(mate_in_one.centipawns_for(Player::White) > mate_in_two.centipawns_for(Player::White));
+    assert!(mate_in_two.centipawns_for(Player::White) > 5000);
+    assert_eq!(
+        mate_in_one.centipawns_for(Player::Black),
+        -mate_in_one.centipawns_for(Player::White)
+    );
+
+    let draw = Score::DrawInN(Contempt::new(Player::White, 50), 4);
+    assert_eq!(draw.centipawns_for(Player::White), 0);
+}
@@ -0,0 +1,330 @@
+/*
+Self-play training data.
+
+Each game starts from the standard position with a few random moves, so no two games follow the
+same line, and then plays every move with a fixed-node `IterativeSearch`. Games end on the usual
+rules or are adjudicated once the score has been decisive, or close to level late in the game, for
+several plies in a row.
+
+The positions reached are written once the game is over, one per line as
+
+    <fen> | <score> | <result>
+
+with the search score in centipawns and the result (1.0, 0.5 or 0.0) both from white's point of
+view. Only quiet positions are written: not in check, with a best move that isn't a capture or a
+promotion and a score that isn't a mate or a forced draw, since those say little about the static
+evaluation of the position.
+*/
+
+use std::{cell::RefCell, io::Write, rc::Rc};
+
+use rand::{seq::SliceRandom, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use crate::{
+    danger::Danger,
+    game::Game,
+    helpers::{err_result, ErrorResult, OptionResult},
+    iterative_deepening::{IterativeSearch, IterativeSearchOptions},
+    score::Score,
+    simple_move::SimpleMove,
+    transposition_table::TranspositionTable,
+    types::Player,
+    zobrist::ZobristHistory,
+};
+
+#[derive(Debug, Clone)]
+pub struct SelfPlayOptions {
+    pub games: usize,
+    pub nodes_per_move: usize,
+    pub random_plies: usize,
+    // Openings scoring more than this after the random moves are thrown away
+    pub max_opening_score: isize,
+    pub seed: u64,
+    // A score at least this big for `win_plies` plies in a row ends the game
+    pub win_score: isize,
+    pub win_plies: usize,
+    // From `draw_min_ply` on, a score within `draw_score` for `draw_plies` plies in a row is a draw
+    pub draw_score: isize,
+    pub draw_plies: usize,
+    pub draw_min_ply: usize,
+    pub max_plies: usize,
+}
+
+impl Default for SelfPlayOptions {
+    fn default() -> Self {
+        Self {
+            games: 100,
+            nodes_per_move: 5_000,
+            random_plies: 8,
+            max_opening_score: 300,
+            seed: 0,
+            win_score: 1_000,
+            win_plies: 4,
+            draw_score: 10,
+            draw_plies: 8,
+            draw_min_ply: 60,
+            max_plies: 400,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SelfPlayStats {
+    pub games: usize,
+    pub white_wins: usize,
+    pub draws: usize,
+    pub black_wins: usize,
+    pub adjudicated: usize,
+    pub positions: usize,
+    pub filtered: usize,
+}
+
+impl std::fmt::Display for SelfPlayStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} games (+{} ={} -{}, {} adjudicated), {} positions written, {} filtered",
+            self.games,
+            self.white_wins,
+            self.draws,
+            self.black_wins,
+            self.adjudicated,
+            self.positions,
+            self.filtered
+        )
+    }
+}
+
+struct SearchResult {
+    bestmove: SimpleMove,
+    score: Score,
+}
+
+fn search(
+    game: &Game,
+    history: &ZobristHistory,
+    tt: &Rc<RefCell<TranspositionTable>>,
+    options: &SelfPlayOptions,
+) -> ErrorResult<SearchResult> {
+    let mut search = IterativeSearch::new(
+        *game,
+        IterativeSearchOptions {
+            max_nodes: Some(options.nodes_per_move),
+            // Draws are worth the same to both sides in training data
+            contempt: 0,
+            starting_history: history.clone(),
+            transposition_table: Some(tt.clone()),
+            ..IterativeSearchOptions::default()
+        },
+    )?;
+    while !search.done() {
+        search.iterate(&mut |_| {})?;
+    }
+
+    let (bestmove, _) = search.bestmove().as_result()?;
+    let score = search.best_score().as_result()?;
+    Ok(SearchResult { bestmove, score })
+}
+
+// Centipawns for white, `None` for mate and draw scores
+fn white_centipawns(score: Score) -> Option<isize> {
+    match score {
+        Score::Centipawns(..) => Some(score.centipawns_for(Player::White)),
+        _ => None,
+    }
+}
+
+fn is_quiet(game: &Game, bestmove: SimpleMove) -> ErrorResult<bool> {
+    if Danger::from(game.player(), game.bitboards())?.check {
+        return Ok(false);
+    }
+    let m = game.legal_move(bestmove)?;
+    Ok(m.is_quiet() && m.promotion.is_none())
+}
+
+// Plays random moves from the start position until one leaves a playable, roughly level game.
+// The history of the random moves comes with it, so repetitions count from the start.
+fn random_opening(
+    rng: &mut ChaCha8Rng,
+    options: &SelfPlayOptions,
+) -> ErrorResult<(Game, ZobristHistory)> {
+    let tt = Rc::new(RefCell::new(TranspositionTable::small()));
+    loop {
+        let mut game = Game::from_position_uci("position startpos")?;
+        let mut history = ZobristHistory::new();
+        history.add(game.zobrist());
+
+        let mut playable = true;
+        for _ in 0..options.random_plies {
+            let legal_moves = game.legal_moves()?;
+            let Some(&m) = legal_moves.choose(rng) else {
+                playable = false;
+                break;
+            };
+            game.make_move(m)?;
+            history.add(game.zobrist());
+        }
+        if !playable || game.status(&history)?.is_over() {
+            continue;
+        }
+
+        let result = search(&game, &history, &tt, options)?;
+        let level = white_centipawns(result.score)
+            .is_some_and(|centipawns| centipawns.abs() <= options.max_opening_score);
+        if level {
+            return Ok((game, history));
+        }
+    }
+}
+
+struct Record {
+    fen: String,
+    score: isize,
+}
+
+// Plays one game, returning the quiet positions, the winner (`None` for a draw), and whether the
+// result was adjudicated
+fn play_game(
+    rng: &mut ChaCha8Rng,
+    options: &SelfPlayOptions,
+    stats: &mut SelfPlayStats,
+) -> ErrorResult<(Vec<Record>, Option<Player>, bool)> {
+    let (mut game, mut history) = random_opening(rng, options)?;
+    let tt = Rc::new(RefCell::new(TranspositionTable::small()));
+
+    let mut records = vec![];
+    let mut win_streak: (Option<Player>, usize) = (None, 0);
+    let mut draw_streak = 0;
+
+    for ply in 0..options.max_plies {
+        let status = game.status(&history)?;
+        if status.is_over() {
+            return Ok((records, status.winner(), false));
+        }
+
+        let result = search(&game, &history, &tt, options)?;
+        let centipawns = white_centipawns(result.score);
+        match centipawns {
+            Some(centipawns) if is_quiet(&game, result.bestmove)? => records.push(Record {
+                fen: game.to_fen(),
+                score: centipawns,
+            }),
+            _ => stats.filtered += 1,
+        }
+
+        // A forced mate is as decisive as it gets
+        if let Score::WinInN(winner, _) = result.score {
+            return Ok((records, Some(winner), true));
+        }
+
+        let leader = match centipawns {
+            Some(centipawns) if centipawns >= options.win_score => Some(Player::White),
+            Some(centipawns) if centipawns <= -options.win_score => Some(Player::Black),
+            _ => None,
+        };
+        win_streak = match (leader, win_streak) {
+            (Some(leader), (Some(previous), plies)) if leader == previous => {
+                (Some(leader), plies + 1)
+            }
+            (Some(leader), _) => (Some(leader), 1),
+            (None, _) => (None, 0),
+        };
+        if win_streak.1 >= options.win_plies {
+            return Ok((records, win_streak.0, true));
+        }
+
+        let level = centipawns.is_some_and(|centipawns| centipawns.abs() <= options.draw_score);
+        draw_streak = if level { draw_streak + 1 } else { 0 };
+        if ply >= options.draw_min_ply && draw_streak >= options.draw_plies {
+            return Ok((records, None, true));
+        }
+
+        game.make_move(game.legal_move(result.bestmove)?)?;
+        history.add(game.zobrist());
+    }
+    Ok((records, None, true))
+}
+
+// Plays `options.games` games, writing the quiet positions of each to `out` when it ends
+pub fn run_self_play(
+    options: &SelfPlayOptions,
+    out: &mut dyn Write,
+    log: &mut dyn FnMut(&str),
+) -> ErrorResult<SelfPlayStats> {
+    let mut rng = ChaCha8Rng::seed_from_u64(options.seed);
+    let mut stats = SelfPlayStats::default();
+
+    for i in 0..options.games {
+        let (records, winner, adjudicated) = play_game(&mut rng, options, &mut stats)?;
+        let result = match winner {
+            Some(Player::White) => {
+                stats.white_wins += 1;
+                "1.0"
+            }
+            Some(Player::Black) => {
+                stats.black_wins += 1;
+                "0.0"
+            }
+            None => {
+                stats.draws += 1;
+                "0.5"
+            }
+        };
+        stats.games += 1;
+        stats.adjudicated += adjudicated as usize;
+        stats.positions += records.len();
+
+        for record in &records {
+            if let Err(e) = writeln!(out, "{} | {} | {}", record.fen, record.score, result) {
+                return err_result(&format!("couldn't write self-play data: {}", e));
+            }
+        }
+        log(&format!(
+            "Game {}/{}: {}, {} positions",
+            i + 1,
+            options.games,
+            result,
+            records.len()
+        ));
+    }
+    Ok(stats)
+}
+
+#[test]
+fn test_self_play() {
+    let options = SelfPlayOptions {
+        games: 2,
+        nodes_per_move: 500,
+        random_plies: 6,
+        max_plies: 40,
+        seed: 7,
+        ..SelfPlayOptions::default()
+    };
+
+    let mut out = vec![];
+    let mut lines = vec![];
+    let stats =
+        run_self_play(&options, &mut out, &mut |line| lines.push(line.to_string())).unwrap();
+    println!("{}", stats);
+    assert_eq!(stats.games, 2);
+    assert_eq!(stats.white_wins + stats.draws + stats.black_wins, 2);
+    assert_eq!(lines.len(), 2);
+
+    let text = String::from_utf8(out).unwrap();
+    assert_eq!(text.lines().count(), stats.positions);
+    assert!(stats.positions > 0);
+    for line in text.lines() {
+        let fields: Vec<&str> = line.split(" | ").collect();
+        assert_eq!(fields.len(), 3, "{}", line);
+        let game = Game::from_fen(fields[0]).unwrap();
+        assert!(!Danger::from(game.player(), game.bitboards()).unwrap().check);
+        fields[1].parse::<isize>().unwrap();
+        assert!(["1.0", "0.5", "0.0"].contains(&fields[2]));
+    }
+
+    // The same seed plays the same games
+    let mut again = vec![];
+    run_self_play(&options, &mut again, &mut |_| {}).unwrap();
+    assert_eq!(String::from_utf8(again).unwrap(), text);
+}
@@ -9,7 +9,7 @@ use rand_chacha::ChaCha8Rng;
 
 use crate::{
     bitboard::{
-        matches_castling, pawn_capture_directions_for_player, pawn_push_direction_for_player,
+        castling_notation_end, matches_castling, pawn_capture_directions_for_player, pawn_push_direction_for_player,
         pawn_push_rank_direction_for_player, single_bitboard, starting_pawns_mask, BoardIndex,
     },
     game::Game,
@@ -112,9 +112,15 @@ impl SimpleMove {
     }
 
     pub fn from(m: &Move) -> Self {
+        let end = match m.move_type {
+            MoveType::Quiet(Quiet::Castle { rook_start, .. }) => {
+                castling_notation_end(m.start_index, m.end_index, rook_start)
+            }
+            _ => m.end_index,
+        };
         Self {
             start: m.start_index,
-            end: m.end_index,
+            end,
             promotion: m.promotion,
         }
     }
@@ -142,30 +148,34 @@ impl SimpleMove {
             return Ok(None);
         }
 
+        // Castle, which can be written as the king taking its own rook
+        if start_piece.piece == Piece::King {
+            let files = game.castling_files()[player];
+            let castling = matches_castling(player, files, start, end)
+                .filter(|&(side, _)| game.can_castle()[player][side]);
+            if let Some((side, req)) = castling {
+                if can_castle_on_side(side, player, game, &req)? {
+                    return Ok(Some(Move {
+                        piece: start_piece,
+                        start_index: start,
+                        end_index: req.king_end,
+                        move_type: MoveType::Quiet(Quiet::Castle {
+                            rook_start: req.rook_start,
+                            rook_end: req.rook_end,
+                        }),
+                        promotion: promo.expect_none(|| {
+                            "promotions not allowed on castling moves".to_string()
+                        })?,
+                    }));
+                } else {
+                    return Ok(None);
+                }
+            }
+        }
+
         // Quiet
         match end_piece {
             None => {
-                // => Castle
-                if start_piece.piece == Piece::King {
-                    if let Some((side, req)) = matches_castling(player, start, end) {
-                        if can_castle_on_side(side, player, game, req)? {
-                            return Ok(Some(Move {
-                                piece: start_piece,
-                                start_index: start,
-                                end_index: end,
-                                move_type: MoveType::Quiet(Quiet::Castle {
-                                    rook_start: req.rook_start,
-                                    rook_end: req.rook_end,
-                                }),
-                                promotion: promo.expect_none(|| {
-                                    "promotions not allowed on castling moves".to_string()
-                                })?,
-                            }));
-                        } else {
-                            return Ok(None);
-                        }
-                    }
-                }
                 if start_piece.piece == Piece::Pawn {
                     // => PawnSkip
                     let pawn_dir = pawn_push_direction_for_player(player).offset();
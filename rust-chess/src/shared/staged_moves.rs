@@ -0,0 +1,250 @@
+/*
+Staged move generation for alpha-beta.
+
+Most nodes that cut off do so on their first or second move, so generating and sorting every move
+up front mostly goes to waste. Moves are handed out in stages instead, and a stage only does its
+work once the ones before it failed to cut off:
+
+1. The transposition table's best move, converted without generating anything.
+2. Captures, ordered by the search's sorter (MVV-LVA). Captures that lose material by static
+   exchange are held back.
+3. Killers: quiet moves that caused a beta cutoff in a sibling position.
+4. The captures that lose material.
+5. The remaining quiet moves.
+
+The hash move and killers come from other positions, so they're only pseudo-legal and have to be
+checked by the caller. Generated moves are legal, and skip whatever the first stages handed out.
+
+Quiescence (including razored nodes) only gets captures. It should never follow a quiet hash move
+or killer, otherwise it never bottoms out.
+*/
+
+use crate::{
+    game::Game,
+    helpers::ErrorResult,
+    legal_moves::legal_moves,
+    move_ordering::static_exchange,
+    moves::{Move, MoveOptions, OnlyCaptures, OnlyQuiets},
+    simple_move::SimpleMove,
+};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    #[default]
+    HashMove,
+    GenerateCaptures,
+    Captures,
+    Killers,
+    LosingCaptures,
+    GenerateQuiets,
+    Quiets,
+    Done,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct StagedMoves {
+    stage: Stage,
+    hash_move: Option<SimpleMove>,
+    killers: [Option<SimpleMove>; 2],
+
+    // The hash move and killers, once handed out
+    tried: Vec<Move>,
+
+    moves: Vec<Move>,
+    losing_captures: Vec<Move>,
+    index: usize,
+}
+
+impl StagedMoves {
+    pub fn reset(&mut self, killers: [Option<SimpleMove>; 2]) {
+        self.stage = Stage::HashMove;
+        self.hash_move = None;
+        self.killers = killers;
+        self.tried.clear();
+        self.moves.clear();
+        self.losing_captures.clear();
+        self.index = 0;
+    }
+
+    // Only has an effect before the first move is handed out
+    pub fn set_hash_move(&mut self, m: SimpleMove) {
+        self.hash_move = Some(m);
+    }
+
+    fn next_untried(&mut self) -> Option<Move> {
+        while self.index < self.moves.len() {
+            let m = self.moves[self.index];
+            self.index += 1;
+            if !self.tried.contains(&m) {
+                return Some(m);
+            }
+        }
+        None
+    }
+
+    // Converts a move from another position, if it's playable here and hasn't been tried yet
+    fn try_special(
+        &mut self,
+        m: Option<SimpleMove>,
+        game: &Game,
+        allowed: fn(&Move) -> bool,
+    ) -> ErrorResult<Option<Move>> {
+        let Some(m) = m else {
+            return Ok(None);
+        };
+        match m.to_move(game)? {
+            Some(m) if allowed(&m) && !self.tried.contains(&m) => {
+                self.tried.push(m);
+                Ok(Some(m))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // The next move to search, and whether it's known to be legal
+    pub fn next<S>(
+        &mut self,
+        game: &Game,
+        options: MoveOptions,
+        sorter: S,
+    ) -> ErrorResult<Option<(Move, bool)>>
+    where
+        S: Fn(&Game, &mut [Move]) -> ErrorResult<()>,
+    {
+        let only_captures = options.only_captures == OnlyCaptures::Yes;
+
+        loop {
+            match self.stage {
+                Stage::HashMove => {
+                    self.stage = Stage::GenerateCaptures;
+                    let allowed: fn(&Move) -> bool = match only_captures {
+                        true => |m| !m.is_quiet(),
+                        false => |_| true,
+                    };
+                    if let Some(m) = self.try_special(self.hash_move, game, allowed)? {
+                        return Ok(Some((m, false)));
+                    }
+                }
+                Stage::GenerateCaptures => {
+                    let capture_options = MoveOptions {
+                        only_captures: OnlyCaptures::Yes,
+                        ..options
+                    };
+                    legal_moves(&mut self.moves, game.player(), game, capture_options)?;
+                    sorter(game, &mut self.moves)?;
+
+                    let bitboards = game.bitboards();
+                    let mut kept = 0;
+                    for i in 0..self.moves.len() {
+                        let m = self.moves[i];
+                        if static_exchange(&m, bitboards) < 0 {
+                            self.losing_captures.push(m);
+                        } else {
+                            self.moves[kept] = m;
+                            kept += 1;
+                        }
+                    }
+                    self.moves.truncate(kept);
+
+                    self.index = 0;
+                    self.stage = Stage::Captures;
+                }
+                Stage::Captures => {
+                    if let Some(m) = self.next_untried() {
+                        return Ok(Some((m, true)));
+                    }
+                    self.stage = Stage::Killers;
+                    self.index = 0;
+                }
+                Stage::Killers => {
+                    if only_captures || self.index >= self.killers.len() {
+                        std::mem::swap(&mut self.moves, &mut self.losing_captures);
+                        self.index = 0;
+                        self.stage = Stage::LosingCaptures;
+                        continue;
+                    }
+                    let killer = self.killers[self.index];
+                    self.index += 1;
+                    if let Some(m) = self.try_special(killer, game, |m| m.is_quiet())? {
+                        return Ok(Some((m, false)));
+                    }
+                }
+                Stage::LosingCaptures => {
+                    if let Some(m) = self.next_untried() {
+                        return Ok(Some((m, true)));
+                    }
+                    self.stage = Stage::GenerateQuiets;
+                }
+                Stage::GenerateQuiets => {
+                    if only_captures {
+                        self.stage = Stage::Done;
+                        continue;
+                    }
+                    let quiet_options = MoveOptions {
+                        only_quiets: OnlyQuiets::Yes,
+                        ..options
+                    };
+                    legal_moves(&mut self.moves, game.player(), game, quiet_options)?;
+                    self.index = 0;
+                    self.stage = Stage::Quiets;
+                }
+                Stage::Quiets => {
+                    if let Some(m) = self.next_untried() {
+                        return Ok(Some((m, true)));
+                    }
+                    self.stage = Stage::Done;
+                }
+                Stage::Done => return Ok(None),
+            }
+        }
+    }
+}
+
+#[test]
+fn test_staged_moves() {
+    use crate::move_ordering::capture_sort;
+    use std::collections::HashSet;
+
+    let sorter = |_: &Game, moves: &mut [Move]| capture_sort(moves);
+    let simple = |s: &str| SimpleMove::from_str(s).unwrap();
+
+    // The queen can take a defended pawn on d6 or an undefended one on h6. A capture isn't
+    // used as a killer, since it's tried with the other captures.
+    let game = Game::from_fen("4k3/2p5/3p3p/8/8/8/3Q2N1/4K3 w - - 0 1").unwrap();
+    let mut staged = StagedMoves::default();
+    staged.reset([Some(simple("d2h6")), Some(simple("g2f4"))]);
+    staged.set_hash_move(simple("e1f1"));
+
+    let mut moves = vec![];
+    while let Some((m, known_legal)) = staged.next(&game, MoveOptions::default(), sorter).unwrap() {
+        moves.push((m.to_uci(), known_legal));
+    }
+
+    let expected_start = [
+        ("e1f1", false),
+        ("d2h6", true),
+        ("g2f4", false),
+        ("d2d6", true),
+    ];
+    let expected_start = expected_start.map(|(m, known_legal)| (m.to_string(), known_legal));
+    assert_eq!(moves[..4], expected_start);
+    assert!(moves[4..].iter().all(|(_, known_legal)| *known_legal));
+
+    let legal: HashSet<String> = game.legal_moves().unwrap().iter().map(|m| m.to_uci()).collect();
+    let staged_set: HashSet<String> = moves.iter().map(|(m, _)| m.clone()).collect();
+    assert_eq!(staged_set, legal);
+    assert_eq!(moves.len(), legal.len());
+
+    // Quiescence skips quiet hash moves and killers
+    staged.reset([Some(simple("g2f4")), None]);
+    staged.set_hash_move(simple("e1f1"));
+    let options = MoveOptions {
+        only_captures: OnlyCaptures::Yes,
+        ..MoveOptions::default()
+    };
+    let mut captures = vec![];
+    while let Some((m, _)) = staged.next(&game, options, sorter).unwrap() {
+        captures.push(m.to_uci());
+    }
+    assert_eq!(captures, ["d2h6", "d2d6"]);
+}
@@ -0,0 +1,173 @@
+use rand::Rng;
+
+use crate::{score::Score, simple_move::SimpleMove, types::Player};
+
+pub const MAX_SKILL_LEVEL: usize = 20;
+
+pub const MIN_ELO: usize = 800;
+pub const MAX_ELO: usize = 2000;
+
+// Each skill level searches a little deeper, looks at more nodes and is less willing to play a
+// worse move. The steps are tuned so that each level beats the one below it by a similar margin
+// in self-play, and the whole range spans about 1450 Elo there.
+//
+// The ratings are not measured against rated players. They spread the levels evenly between
+// MIN_ELO and MAX_ELO, a little narrower than self-play, which exaggerates the differences
+// between versions of one engine.
+const SKILL_LEVELS: [(usize, usize, isize, usize); MAX_SKILL_LEVEL + 1] = [
+    // (max depth, max nodes, score margin, elo)
+    (2, 200, 150, 800),
+    (2, 225, 138, 860),
+    (2, 250, 128, 920),
+    (2, 280, 118, 980),
+    (3, 320, 109, 1040),
+    (3, 360, 100, 1100),
+    (3, 400, 92, 1160),
+    (3, 450, 85, 1220),
+    (3, 500, 79, 1280),
+    (4, 560, 73, 1340),
+    (4, 630, 67, 1400),
+    (4, 710, 62, 1460),
+    (4, 800, 57, 1520),
+    (4, 900, 53, 1580),
+    (5, 1_000, 49, 1640),
+    (5, 1_120, 45, 1700),
+    (5, 1_260, 41, 1760),
+    (5, 1_420, 38, 1820),
+    (5, 1_600, 35, 1880),
+    (6, 1_800, 33, 1940),
+    (6, 2_000, 30, 2000),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StrengthLimit {
+    pub skill_level: usize,
+    pub max_depth: usize,
+    pub max_nodes: usize,
+
+    // Root moves whose score is within this many centipawns of the best one may be played
+    pub score_margin: isize,
+}
+
+impl StrengthLimit {
+    pub fn from_skill_level(skill_level: usize) -> Self {
+        let skill_level = skill_level.min(MAX_SKILL_LEVEL);
+        let (max_depth, max_nodes, score_margin, _) = SKILL_LEVELS[skill_level];
+        Self {
+            skill_level,
+            max_depth,
+            max_nodes,
+            score_margin,
+        }
+    }
+
+    // Picks the strongest level whose rating doesn't exceed `elo`
+    pub fn from_elo(elo: usize) -> Self {
+        let skill_level = SKILL_LEVELS
+            .iter()
+            .rposition(|&(_, _, _, level_elo)| level_elo <= elo)
+            .unwrap_or(0);
+        Self::from_skill_level(skill_level)
+    }
+
+    pub fn elo(&self) -> usize {
+        SKILL_LEVELS[self.skill_level].3
+    }
+
+    // Chooses between root moves that score close to the best one. Closer moves are more
+    // likely to be picked. Forced mates are never thrown away.
+    pub fn choose_move<R: Rng>(
+        &self,
+        player: Player,
+        root_move_scores: &[(SimpleMove, Score)],
+        rng: &mut R,
+    ) -> Option<SimpleMove> {
+        let (best_move, best_score) = *root_move_scores.first()?;
+
+        let best_centipawns = match best_score {
+            Score::WinInN(..) => return Some(best_move),
+            _ if self.score_margin == 0 => return Some(best_move),
+            _ => best_score.centipawns_for(player),
+        };
+
+        let candidates: Vec<(SimpleMove, isize)> = root_move_scores
+            .iter()
+            .filter_map(|&(m, score)| {
+                let loss = best_centipawns - score.centipawns_for(player);
+                if loss <= self.score_margin {
+                    Some((m, self.score_margin - loss + 1))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        let total_weight: isize = candidates.iter().map(|(_, weight)| weight).sum();
+        let mut choice = rng.gen_range(0..total_weight);
+        for (m, weight) in candidates {
+            if choice < weight {
+                return Some(m);
+            }
+            choice -= weight;
+        }
+
+        Some(best_move)
+    }
+}
+
+#[test]
+fn test_strength_from_elo() {
+    assert_eq!(StrengthLimit::from_elo(0).skill_level, 0);
+    assert_eq!(StrengthLimit::from_elo(MIN_ELO).skill_level, 0);
+    assert_eq!(StrengthLimit::from_elo(1400).skill_level, 10);
+    assert_eq!(StrengthLimit::from_elo(1459).elo(), 1400);
+    assert_eq!(StrengthLimit::from_elo(MAX_ELO).skill_level, MAX_SKILL_LEVEL);
+    assert_eq!(StrengthLimit::from_elo(5000).skill_level, MAX_SKILL_LEVEL);
+
+    for level in 1..=MAX_SKILL_LEVEL {
+        let weaker = StrengthLimit::from_skill_level(level - 1);
+        let stronger = StrengthLimit::from_skill_level(level);
+        assert!(weaker.max_depth <= stronger.max_depth);
+        assert!(weaker.max_nodes < stronger.max_nodes);
+        assert!(weaker.score_margin > stronger.score_margin);
+        assert!(weaker.elo() < stronger.elo());
+    }
+}
+
+#[test]
+fn test_strength_choose_move() {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use std::collections::HashSet;
+
+    let m = |s: &str| SimpleMove::from_str(s).unwrap();
+    let scores = [
+        (m("e2e4"), Score::Centipawns(Player::Black, -50)),
+        (m("d2d4"), Score::Centipawns(Player::White, 10)),
+        (m("g1f3"), Score::Centipawns(Player::White, -300)),
+    ];
+
+    let mut rng = ChaCha8Rng::seed_from_u64(1234);
+
+    let strongest = StrengthLimit::from_skill_level(MAX_SKILL_LEVEL);
+    for _ in 0..20 {
+        let chosen = strongest.choose_move(Player::White, &scores, &mut rng);
+        assert_eq!(chosen, Some(m("e2e4")));
+    }
+
+    let casual = StrengthLimit::from_skill_level(10);
+    let chosen: HashSet<SimpleMove> = (0..100)
+        .map(|_| casual.choose_move(Player::White, &scores, &mut rng).unwrap())
+        .collect();
+    assert_eq!(chosen, HashSet::from([m("e2e4"), m("d2d4")]));
+
+    let mate = [
+        (m("d1d8"), Score::WinInN(Player::White, 1)),
+        (m("f2f3"), Score::Centipawns(Player::White, 500)),
+    ];
+    let weakest = StrengthLimit::from_skill_level(0);
+    for _ in 0..20 {
+        let chosen = weakest.choose_move(Player::White, &mate, &mut rng);
+        assert_eq!(chosen, Some(m("d1d8")));
+    }
+}
@@ -0,0 +1,303 @@
+/*
+Tactical test suites such as WAC, ECM and STS.
+
+Each EPD record names the moves to find with `bm` and/or the moves to stay away from with `am`. A
+position is searched with `IterativeSearch` to a fixed depth or for a fixed time, and counts as
+solved if the final best move is one of the `bm` moves and none of the `am` moves. The time to
+solution is when the best move last became a solving move, so a search that finds the answer,
+drops it and finds it again only gets credit for the second time.
+
+STS records also award points to several moves in their c0 comment, e.g. c0 "f5=10, Be5+=2";
+positions without one score the full 10 points when solved.
+*/
+
+use std::{
+    cell::RefCell,
+    fmt::Display,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    epd::{parse_epd_lines, Epd},
+    game::Game,
+    helpers::{err_result, ErrorResult, OptionResult},
+    iterative_deepening::{IterativeSearch, IterativeSearchOptions},
+    moves::Move,
+    san::{move_from_san, move_to_san},
+    simple_move::SimpleMove,
+    transposition_table::TranspositionTable,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuiteLimit {
+    Depth(usize),
+    Time(Duration),
+}
+
+impl Display for SuiteLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SuiteLimit::Depth(depth) => write!(f, "depth {}", depth),
+            SuiteLimit::Time(time) => write!(f, "{}ms", time.as_millis()),
+        }
+    }
+}
+
+pub const MAX_POINTS_PER_POSITION: usize = 10;
+
+#[derive(Debug, Clone)]
+pub struct TacticalResult {
+    pub id: String,
+    pub fen: String,
+    // The bm and am operations as written, e.g. "bm Qxf7#"
+    pub expected: String,
+    // The best move in SAN, `None` if the search found no move at all
+    pub found: Option<String>,
+    pub solved: bool,
+    pub points: usize,
+    pub time_to_solution: Option<Duration>,
+    pub depth_to_solution: Option<usize>,
+    pub elapsed: Duration,
+    pub nodes: usize,
+}
+
+impl Display for TacticalResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let found = self.found.as_deref().unwrap_or("(none)");
+        match (self.solved, self.time_to_solution, self.depth_to_solution) {
+            (true, Some(time), Some(depth)) => write!(
+                f,
+                "ok   {}: {} in {}ms at depth {} ({} points)",
+                self.id,
+                found,
+                time.as_millis(),
+                depth,
+                self.points
+            ),
+            _ => write!(
+                f,
+                "FAIL {}: found {}, expected {} ({} points)",
+                self.id, found, self.expected, self.points
+            ),
+        }
+    }
+}
+
+// The moves and points from an STS c0 comment, `None` if there isn't one
+fn sts_points(epd: &Epd, game: &Game) -> ErrorResult<Option<Vec<(SimpleMove, usize)>>> {
+    let Some(comment) = epd.comment(0) else {
+        return Ok(None);
+    };
+    if !comment.contains('=') {
+        return Ok(None);
+    }
+
+    let mut points = vec![];
+    for entry in comment.split(',') {
+        let (san, value) = entry.split_once('=').as_result()?;
+        let m = move_from_san(game, san.trim())?;
+        match value.trim().parse::<usize>() {
+            Ok(value) => points.push((SimpleMove::from(&m), value.min(MAX_POINTS_PER_POSITION))),
+            Err(_) => return err_result(&format!("invalid points in c0 \"{}\"", comment)),
+        }
+    }
+    Ok(Some(points))
+}
+
+struct Solutions {
+    best: Vec<SimpleMove>,
+    avoid: Vec<SimpleMove>,
+}
+
+impl Solutions {
+    fn solves(&self, m: SimpleMove) -> bool {
+        (self.best.is_empty() || self.best.contains(&m)) && !self.avoid.contains(&m)
+    }
+}
+
+fn simple_moves(moves: &[Move]) -> Vec<SimpleMove> {
+    moves.iter().map(SimpleMove::from).collect()
+}
+
+fn expected_operations(epd: &Epd) -> String {
+    ["bm", "am"]
+        .iter()
+        .filter_map(|opcode| {
+            let operands = epd.operands(opcode)?;
+            Some(format!("{} {}", opcode, operands.join(" ")))
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn run_position(epd: &Epd, index: usize, limit: SuiteLimit) -> ErrorResult<TacticalResult> {
+    let game = epd.game()?;
+    let solutions = Solutions {
+        best: simple_moves(&epd.best_moves()?),
+        avoid: simple_moves(&epd.avoid_moves()?),
+    };
+    if solutions.best.is_empty() && solutions.avoid.is_empty() {
+        return err_result(&format!("no bm or am in {}", epd));
+    }
+    let points = sts_points(epd, &game)?;
+
+    let mut search = IterativeSearch::new(
+        game,
+        IterativeSearchOptions {
+            max_depth: match limit {
+                SuiteLimit::Depth(depth) => Some(depth),
+                SuiteLimit::Time(_) => None,
+            },
+            transposition_table: Some(Rc::new(RefCell::new(TranspositionTable::small()))),
+            ..IterativeSearchOptions::default()
+        },
+    )?;
+
+    let start = Instant::now();
+    let mut bestmove = None;
+    let mut solved_at = None;
+    let mut depth = search.max_depth();
+    while !search.done() {
+        if let SuiteLimit::Time(time) = limit {
+            // Always finish the first depth so that there's a move to judge
+            if bestmove.is_some() && start.elapsed() >= time {
+                break;
+            }
+        }
+        search.iterate(&mut |_| {})?;
+
+        // A new best move is only known once a depth finishes, and the search is already set up
+        // for the next one by then
+        if search.max_depth() == depth {
+            continue;
+        }
+        depth = search.max_depth();
+        bestmove = search.bestmove().map(|(m, _)| m);
+        match bestmove {
+            Some(m) if solutions.solves(m) => {
+                solved_at = solved_at.or(Some((start.elapsed(), depth - 1)));
+            }
+            _ => solved_at = None,
+        }
+    }
+    let elapsed = start.elapsed();
+
+    let solved = bestmove.is_some_and(|m| solutions.solves(m));
+    let found = match bestmove {
+        Some(bestmove) => {
+            let m = game.legal_move(bestmove)?;
+            Some(move_to_san(&game, &m)?)
+        }
+        None => None,
+    };
+    let points = match (points, bestmove) {
+        (Some(points), Some(bestmove)) => points
+            .iter()
+            .find(|(m, _)| *m == bestmove)
+            .map_or(0, |(_, points)| *points),
+        _ if solved => MAX_POINTS_PER_POSITION,
+        _ => 0,
+    };
+
+    Ok(TacticalResult {
+        id: epd.id().map_or_else(|| format!("#{}", index + 1), |id| id.to_string()),
+        fen: game.to_fen(),
+        expected: expected_operations(epd),
+        found,
+        solved,
+        points,
+        time_to_solution: solved_at.map(|(time, _)| time),
+        depth_to_solution: solved_at.map(|(_, depth)| depth),
+        elapsed,
+        nodes: search.num_nodes(),
+    })
+}
+
+// Searches every position in the suite, logging each result
+pub fn run_tactical_suite(
+    text: &str,
+    limit: SuiteLimit,
+    log: &mut dyn FnMut(&str),
+) -> ErrorResult<Vec<TacticalResult>> {
+    let mut results = vec![];
+    for (i, epd) in parse_epd_lines(text)?.iter().enumerate() {
+        let result = run_position(epd, i, limit)?;
+        log(&result.to_string());
+        results.push(result);
+    }
+    Ok(results)
+}
+
+pub fn run_tactical_suite_file(
+    path: &str,
+    limit: SuiteLimit,
+    log: &mut dyn FnMut(&str),
+) -> ErrorResult<Vec<TacticalResult>> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => run_tactical_suite(&text, limit, log),
+        Err(e) => err_result(&format!("couldn't read suite {}: {}", path, e)),
+    }
+}
+
+pub fn tactical_suite_summary(results: &[TacticalResult]) -> String {
+    let (solved, failed): (Vec<_>, Vec<_>) = results.iter().partition(|result| result.solved);
+    let points: usize = results.iter().map(|result| result.points).sum();
+    let max_points = results.len() * MAX_POINTS_PER_POSITION;
+    let time_to_solution: Duration = solved.iter().filter_map(|r| r.time_to_solution).sum();
+    let ids = |results: &[&TacticalResult]| {
+        results.iter().map(|result| result.id.as_str()).collect::<Vec<_>>().join(" ")
+    };
+
+    format!(
+        "solved {}/{}, score {}/{} ({:.1}%), average time to solution {}ms\n\
+         solved: [{}]\nfailed: [{}]",
+        solved.len(),
+        results.len(),
+        points,
+        max_points,
+        100.0 * points as f64 / max_points.max(1) as f64,
+        time_to_solution.as_millis() / solved.len().max(1) as u128,
+        ids(&solved),
+        ids(&failed),
+    )
+}
+
+#[test]
+fn test_tactical_suite() {
+    let suite = r#"
+# A knight fork wins the rook, with STS style points for the other check
+r3k3/8/8/3N4/8/8/8/4K3 w - - bm Nc7+; id "fork"; c0 "Nc7+=10, Nf6+=3";
+# Taking the pawn loses the queen
+4k3/8/2p5/3p4/8/8/8/3QK3 w - - am Qxd5; id "poisoned";
+# Nothing forces Na3 here
+rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - bm Na3; id "opening";
+"#;
+    let mut lines = vec![];
+    let results = run_tactical_suite(suite, SuiteLimit::Depth(3), &mut |line| {
+        lines.push(line.to_string())
+    })
+    .unwrap();
+    assert_eq!(lines.len(), 3);
+
+    let ids: Vec<_> = results.iter().map(|result| (result.id.as_str(), result.solved)).collect();
+    assert_eq!(ids, [("fork", true), ("poisoned", true), ("opening", false)]);
+    assert_eq!(results[0].found.as_deref(), Some("Nc7+"));
+    assert_eq!(results[0].points, 10);
+    assert!(results[0].depth_to_solution.is_some_and(|depth| depth <= 3));
+    assert_eq!(results[1].expected, "am Qxd5");
+    assert_eq!(results[2].points, 0);
+    assert!(lines[2].starts_with("FAIL opening: found "));
+
+    let summary = tactical_suite_summary(&results);
+    println!("{}", summary);
+    assert!(summary.starts_with("solved 2/3, score 20/30 (66.7%)"));
+    assert!(summary.contains("failed: [opening]"));
+
+    // A time limit still finishes the first depth
+    let results = run_tactical_suite(suite, SuiteLimit::Time(Duration::ZERO), &mut |_| {}).unwrap();
+    assert!(results[0].found.is_some());
+
+    let no_solution = "8/8/8/8/8/8/8/K6k w - - id \"none\";";
+    assert!(run_tactical_suite(no_solution, SuiteLimit::Depth(1), &mut |_| {}).is_err());
+}
@@ -62,12 +62,23 @@ impl fmt::Debug for TranspositionTable {
 // 26 => 4gb
 const DEFAULT_BITS: u32 = 25;
 
+// 20 => 64mb, for tools that start a new search for every game or position
+const SMALL_BITS: u32 = 20;
+
 impl TranspositionTable {
     pub fn new() -> Self {
+        Self::with_bits(DEFAULT_BITS)
+    }
+
+    pub fn small() -> Self {
+        Self::with_bits(SMALL_BITS)
+    }
+
+    pub fn with_bits(bits: u32) -> Self {
         let result = Self {
-            table: vec![None; (2 as usize).pow(DEFAULT_BITS)],
-            bits: DEFAULT_BITS as usize,
-            mask: (2 as u64).pow(DEFAULT_BITS) - 1,
+            table: vec![None; 2usize.pow(bits)],
+            bits: bits as usize,
+            mask: 2u64.pow(bits) - 1,
             stats: RefCell::new(TranspositionStats::default()),
         };
         result.stats.borrow_mut().size_in_bytes = size_of::<CacheEntry>() * result.table.len();
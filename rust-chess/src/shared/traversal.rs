@@ -6,6 +6,8 @@ use crate::helpers::indent;
 use crate::helpers::Error;
 use crate::helpers::StableOption;
 use crate::moves::LazyMoves;
+use crate::score::Contempt;
+use crate::score::Score;
 use crate::zobrist::IsDraw;
 use crate::zobrist::ZobristHistory;
 
@@ -47,6 +49,7 @@ impl<D: Debug + TraversalData> TraversalStackFrame<D> {
         &mut self,
         previous: &mut TraversalStackFrame<D>,
         move_to_apply: &Move,
+        known_legal: bool,
     ) -> ErrorResult<Legal> {
         self.game = previous.game;
         self.game.make_move(*move_to_apply)?;
@@ -56,7 +59,8 @@ impl<D: Debug + TraversalData> TraversalStackFrame<D> {
 
         self.data.setup(&previous.data);
 
-        if self.game.move_legality(move_to_apply, &previous.danger()?) == Legal::No {
+        // Moves from `LazyMoves` are generated legal, anything else still needs checking
+        if !known_legal && self.game.move_legality(move_to_apply, &previous.danger()?) == Legal::No {
             return Ok(Legal::No);
         }
 
@@ -109,8 +113,12 @@ impl<D: TraversalData> TraversalStack<D> {
         self.depth
     }
 
-    pub fn is_draw_by_repetition(&self) -> bool {
-        self.zobrist_history.is_draw() == IsDraw::Yes
+    pub fn draw_by_repetition(&self, contempt: Contempt) -> Option<Score> {
+        if self.zobrist_history.is_draw() == IsDraw::Yes {
+            Some(Score::DrawInN(contempt, 0))
+        } else {
+            None
+        }
     }
 
     pub fn increment_depth(&mut self) -> ErrorResult<()> {
@@ -1,22 +1,115 @@
 use itertools::Itertools;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
 use std::{cell::RefCell, iter, rc::Rc, sync::Mutex};
 
 use crate::{
-    alphabeta::{AlphaBetaStack, LoopResult},
-    bitboard::warm_magic_cache,
+    alphabeta::{AlphaBetaStack, LoopResult, RootMoves},
+    bench::{run_bench, DEFAULT_BENCH_DEPTH},
+    bitboard::{warm_magic_cache, BoardIndex},
     fen::FenDefinition,
     helpers::Joinable,
     iterative_deepening::{IterativeSearch, IterativeSearchOptions},
+    mate_search::{MateResult, MateSearch, MateSearchOptions, PROOF_NUMBER_SEARCH_MIN_MOVES},
+    score::DEFAULT_CONTEMPT,
+    simple_move::SimpleMove,
+    strength::{StrengthLimit, MAX_ELO, MAX_SKILL_LEVEL, MIN_ELO},
     transposition_table::TranspositionTable,
     zobrist::{IsDraw, ZobristHistory},
 };
 
 use super::{
-    game::Game,
+    game::{Game, GameStatus},
     helpers::{err_result, ErrorResult},
-    perft::run_perft_counting_first_move,
+    perft::{perft_stats_table, run_perft_counting_first_move, run_perft_stats},
 };
 
+// Parameters that can follow `go`, used to find where the `searchmoves` list ends
+const GO_PARAMETERS: [&str; 12] = [
+    "searchmoves",
+    "ponder",
+    "wtime",
+    "btime",
+    "winc",
+    "binc",
+    "movestogo",
+    "depth",
+    "nodes",
+    "mate",
+    "movetime",
+    "infinite",
+];
+
+// Mate search iterations can expand a whole node, so they get a smaller slice per `think()`
+const MATE_SEARCH_ITERATIONS_PER_THINK: usize = 1_000;
+
+#[derive(Debug, Clone)]
+pub struct UciOptions {
+    pub limit_strength: bool,
+    pub elo: usize,
+    pub skill_level: usize,
+    pub contempt: isize,
+    pub chess960: bool,
+}
+
+// Contempt beyond a few pawns would have the engine throw away material to avoid draws
+const MAX_CONTEMPT: isize = 500;
+
+impl Default for UciOptions {
+    fn default() -> Self {
+        Self {
+            limit_strength: false,
+            elo: MAX_ELO,
+            skill_level: MAX_SKILL_LEVEL,
+            contempt: DEFAULT_CONTEMPT,
+            chess960: false,
+        }
+    }
+}
+
+impl UciOptions {
+    // UCI_LimitStrength takes priority over the skill level
+    pub fn strength_limit(&self) -> Option<StrengthLimit> {
+        if self.limit_strength {
+            Some(StrengthLimit::from_elo(self.elo))
+        } else if self.skill_level < MAX_SKILL_LEVEL {
+            Some(StrengthLimit::from_skill_level(self.skill_level))
+        } else {
+            None
+        }
+    }
+
+    fn set(&mut self, name: &str, value: &str) -> ErrorResult<()> {
+        let parse_number = || match value.parse::<usize>() {
+            Ok(value) => Ok(value),
+            Err(_) => err_result(&format!("invalid value '{}' for option '{}'", value, name)),
+        };
+
+        let parse_bool = || match value {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => err_result(&format!("invalid value '{}' for '{}'", value, name)),
+        };
+
+        match name.to_lowercase().as_str() {
+            "uci_limitstrength" => self.limit_strength = parse_bool()?,
+            "uci_chess960" => self.chess960 = parse_bool()?,
+            "uci_elo" => self.elo = parse_number()?.clamp(MIN_ELO, MAX_ELO),
+            "skill level" => self.skill_level = parse_number()?.min(MAX_SKILL_LEVEL),
+            "contempt" => {
+                self.contempt = match value.parse::<isize>() {
+                    Ok(value) => value.clamp(-MAX_CONTEMPT, MAX_CONTEMPT),
+                    Err(_) => {
+                        return err_result(&format!("invalid value '{}' for '{}'", value, name))
+                    }
+                }
+            }
+            _ => return err_result(&format!("unknown option '{}'", name)),
+        }
+        Ok(())
+    }
+}
+
 pub struct UciAsync {
     game: Mutex<Game>,
 }
@@ -24,8 +117,11 @@ pub struct UciAsync {
 pub struct Uci {
     pub game: Game,
     pub search: Option<IterativeSearch>,
+    pub mate_search: Option<MateSearch>,
     pub tt: Rc<RefCell<TranspositionTable>>,
     pub history: ZobristHistory,
+    pub options: UciOptions,
+    pub rng: ChaCha8Rng,
     pub logger: fn (s: &str),
 }
 
@@ -34,13 +130,49 @@ impl Uci {
         Self {
             game: Game::from_position_uci(&"position startpos").unwrap(),
             search: None,
+            mate_search: None,
             tt: Rc::new(RefCell::new(TranspositionTable::new())),
             history: ZobristHistory::new(),
+            options: UciOptions::default(),
+            rng: ChaCha8Rng::from_entropy(),
             logger: logger,
         }
     }
     pub fn handle_line(&mut self, line: &str) -> ErrorResult<String> {
-        if line.starts_with("position") {
+        if line == "uci" {
+            Ok([
+                "id name rust-chess".to_string(),
+                "option name UCI_LimitStrength type check default false".to_string(),
+                format!(
+                    "option name UCI_Elo type spin default {} min {} max {}",
+                    MAX_ELO, MIN_ELO, MAX_ELO
+                ),
+                format!(
+                    "option name Skill Level type spin default {} min 0 max {}",
+                    MAX_SKILL_LEVEL, MAX_SKILL_LEVEL
+                ),
+                format!(
+                    "option name Contempt type spin default {} min {} max {}",
+                    DEFAULT_CONTEMPT, -MAX_CONTEMPT, MAX_CONTEMPT
+                ),
+                "option name UCI_Chess960 type check default false".to_string(),
+                "uciok".to_string(),
+            ]
+            .join("\n"))
+        } else if line == "isready" {
+            Ok("readyok".to_string())
+        } else if let Some(option) = line.strip_prefix("setoption") {
+            let option = match option.trim().strip_prefix("name ") {
+                Some(option) => option,
+                None => return err_result(&format!("missing option name in '{}'", line)),
+            };
+            let (name, value) = match option.split_once(" value ") {
+                Some((name, value)) => (name.trim(), value.trim()),
+                None => (option.trim(), ""),
+            };
+            self.options.set(name, value)?;
+            Ok("".to_string())
+        } else if line.starts_with("position") {
             let (position_str, moves) = FenDefinition::split_uci(line)?;
             (self.logger)(&format!("{}, {}", position_str, moves.join(" ")));
 
@@ -52,6 +184,15 @@ impl Uci {
             } else {
                 Ok(format!("{:?}", self.game))
             }
+        } else if let Some(depth) = line.strip_prefix("go perft stats") {
+            let depth = match depth.trim().parse::<usize>() {
+                Ok(depth) => depth,
+                Err(_) => {
+                    return err_result(&format!("invalid depth for '{}'", line));
+                }
+            };
+            let stats = run_perft_stats(&self.game, depth)?;
+            Ok(perft_stats_table(&stats))
         } else if line.starts_with("go perft") {
             let depth = line["go perft".len()..].trim();
             let depth = match depth.parse::<usize>() {
@@ -74,18 +215,66 @@ impl Uci {
                 perft_overall,
             );
             Ok(perft_output)
+        } else if let Some(depth) = line.strip_prefix("bench") {
+            let depth = match depth.trim() {
+                "" => DEFAULT_BENCH_DEPTH,
+                depth => match depth.parse::<usize>() {
+                    Ok(depth) => depth,
+                    Err(_) => return err_result(&format!("invalid depth for '{}'", line)),
+                },
+            };
+            let logger = self.logger;
+            Ok(run_bench(depth, &mut |line| logger(line))?.to_string())
         } else if line == "d" {
             let debug_str = format!("{}\nFen: {}", self.game, self.game.to_fen());
             Ok(debug_str)
-        } else if line == "go" {
+        } else if let Some(moves) = line.strip_prefix("go mate") {
+            let moves = match moves.trim().parse::<usize>() {
+                Ok(moves) if moves > 0 => moves,
+                _ => {
+                    return err_result(&format!("invalid number of moves for '{}'", line));
+                }
+            };
+            let mate_search = MateSearch::new(
+                self.game,
+                moves,
+                MateSearchOptions {
+                    use_proof_number_search: moves >= PROOF_NUMBER_SEARCH_MIN_MOVES,
+                    starting_history: self.history.clone(),
+                    chess960: self.options.chess960,
+                    ..MateSearchOptions::default()
+                },
+            )?;
+            self.search = None;
+            self.mate_search = Some(mate_search);
+            Ok("".to_string())
+        } else if line == "go" || line.starts_with("go ") {
+            // Parameters can come in any order, e.g. `go wtime 1000 searchmoves e2e4 depth 5`
+            let root_moves = self.parse_searchmoves(line)?;
+            let depth = self.parse_go_number(line, "depth")?;
+            let nodes = self.parse_go_number(line, "nodes")?;
+            let strength_limit = self.options.strength_limit();
+            if let Some(strength_limit) = strength_limit {
+                (self.logger)(&format!("limiting strength to {:?}", strength_limit));
+            }
+            let max_depth = [depth, strength_limit.map(|limit| limit.max_depth)];
+            let max_nodes = [nodes, strength_limit.map(|limit| limit.max_nodes)];
+
             let search = IterativeSearch::new(
                 self.game,
                 IterativeSearchOptions {
                     transposition_table: Some(self.tt.clone()),
                     starting_history: self.history.clone(),
+                    root_moves,
+                    max_depth: max_depth.into_iter().flatten().min(),
+                    max_nodes: max_nodes.into_iter().flatten().min(),
+                    score_root_moves: strength_limit.is_some_and(|limit| limit.score_margin > 0),
+                    contempt: self.options.contempt,
+                    chess960: self.options.chess960,
                     ..IterativeSearchOptions::default()
                 },
             )?;
+            self.mate_search = None;
             self.search = Some(search);
             Ok("".to_string())
         } else if line == "stop" {
@@ -95,17 +284,107 @@ impl Uci {
         }
     }
 
+    // Status of the position from the last `position` command
+    pub fn game_status(&self) -> ErrorResult<GameStatus> {
+        self.game.status(&self.history)
+    }
+
+    // Legal moves in the current position as UCI strings, optionally only those starting on
+    // `start`
+    pub fn legal_moves(&self, start: Option<BoardIndex>) -> ErrorResult<Vec<String>> {
+        let moves = match start {
+            Some(start) => self.game.legal_moves_from(start)?,
+            None => self.game.legal_moves()?,
+        };
+        Ok(moves
+            .iter()
+            .map(|m| m.to_uci_with(self.options.chess960))
+            .collect())
+    }
+
+    // The number after `name` in a `go` command, if it's there
+    fn parse_go_number(&self, line: &str, name: &str) -> ErrorResult<Option<usize>> {
+        let mut tokens = line.split_whitespace().skip_while(|&t| t != name);
+        if tokens.next().is_none() {
+            return Ok(None);
+        }
+        match tokens.next().map(|token| token.parse::<usize>()) {
+            Some(Ok(value)) => Ok(Some(value)),
+            _ => err_result(&format!("invalid {} for '{}'", name, line)),
+        }
+    }
+
+    fn parse_searchmoves(&self, line: &str) -> ErrorResult<RootMoves> {
+        let mut tokens = line.split_whitespace().skip_while(|&t| t != "searchmoves");
+        if tokens.next().is_none() {
+            return Ok(RootMoves::default());
+        }
+
+        let mut include = vec![];
+        for token in tokens.take_while(|t| !GO_PARAMETERS.contains(t)) {
+            match self.game.move_from_str(token) {
+                Some(m) => include.push(SimpleMove::from(&m)),
+                None => return err_result(&format!("illegal move in searchmoves: '{}'", token)),
+            }
+        }
+        if include.is_empty() {
+            return err_result(&format!("no moves given for '{}'", line));
+        }
+
+        Ok(RootMoves {
+            include: Some(include),
+            exclude: vec![],
+        })
+    }
+
+    fn finish_mate_search(&mut self) -> ErrorResult<String> {
+        let mate_search = self.mate_search.take();
+        let result = mate_search.as_ref().and_then(|search| search.result());
+
+        match result {
+            Some(result @ MateResult::Mate(variation)) => {
+                let ucis = self.game.line_to_uci(variation, self.options.chess960)?;
+                Ok(format!(
+                    "info score mate {} pv {}\nbestmove {} ponder {}",
+                    result.mate_in().unwrap(),
+                    ucis.join(" "),
+                    ucis[0],
+                    ucis[1..].join(" ")
+                ))
+            }
+            Some(MateResult::NoMate) => {
+                Ok("info string no forced mate found\nbestmove (none)".to_string())
+            }
+            Some(MateResult::Unknown) | None => Ok("bestmove (none)".to_string()),
+        }
+    }
+
     fn finish_search(&mut self) -> ErrorResult<String> {
+        if self.mate_search.is_some() {
+            return self.finish_mate_search();
+        }
         if let Some(search) = &mut self.search {
             let best_move = search.bestmove();
+            let weakened_move = self.options.strength_limit().and_then(|limit| {
+                limit.choose_move(self.game.player(), search.root_move_scores(), &mut self.rng)
+            });
             self.search = None;
 
+            let chess960 = self.options.chess960;
+            if let (Some(weakened_move), Some((best_move, _))) = (weakened_move, &best_move) {
+                if weakened_move != *best_move {
+                    let uci = self.game.line_to_uci(&[weakened_move], chess960)?;
+                    return Ok(format!("bestmove {}", uci[0]));
+                }
+            }
+
             match best_move {
-                Some((best_move, response_moves)) => Ok(format!(
-                    "bestmove {} ponder {}",
-                    best_move,
-                    response_moves.iter().map(|v| v.to_string()).join(" ")
-                )),
+                Some((best_move, response_moves)) => {
+                    let mut variation = vec![best_move];
+                    variation.extend(response_moves);
+                    let ucis = self.game.line_to_uci(&variation, chess960)?;
+                    Ok(format!("bestmove {} ponder {}", ucis[0], ucis[1..].join(" ")))
+                }
                 None => Ok("bestmove (none)".to_string()),
             }
         } else {
@@ -115,6 +394,23 @@ impl Uci {
 
     pub fn think(&mut self) -> ErrorResult<String> {
         let mut output: Vec<String> = vec![];
+
+        // Unlike `go`, `go mate` ends on its own once the search is decided
+        if let Some(mate_search) = &mut self.mate_search {
+            for _ in 0..MATE_SEARCH_ITERATIONS_PER_THINK {
+                let result = mate_search.iterate(&mut |line| {
+                    if !line.is_empty() {
+                        output.push(line.to_string())
+                    }
+                })?;
+                if result == LoopResult::Done {
+                    output.push(self.finish_mate_search()?);
+                    break;
+                }
+            }
+            return Ok(output.join("\n"));
+        }
+
         for _ in 0..100_000 {
             if let Some(search) = &mut self.search {
                 search.iterate(&mut |line| {
@@ -320,3 +616,169 @@ fn test_match_avoid_draw() {
         moves.push(bestmove.to_string());
     }
 }
+
+#[test]
+fn test_go_mate() {
+    let mut uci = Uci::new(debug_logger);
+    uci.handle_line("position fen 3r4/pR2N3/2pkb3/5p2/8/2B5/qP3PPP/4R1K1 w - - 1 0")
+        .unwrap();
+    uci.handle_line("go mate 3").unwrap();
+
+    let mut output = String::new();
+    while !output.contains("bestmove") {
+        output = uci.think().unwrap();
+    }
+    println!("{}", output);
+    assert!(output.contains("info score mate 3 pv c3e5"));
+    assert!(output.contains("bestmove c3e5"));
+
+    uci.handle_line("position fen 4k3/8/8/8/8/8/8/R3K3 w - - 0 1")
+        .unwrap();
+    uci.handle_line("go mate 2").unwrap();
+
+    let mut output = String::new();
+    while !output.contains("bestmove") {
+        output = uci.think().unwrap();
+    }
+    assert!(output.contains("bestmove (none)"));
+}
+
+#[test]
+fn test_go_perft_stats() {
+    let mut uci = Uci::new(debug_logger);
+    uci.handle_line("position startpos moves e2e4 d7d5").unwrap();
+
+    let output = uci.handle_line("go perft stats 2").unwrap();
+    println!("{}", output);
+    let lines: Vec<&str> = output.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].contains("captures") && lines[0].contains("checkmates"));
+    let row: Vec<&str> = lines[1].split_whitespace().collect();
+    assert_eq!(row, ["1", "31", "1", "0", "0", "0", "1", "0", "0", "0"]);
+
+    assert!(uci.handle_line("go perft stats deep").is_err());
+}
+
+#[test]
+fn test_bench() {
+    let mut uci = Uci::new(debug_logger);
+    let first = uci.handle_line("bench 2").unwrap();
+    let second = uci.handle_line("bench 2").unwrap();
+    assert!(first.contains("Nodes searched"));
+    assert_eq!(first.lines().nth(1), second.lines().nth(1));
+
+    assert!(uci.handle_line("bench deep").is_err());
+}
+
+#[test]
+fn test_go_searchmoves() {
+    let mut uci = Uci::new(debug_logger);
+    uci.handle_line("position fen 6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1")
+        .unwrap();
+    uci.handle_line("go searchmoves g2g3 h2h3").unwrap();
+    for _ in 0..5 {
+        uci.think().unwrap();
+    }
+
+    let result = uci.finish_search().unwrap();
+    println!("{}", result);
+    let bestmove = result.split_whitespace().nth(1).unwrap();
+    assert!(bestmove == "g2g3" || bestmove == "h2h3");
+
+    assert!(uci.handle_line("go searchmoves d1d9").is_err());
+    assert!(uci.handle_line("go searchmoves").is_err());
+
+    // searchmoves after other parameters, with a depth limit that ends the search on its own
+    uci.handle_line("go wtime 1000 btime 1000 depth 2 searchmoves h2h3 nodes 100000")
+        .unwrap();
+    while !uci.search.as_ref().unwrap().done() {
+        uci.think().unwrap();
+    }
+    assert_eq!(uci.search.as_ref().unwrap().max_depth(), 3);
+    assert!(uci.finish_search().unwrap().starts_with("bestmove h2h3"));
+
+    assert!(uci.handle_line("go depth deep").is_err());
+}
+
+#[test]
+fn test_setoption_strength() {
+    let mut uci = Uci::new(debug_logger);
+    assert!(uci.handle_line("uci").unwrap().contains("option name UCI_Elo"));
+    assert_eq!(uci.options.strength_limit(), None);
+
+    uci.handle_line("setoption name Skill Level value 3").unwrap();
+    assert_eq!(
+        uci.options.strength_limit(),
+        Some(StrengthLimit::from_skill_level(3))
+    );
+
+    uci.handle_line("setoption name UCI_LimitStrength value true")
+        .unwrap();
+    uci.handle_line("setoption name UCI_Elo value 1500").unwrap();
+    assert_eq!(uci.options.strength_limit().unwrap().elo(), 1460);
+
+    assert!(uci.handle_line("setoption name UCI_Elo value lots").is_err());
+    assert!(uci.handle_line("setoption name Hash value 16").is_err());
+}
+
+#[test]
+fn test_setoption_contempt() {
+    let mut uci = Uci::new(debug_logger);
+    assert!(uci.handle_line("uci").unwrap().contains("option name Contempt"));
+    assert_eq!(uci.options.contempt, DEFAULT_CONTEMPT);
+
+    uci.handle_line("setoption name Contempt value -20").unwrap();
+    assert_eq!(uci.options.contempt, -20);
+
+    uci.handle_line("setoption name Contempt value 10000").unwrap();
+    assert_eq!(uci.options.contempt, MAX_CONTEMPT);
+}
+
+#[test]
+fn test_setoption_chess960() {
+    let mut uci = Uci::new(debug_logger);
+    assert!(uci.handle_line("uci").unwrap().contains("option name UCI_Chess960"));
+    uci.handle_line("position fen r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1 moves e1h1")
+        .unwrap();
+    assert_eq!(uci.game.board().to_fen(), "r3k2r/8/8/8/8/8/8/R4RK1 b kq -");
+
+
+    // Black can only castle, and the search output writes it the way the setting asks for
+    let castle = |uci: &mut Uci| {
+        uci.handle_line("go depth 1 searchmoves e8c8").unwrap();
+        let thinking = uci.think().unwrap();
+        (thinking, uci.finish_search().unwrap())
+    };
+    let (thinking, bestmove) = castle(&mut uci);
+    assert!(thinking.contains("bestmove e8c8"), "{}", thinking);
+    assert!(bestmove.starts_with("bestmove e8c8"), "{}", bestmove);
+
+    uci.handle_line("setoption name UCI_Chess960 value true").unwrap();
+    let (thinking, bestmove) = castle(&mut uci);
+    assert!(thinking.contains("bestmove e8a8"), "{}", thinking);
+    assert!(bestmove.starts_with("bestmove e8a8"), "{}", bestmove);
+    assert_eq!(uci.legal_moves(None).unwrap().iter().filter(|m| *m == "e8a8").count(), 1);
+}
+
+#[test]
+fn test_limited_strength_search() {
+    let mut uci = Uci::new(debug_logger);
+    uci.rng = ChaCha8Rng::seed_from_u64(1234);
+    uci.handle_line("setoption name Skill Level value 0").unwrap();
+    uci.handle_line("position startpos").unwrap();
+
+    let mut bestmoves = std::collections::HashSet::new();
+    for _ in 0..10 {
+        uci.handle_line("go").unwrap();
+        while !uci.search.as_ref().unwrap().done() {
+            uci.think().unwrap();
+        }
+        let search = uci.search.as_ref().unwrap();
+        assert!(search.root_move_scores().len() > 1);
+
+        let result = uci.finish_search().unwrap();
+        bestmoves.insert(result.split_whitespace().nth(1).unwrap().to_string());
+    }
+    println!("{:?}", bestmoves);
+    assert!(bestmoves.len() > 1);
+}
@@ -0,0 +1,101 @@
+/*
+An external UCI engine running as a child process.
+
+Commands go to its stdin and replies are read from its stdout a line at a time, until the line that
+ends the reply (`uciok`, `readyok`, `bestmove ...`). The engine is told to quit when dropped.
+*/
+
+use std::{
+    io::{BufRead, BufReader, Write},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+use crate::helpers::{err_result, ErrorResult, OptionResult};
+
+pub struct UciEngine {
+    // From the engine's `id name`, or the path if it doesn't send one
+    pub name: String,
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl UciEngine {
+    pub fn spawn(path: &str) -> ErrorResult<Self> {
+        let mut child = match Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) => return err_result(&format!("couldn't start engine {}: {}", path, e)),
+        };
+        let stdin = child.stdin.take().as_result()?;
+        let stdout = BufReader::new(child.stdout.take().as_result()?);
+
+        let mut engine = Self {
+            name: path.to_string(),
+            child,
+            stdin,
+            stdout,
+        };
+        engine.send("uci")?;
+        for line in engine.read_until(|line| line == "uciok")? {
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.trim().to_string();
+            }
+        }
+        engine.send("isready")?;
+        engine.read_until(|line| line == "readyok")?;
+        Ok(engine)
+    }
+
+    pub fn send(&mut self, line: &str) -> ErrorResult<()> {
+        match writeln!(self.stdin, "{}", line).and_then(|_| self.stdin.flush()) {
+            Ok(()) => Ok(()),
+            Err(e) => err_result(&format!("couldn't send '{}' to engine: {}", line, e)),
+        }
+    }
+
+    // Returns every line read, up to and including the one that matches
+    pub fn read_until(&mut self, done: impl Fn(&str) -> bool) -> ErrorResult<Vec<String>> {
+        let mut lines = vec![];
+        loop {
+            let mut line = String::new();
+            match self.stdout.read_line(&mut line) {
+                Ok(0) => return err_result("engine exited before answering"),
+                Ok(_) => {}
+                Err(e) => return err_result(&format!("couldn't read from engine: {}", e)),
+            }
+            let line = line.trim().to_string();
+            let finished = done(&line);
+            lines.push(line);
+            if finished {
+                return Ok(lines);
+            }
+        }
+    }
+
+    pub fn new_game(&mut self) -> ErrorResult<()> {
+        self.send("ucinewgame")?;
+        self.send("isready")?;
+        self.read_until(|line| line == "readyok")?;
+        Ok(())
+    }
+
+    // Sends the `position` and `go` lines and returns the move from the `bestmove` reply
+    pub fn bestmove(&mut self, position: &str, go: &str) -> ErrorResult<String> {
+        self.send(position)?;
+        self.send(go)?;
+        let lines = self.read_until(|line| line.starts_with("bestmove"))?;
+        let bestmove = lines.last().and_then(|line| line.split_whitespace().nth(1));
+        Ok(bestmove.as_result()?.to_string())
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,98 @@
+use rust_chess::analysis::{AnalysisOptions, GameAnalysis};
+use rust_chess::pgn::PgnGame;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::{log_to_js, set_panic_hook, JsError};
+
+// Search iterations between checks of the clock
+const ITERATIONS_PER_THINK: usize = 1000;
+
+struct AnalysisState {
+    analysis: GameAnalysis,
+    movetime_ms: i64,
+    // When the current position started being searched
+    position_start: chrono::DateTime<chrono::Utc>,
+}
+
+#[wasm_bindgen]
+pub struct AnalysisForJs {
+    data: Option<AnalysisState>,
+}
+
+#[wasm_bindgen]
+impl AnalysisForJs {
+    pub fn new() -> Self {
+        set_panic_hook();
+        Self { data: None }
+    }
+
+    pub fn setup(&mut self, pgn: String, movetime_ms: i64) -> Result<(), JsError> {
+        let game = PgnGame::from_pgn(&pgn).map_err(JsError::from)?;
+        let analysis = GameAnalysis::new(game, AnalysisOptions::default());
+        log_to_js(format!("analysis setup for {} positions", analysis.positions()).as_str());
+        self.data = Some(AnalysisState {
+            analysis,
+            movetime_ms,
+            position_start: chrono::Utc::now(),
+        });
+        Ok(())
+    }
+
+    // Positions analysed so far, -1 before setup
+    pub fn progress(&self) -> i32 {
+        match &self.data {
+            Some(data) => data.analysis.current() as i32,
+            None => -1,
+        }
+    }
+
+    pub fn positions(&self) -> i32 {
+        match &self.data {
+            Some(data) => data.analysis.positions() as i32,
+            None => -1,
+        }
+    }
+
+    // Searches for a short while, moving on to the next position once the current one has had
+    // its time
+    pub fn think_and_return_done(&mut self) -> Result<bool, JsError> {
+        let Some(data) = self.data.as_mut() else {
+            panic!("analysis not setup");
+        };
+        let analysis = &mut data.analysis;
+        if analysis.done() {
+            return Ok(true);
+        }
+
+        analysis.think(ITERATIONS_PER_THINK).map_err(JsError::from)?;
+
+        let elapsed = (chrono::Utc::now() - data.position_start).num_milliseconds();
+        let out_of_time =
+            elapsed >= data.movetime_ms && analysis.has_result().map_err(JsError::from)?;
+        if out_of_time || analysis.position_finished().map_err(JsError::from)? {
+            analysis.finish_position().map_err(JsError::from)?;
+            data.position_start = chrono::Utc::now();
+        }
+        Ok(analysis.done())
+    }
+
+    pub fn annotated_pgn(&self) -> Result<String, JsError> {
+        let Some(data) = self.data.as_ref() else {
+            panic!("analysis not setup");
+        };
+        let game = data.analysis.annotated_game().map_err(JsError::from)?;
+        Ok(game.to_pgn())
+    }
+
+    // One line per player with accuracy, average centipawn loss and the number of marked moves
+    pub fn summary(&self) -> String {
+        match &self.data {
+            Some(data) => data.analysis.summary().to_string(),
+            None => panic!("analysis not setup"),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.data = None;
+    }
+}
@@ -1,3 +1,4 @@
+mod analysis_for_js;
 mod counter_for_js;
 mod perft_for_js;
 
@@ -33,6 +34,17 @@ pub struct UciForJs {
     uci: uci::Uci,
 }
 
+#[wasm_bindgen(getter_with_clone)]
+pub struct GameStatusForJs {
+    // e.g. "checkmate", "stalemate" or "ongoing"
+    pub status: String,
+    pub is_over: bool,
+    // "white" or "black" after a checkmate
+    pub winner: Option<String>,
+    // "1-0", "0-1", "1/2-1/2" or "*"
+    pub result: String,
+}
+
 pub struct JsError {
     msg: String,
 }
@@ -79,6 +91,57 @@ impl UciForJs {
         }
     }
 
+    // Play at the skill level rated at or below `elo`, or at full strength when `elo` is undefined
+    pub fn set_strength(&mut self, elo: Option<u32>) -> Result<String, JsError> {
+        match elo {
+            Some(elo) => {
+                self.handle_line("setoption name UCI_LimitStrength value true")?;
+                self.handle_line(&format!("setoption name UCI_Elo value {}", elo))
+            }
+            None => {
+                self.handle_line("setoption name UCI_LimitStrength value false")?;
+                self.handle_line(&format!(
+                    "setoption name Skill Level value {}",
+                    strength::MAX_SKILL_LEVEL
+                ))
+            }
+        }
+    }
+
+    pub fn set_skill_level(&mut self, skill_level: u32) -> Result<String, JsError> {
+        self.handle_line(&format!("setoption name Skill Level value {}", skill_level))
+    }
+
+    pub fn game_status(&self) -> Result<GameStatusForJs, JsError> {
+        let status = self.uci.game_status().map_err(|e| JsError::from(e))?;
+        Ok(GameStatusForJs {
+            status: status.to_string(),
+            is_over: status.is_over(),
+            winner: status.winner().map(|winner| match winner {
+                types::Player::White => "white".to_string(),
+                types::Player::Black => "black".to_string(),
+            }),
+            result: status.result().to_string(),
+        })
+    }
+
+    // Space separated UCI moves, only those starting on `square` (e.g. "e2") when it's given
+    pub fn legal_moves(&self, square: Option<String>) -> Result<String, JsError> {
+        let start = match square {
+            Some(square) => {
+                Some(bitboard::BoardIndex::from_str(&square).map_err(|e| JsError::from(e))?)
+            }
+            None => None,
+        };
+        let moves = self.uci.legal_moves(start).map_err(|e| JsError::from(e))?;
+        Ok(moves.join(" "))
+    }
+
+    pub fn legal_move_count(&self) -> Result<usize, JsError> {
+        let count = self.uci.game.legal_move_count();
+        count.map_err(|e| JsError::from(e))
+    }
+
     pub fn think(&mut self) -> Result<String, JsError> {
         let start = chrono::Utc::now();
         let result = self.uci.think().map_err(|e| JsError::from(e))?;
@@ -1,8 +1,9 @@
 
-use rust_chess::perft::{PerftLoop, PerftLoopResult};
+use rust_chess::game::Game;
+use rust_chess::perft::{run_perft_with, PerftLoop, PerftLoopResult, MAKE_STRATEGIES};
 use wasm_bindgen::prelude::wasm_bindgen;
 
-use crate::{set_panic_hook, log_to_js};
+use crate::{set_panic_hook, log_to_js, JsError};
 
 #[wasm_bindgen]
 pub struct PerftForJs {
@@ -56,4 +57,24 @@ impl PerftForJs {
     pub fn clear(&mut self) {
         self.data = None;
     }
+
+    // Times perft with each way of making moves, one line per strategy, e.g.
+    // "copy-make: 4085603 nodes in 338ms"
+    pub fn compare_make_strategies(fen: String, depth: usize) -> Result<String, JsError> {
+        let game = Game::from_fen(&fen).map_err(JsError::from)?;
+
+        let mut lines = vec![];
+        for strategy in MAKE_STRATEGIES {
+            let start = chrono::Utc::now();
+            let nodes = run_perft_with(&game, depth, strategy).map_err(JsError::from)?;
+            let elapsed = chrono::Utc::now() - start;
+            lines.push(format!(
+                "{}: {} nodes in {}ms",
+                strategy,
+                nodes,
+                elapsed.num_milliseconds()
+            ));
+        }
+        Ok(lines.join("\n"))
+    }
 }